@@ -1,6 +1,6 @@
 use crate::bytes::{FromBytes, IntoBytes};
 use crate::consts::{ACC_HRP, ADDR_HRP, MAX_ACCOUNT_KEY_SIZE};
-use crate::crypto::{bech32_decode, PriKey, PubKey, SigValue};
+use crate::crypto::{bech32_decode, PriKey, PubKey, SigCache, SigScheme, SigValue};
 use crate::errors;
 use crate::hasher::{Hasher, SIZE as HasherSize};
 use crate::iobuf;
@@ -10,18 +10,99 @@ use bech32::{FromBase32, ToBase32, Variant};
 use hex::{FromHex, ToHex};
 use std::sync::Arc;
 
+//验签函数,返回签名是否正确
+type Verifier<'a> = dyn Fn(&PubKey, &SigValue) -> bool + 'a;
+
+/// 金额选择函数,参数为输出是否已锁定的判断,返回需要锁定的交易输出
+pub type CoinSelector<'a> =
+    dyn FnMut(&dyn Fn(&Hasher, u16) -> bool) -> Result<Vec<(Hasher, u16)>, errors::Error> + 'a;
+
 /// 账户管理器
 pub trait AccountPool: Sync + Send {
     /// 获取指定的账户
     fn account(&self, id: &str) -> Result<Arc<Account>, errors::Error>;
     /// 列出所有账户地址
-    fn list_keys(&self) -> &Vec<String>;
+    fn list_keys(&self) -> Vec<String>;
     /// 获取账户总数
     fn len(&self) -> usize;
     /// 按索引获取账户
     fn value(&self, idx: usize) -> Result<Arc<Account>, errors::Error>;
     /// 按索引获取账户地址
-    fn key(&self, idx: usize) -> Result<&String, errors::Error>;
+    fn key(&self, idx: usize) -> Result<String, errors::Error>;
+    /// 创建新账户,返回账户地址,height为当前区块高度
+    fn create(
+        &self,
+        _num: u8,
+        _less: u8,
+        _arb: bool,
+        _label: &str,
+        _height: u32,
+    ) -> Result<String, errors::Error> {
+        errors::Error::msg("account pool readonly")
+    }
+    /// 是否为只读账户或者只读地址,只读的不能签名
+    fn is_watch_only(&self, id: &str) -> Result<bool, errors::Error> {
+        Ok(self.account(id)?.is_watch_only())
+    }
+    /// 导入encode_to_bech32编码的账户,返回账户地址
+    /// 不包含私钥的账户或者地址作为只读导入
+    fn import(&self, _value: &str, _label: &str, _height: u32) -> Result<String, errors::Error> {
+        errors::Error::msg("account pool readonly")
+    }
+    /// 删除账户
+    fn remove(&self, _id: &str) -> Result<(), errors::Error> {
+        errors::Error::msg("account pool readonly")
+    }
+    /// 修改账户标签
+    fn rename(&self, _id: &str, _label: &str) -> Result<(), errors::Error> {
+        errors::Error::msg("account pool readonly")
+    }
+    /// 锁定交易输出,自动选择金额时跳过,已锁定返回错误
+    /// persist为true时保存,重新打开后仍然锁定
+    fn lock_coin(&self, _tx: &Hasher, _idx: u16, _persist: bool) -> Result<(), errors::Error> {
+        errors::Error::msg("coin lock not support")
+    }
+    /// 解锁交易输出,未锁定返回错误
+    fn unlock_coin(&self, _tx: &Hasher, _idx: u16) -> Result<(), errors::Error> {
+        errors::Error::msg("coin lock not support")
+    }
+    /// 交易输出是否锁定
+    fn is_locked_coin(&self, _tx: &Hasher, _idx: u16) -> bool {
+        false
+    }
+    /// 列出所有锁定的交易输出
+    fn list_locked_coins(&self) -> Vec<(Hasher, u16)> {
+        vec![]
+    }
+    /// 在同一个锁内选择并锁定交易输出,避免并发选择相同的输出
+    /// f使用是否已锁定的判断方法选择输出,返回需要锁定的输出
+    fn select_lock_coins(
+        &self,
+        _persist: bool,
+        _f: &mut CoinSelector,
+    ) -> Result<(), errors::Error> {
+        errors::Error::msg("coin lock not support")
+    }
+    /// 输出已被区块中的交易消费,清除存在的锁定
+    fn release_coins(&self, _keys: &[(Hasher, u16)]) -> Result<(), errors::Error> {
+        Ok(())
+    }
+    /// 设置加密账户使用的密码,密码未设置时不能解锁
+    fn set_password(&self, _password: &str) -> Result<(), errors::Error> {
+        errors::Error::msg("account pool not encrypted")
+    }
+    /// 使用密码解锁加密保存的账户,解锁后才能签名和创建账户
+    fn unlock(&self, _password: &str) -> Result<(), errors::Error> {
+        errors::Error::msg("account pool not encrypted")
+    }
+    /// 从内存中清除私钥
+    fn lock(&self) -> Result<(), errors::Error> {
+        errors::Error::msg("account pool not encrypted")
+    }
+    /// 是否锁定
+    fn is_locked(&self) -> bool {
+        false
+    }
 }
 
 /// 存在地址hasher可获取地址
@@ -76,15 +157,24 @@ impl IntoBytes for Account {
         for iv in self.pubs.iter().filter(|&v| v.is_some()) {
             wb.put(iv.as_ref().unwrap());
         }
-        //sigs
+        //sigs,schnorr账户签名前写入对应的公钥索引
         wb.u8(self.sigs_size());
-        for iv in self.sigs.iter().filter(|&v| v.is_some()) {
+        for (i, iv) in self.sigs.iter().enumerate().filter(|v| v.1.is_some()) {
+            if self.scheme() == SigScheme::Schnorr {
+                wb.u8(i as u8);
+            }
             wb.put(iv.as_ref().unwrap());
         }
         return wb.bytes().to_vec();
     }
 }
 
+//按账户公钥的签名算法读取签名
+fn read_sig(r: &mut Reader, scheme: SigScheme) -> Result<SigValue, errors::Error> {
+    let size = r.usize()?;
+    SigValue::with_scheme(scheme, &r.get_bytes(size)?)
+}
+
 /// 从脚本数据获取,不包含私钥
 impl FromBytes for Account {
     fn from_bytes(bb: &Vec<u8>) -> Result<Account, errors::Error> {
@@ -96,12 +186,20 @@ impl FromBytes for Account {
         for i in 0..r.u8()? as usize {
             acc.pubs[i] = Some(r.get()?);
         }
-        for i in 0..r.u8()? as usize {
-            acc.sigs[i] = Some(r.get()?);
-        }
         if !acc.check_with_pubs() {
             return errors::Error::msg("InvalidAccount");
         }
+        let schnorr = acc.scheme() == SigScheme::Schnorr;
+        let mut next = 0;
+        for i in 0..r.u8()? as usize {
+            //schnorr账户签名索引必须递增
+            let idx = if schnorr { r.u8()? as usize } else { i };
+            if idx < next || idx >= acc.sigs.len() {
+                return errors::Error::msg("InvalidAccount");
+            }
+            acc.sigs[idx] = Some(read_sig(&mut r, acc.scheme())?);
+            next = idx + 1;
+        }
         Ok(acc)
     }
 }
@@ -136,6 +234,21 @@ fn test_account_save_load() {
     assert_eq!(acc, old);
 }
 
+#[test]
+fn test_account_watch_only() {
+    let acc = Account::new(2, 2, false, true).unwrap();
+    assert!(!acc.is_watch_only());
+    //只包含公钥的账户可以编码和解码
+    let watch = acc.public();
+    assert!(watch.is_watch_only());
+    let tmp = Account::decode_from_bech32(&watch.encode_to_bech32().unwrap()).unwrap();
+    assert!(tmp.is_watch_only());
+    assert_eq!(tmp.encode().unwrap(), acc.encode().unwrap());
+    //地址和账户编码不能混用
+    assert!(Account::decode(&acc.encode_to_bech32().unwrap()).is_err());
+    assert!(Account::decode_from_bech32(&acc.encode().unwrap()).is_err());
+}
+
 #[test]
 fn test_encode_decode() {
     let acc = Account::new(2, 2, false, true).unwrap();
@@ -166,6 +279,13 @@ impl HasAddress for Account {
     }
 }
 
+/// 共享的账户,账户池返回的账户
+impl<T: HasAddress> HasAddress for Arc<T> {
+    fn get_address(&self) -> Result<Hasher, errors::Error> {
+        (**self).get_address()
+    }
+}
+
 impl Account {
     /// 编码为16进制字符串
     pub fn encode_to_hex(&self) -> Result<String, errors::Error> {
@@ -260,12 +380,13 @@ impl Account {
         if !acc.check_with_pubs() {
             return errors::Error::msg("check with pubs error");
         }
-        if !acc.check_pris_pubs() {
+        //只读账户没有私钥
+        if !acc.is_watch_only() && !acc.check_pris_pubs() {
             return errors::Error::msg("check with pric and pubs error");
         }
         Ok(acc)
     }
-    ///保存到文件
+    ///保存到文件,私钥为明文,加密存储使用 keystore::Keystore
     pub fn save(&self, path: &str) -> Result<(), errors::Error> {
         let mut wb = Writer::default();
         self.encode_to_writer(&mut wb)?;
@@ -297,6 +418,25 @@ impl Account {
     ///创建一个默认账户
     ///is_gen 是否创建新的私钥
     pub fn new(num: u8, less: u8, arb: bool, is_gen: bool) -> Result<Account, errors::Error> {
+        Account::new_with_scheme(num, less, arb, SigScheme::Ecdsa, is_gen)
+    }
+    ///创建使用Schnorr签名的账户,公钥为x-only公钥
+    pub fn new_schnorr(
+        num: u8,
+        less: u8,
+        arb: bool,
+        is_gen: bool,
+    ) -> Result<Account, errors::Error> {
+        Account::new_with_scheme(num, less, arb, SigScheme::Schnorr, is_gen)
+    }
+    ///创建指定签名算法的账户
+    pub fn new_with_scheme(
+        num: u8,
+        less: u8,
+        arb: bool,
+        scheme: SigScheme,
+        is_gen: bool,
+    ) -> Result<Account, errors::Error> {
         let mut acc = Account {
             num: num,
             less: less,
@@ -306,7 +446,7 @@ impl Account {
             sigs: vec![None; num as usize],
         };
         if is_gen {
-            acc.initialize()
+            acc.generate(scheme)
         }
         if arb {
             acc.arb = num - 1;
@@ -319,13 +459,28 @@ impl Account {
     ///创建新账户
     ///重新生成所有私钥和公钥
     pub fn initialize(&mut self) {
+        self.generate(self.scheme())
+    }
+    //按签名算法生成私钥和公钥
+    fn generate(&mut self, scheme: SigScheme) {
         for i in 0..(self.num as usize) {
             let pk = PriKey::new();
-            let pb = pk.pubkey();
+            let pb = pk.pubkey_with(scheme);
             self.pris[i] = Some(pk);
             self.pubs[i] = Some(pb);
         }
     }
+    ///账户使用的签名算法,由公钥类型决定
+    pub fn scheme(&self) -> SigScheme {
+        self.pubs
+            .iter()
+            .find_map(|v| v.as_ref().map(|pb| pb.scheme()))
+            .unwrap_or(SigScheme::Ecdsa)
+    }
+    ///是否为只读账户,只包含公钥不包含私钥,不能签名
+    pub fn is_watch_only(&self) -> bool {
+        self.pris_size() == 0
+    }
     //检测私钥对应的公钥是否正确
     pub fn check_pris_pubs(&self) -> bool {
         if self.pubs_size() != self.pris_size() {
@@ -334,7 +489,7 @@ impl Account {
         for i in 0..self.pris.len() {
             let eq = match (&self.pubs[i], &self.pris[i]) {
                 (None, None) => true,
-                (Some(ref pb), Some(ref pk)) => &pk.pubkey() == pb,
+                (Some(ref pb), Some(ref pk)) => &pk.pubkey_with(pb.scheme()) == pb,
                 _ => false,
             };
             if !eq {
@@ -372,7 +527,9 @@ impl Account {
         if self.use_arb() && self.arb != self.num - 1 {
             return false;
         }
-        true
+        //所有公钥使用相同的签名算法
+        let scheme = self.scheme();
+        self.pubs.iter().flatten().all(|v| v.scheme() == scheme)
     }
     /// 不带签名和私钥的数据
     pub fn encode_sign(&self, wb: &mut Writer) -> Result<(), errors::Error> {
@@ -440,6 +597,9 @@ impl Account {
             return errors::Error::msg("bech32 varinat error");
         }
         let buf = Vec::<u8>::from_base32(&dat).map_or_else(errors::Error::std, |v| Ok(v))?;
+        if buf.len() != HasherSize {
+            return errors::Error::msg("address length error");
+        }
         Ok((hpr, Hasher::with_bytes(&buf)))
     }
     /// 解码地址并验证
@@ -460,7 +620,7 @@ impl Account {
             return errors::Error::msg("InvalidParam");
         }
         match self.pris[idx] {
-            Some(ref pk) => match pk.sign(msg) {
+            Some(ref pk) => match pk.sign_with(self.scheme(), msg) {
                 Ok(sig) => {
                     self.sigs[idx] = Some(sig);
                     Ok(())
@@ -470,6 +630,108 @@ impl Account {
             None => errors::Error::msg("InvalidPrivateKey"),
         }
     }
+    /// 合并其他账户中的签名,公钥必须一致,用于多方分别签名
+    /// msg为签名数据,合并的签名必须通过对应公钥验签,存在错误的签名时不合并
+    /// 已有的签名验签失败时使用合并的正确签名替换
+    pub fn merge_sigs(&mut self, other: &Account, msg: &[u8]) -> Result<(), errors::Error> {
+        if self != other {
+            return errors::Error::msg("account not match");
+        }
+        let verify = |i: usize, sig: &SigValue| match &self.pubs[i] {
+            Some(pb) => pb.verify(msg, sig).unwrap_or(false),
+            None => false,
+        };
+        let mut merged = vec![];
+        for (i, sig) in other.sigs.iter().enumerate() {
+            let sig = match sig {
+                Some(sig) => sig,
+                None => continue,
+            };
+            if !verify(i, sig) {
+                return errors::Error::msg("merge sign verify error");
+            }
+            if self.sigs[i].as_ref().is_none_or(|v| !verify(i, v)) {
+                merged.push((i, sig.clone()));
+            }
+        }
+        for (i, sig) in merged.into_iter() {
+            self.sigs[i] = Some(sig);
+        }
+        Ok(())
+    }
+    /// 按位置编码公钥和已有的签名,不包含私钥
+    pub fn encode_partial(&self, wb: &mut Writer) {
+        wb.u8(self.num);
+        wb.u8(self.less);
+        wb.u8(self.arb);
+        for pb in self.pubs.iter().filter_map(|v| v.as_ref()) {
+            wb.put(pb);
+        }
+        for sig in self.sigs.iter() {
+            match sig {
+                Some(v) => {
+                    wb.u8(1);
+                    wb.put(v);
+                }
+                None => wb.u8(0),
+            }
+        }
+    }
+    /// 解码encode_partial编码的账户
+    pub fn decode_partial(rb: &mut Reader) -> Result<Self, errors::Error> {
+        let num = rb.u8()?;
+        let less = rb.u8()?;
+        let arb = rb.u8()?;
+        let mut acc = Account::new(num, less, arb != 0xFF, false)?;
+        for i in 0..num as usize {
+            acc.pubs[i] = Some(rb.get()?);
+        }
+        for i in 0..num as usize {
+            if rb.u8()? != 0 {
+                acc.sigs[i] = Some(read_sig(rb, acc.scheme())?);
+            }
+        }
+        if !acc.check_with_pubs() {
+            return errors::Error::msg("InvalidAccount");
+        }
+        Ok(acc)
+    }
+    /// 使用公钥创建不包含私钥的账户,arb为true时最后一个公钥为仲裁公钥
+    pub fn with_pubs(less: u8, arb: bool, pubs: &[PubKey]) -> Result<Account, errors::Error> {
+        if pubs.len() > MAX_ACCOUNT_KEY_SIZE as usize {
+            return errors::Error::msg("InvalidAccount");
+        }
+        let mut acc = Account::new(pubs.len() as u8, less, arb, false)?;
+        for (i, pb) in pubs.iter().enumerate() {
+            acc.pubs[i] = Some(pb.clone());
+        }
+        if !acc.check() {
+            return errors::Error::msg("InvalidAccount");
+        }
+        Ok(acc)
+    }
+    /// 设置指定位置的私钥,私钥必须和公钥对应
+    pub fn set_pri(&mut self, idx: usize, pk: &PriKey) -> Result<(), errors::Error> {
+        if idx >= self.pubs.len() {
+            return errors::Error::msg("InvalidParam");
+        }
+        if self.pubs[idx].as_ref() != Some(&pk.pubkey_with(self.scheme())) {
+            return errors::Error::msg("InvalidPrivateKey");
+        }
+        self.pris[idx] = Some(pk.clone());
+        Ok(())
+    }
+    /// 获取指定位置的私钥
+    pub fn get_pri(&self, idx: usize) -> Option<&PriKey> {
+        self.pris.get(idx).and_then(|v| v.as_ref())
+    }
+    /// 获取只包含公钥的账户,不包含私钥和签名
+    pub fn public(&self) -> Account {
+        let mut acc = self.clone();
+        acc.pris = vec![None; self.num as usize];
+        acc.sigs = vec![None; self.num as usize];
+        acc
+    }
     /// 全签名数据
     pub fn sign_full(&mut self, msg: &[u8]) -> Result<(), errors::Error> {
         for idx in 0..self.pris.len() {
@@ -479,6 +741,10 @@ impl Account {
     }
     ///使用指定的公钥验签所有签名,如果其中一个通过返回true
     pub fn verify_with_public(&self, ipub: usize, msg: &[u8]) -> Result<bool, errors::Error> {
+        self.verify_public_by(ipub, &|pb, sb| pb.verify(msg, sb).unwrap_or(false))
+    }
+    //使用验签函数f验证指定公钥的签名
+    fn verify_public_by(&self, ipub: usize, f: &Verifier) -> Result<bool, errors::Error> {
         if ipub >= self.pubs.len() {
             return errors::Error::msg("InvalidParam");
         }
@@ -491,11 +757,7 @@ impl Account {
         //逐个验证签名
         for iv in self.sigs.iter().filter(|&v| v.is_some()) {
             let sb = iv.as_ref().unwrap();
-            let ret = pb.verify(msg, sb);
-            if ret.is_err() {
-                continue;
-            }
-            if ret.unwrap() {
+            if f(pb, sb) {
                 return Ok(true);
             }
         }
@@ -506,7 +768,7 @@ impl Account {
         &self,
         ipub: usize,
         isig: usize,
-        msg: &[u8],
+        f: &Verifier,
     ) -> Result<bool, errors::Error> {
         if ipub >= self.pubs.len() {
             return errors::Error::msg("InvalidParam");
@@ -515,20 +777,30 @@ impl Account {
             return errors::Error::msg("InvalidParam");
         }
         match (&self.pubs[ipub], &self.sigs[isig]) {
-            (Some(pb), Some(sb)) => match pb.verify(msg, sb) {
-                Ok(rb) => Ok(rb),
-                _ => errors::Error::msg("VerifySignErr"),
-            },
+            (Some(pb), Some(sb)) => Ok(f(pb, sb)),
             _ => return errors::Error::msg("InvalidParam"),
         }
     }
     /// 标准验签
     /// msg数据为签名数据,不需要进行hash,签名时会进行一次Hasher::hash*
+    /// 不启用仲裁: 需要less个公钥按顺序签名
+    /// 启用仲裁: 只验证仲裁公钥(最后一个)的签名
     pub fn verify_full(&self, msg: &[u8]) -> Result<bool, errors::Error> {
+        self.verify_by(&|pb, sb| pb.verify(msg, sb).unwrap_or(false))
+    }
+    /// 使用签名缓存验签,规则同verify_full,只跳过缓存中已通过的签名计算
+    pub fn verify_with_cache(&self, msg: &[u8], cache: &SigCache) -> Result<bool, errors::Error> {
+        self.verify_by(&|pb, sb| cache.verify(msg, pb, sb))
+    }
+    //使用验签函数f按账户规则验签
+    fn verify_by(&self, f: &Verifier) -> Result<bool, errors::Error> {
         //检测账户是否包含公钥
         if !self.check_with_pubs() {
             return errors::Error::msg("verify sign error, check_with_pubs ");
         }
+        if self.scheme() == SigScheme::Schnorr {
+            return self.verify_schnorr(f);
+        }
         //不启用仲裁时最小签名数量
         if !self.use_arb() && self.sigs_size() < self.less {
             return errors::Error::msg("verify sign error, sigs size < less");
@@ -539,7 +811,7 @@ impl Account {
         }
         //验证仲裁公钥签名
         if self.use_arb() {
-            return self.verify_with_public(self.arb as usize, msg);
+            return self.verify_public_by(self.arb as usize, f);
         }
         //检测是否达到签名要求
         let mut less = self.less;
@@ -550,7 +822,7 @@ impl Account {
                 i += 1; //下个签名
                 continue;
             }
-            let rb = self.verify_with_index(j, i, msg);
+            let rb = self.verify_with_index(j, i, f);
             if rb.is_ok() && rb.unwrap() {
                 less -= 1;
                 i += 1; //下个签名
@@ -564,6 +836,92 @@ impl Account {
     }
 }
 
+impl Account {
+    /// Schnorr账户需要验证的公钥和签名,签名按公钥位置存放
+    /// 签名数量不满足要求时返回None,满足时所有签名都必须正确
+    /// 启用仲裁: 和verify_full一致,只验证仲裁公钥签名
+    pub fn schnorr_sigs(&self) -> Result<Option<Vec<(&PubKey, &SigValue)>>, errors::Error> {
+        if self.scheme() != SigScheme::Schnorr || !self.check_with_pubs() {
+            return errors::Error::msg("not schnorr account");
+        }
+        if self.use_arb() {
+            let i = self.arb as usize;
+            return match (&self.pubs[i], &self.sigs[i]) {
+                (Some(pb), Some(sig)) => Ok(Some(vec![(pb, sig)])),
+                _ => Ok(None),
+            };
+        }
+        let mut items = vec![];
+        for (pb, sig) in self.pubs.iter().zip(self.sigs.iter()) {
+            if let (Some(pb), Some(sig)) = (pb, sig) {
+                items.push((pb, sig));
+            }
+        }
+        if items.len() < self.less as usize {
+            return Ok(None);
+        }
+        Ok(Some(items))
+    }
+    /// Schnorr账户验签,存在错误的签名返回错误
+    fn verify_schnorr(&self, f: &Verifier) -> Result<bool, errors::Error> {
+        let items = match self.schnorr_sigs()? {
+            Some(items) => items,
+            None => return Ok(false),
+        };
+        for (pb, sig) in items {
+            if !f(pb, sig) {
+                return errors::Error::msg("schnorr sign verify error");
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_account_schnorr() {
+    let msg = "aaa".as_bytes();
+    let acc = Account::new_schnorr(3, 2, false, true).unwrap();
+    assert_eq!(acc.scheme(), SigScheme::Schnorr);
+    assert!(acc.check_pris_pubs());
+    //x-only公钥生成不同的地址
+    assert_eq!(acc.public().into_bytes().len(), 3 + 1 + 3 * (1 + 32) + 1);
+    let mut tmp = acc.clone();
+    tmp.sign_with_index(1, msg).unwrap();
+    assert!(!tmp.verify_full(msg).unwrap());
+    tmp.sign_with_index(0, msg).unwrap();
+    assert!(tmp.verify_full(msg).unwrap());
+    //签名按公钥位置编码
+    let dec = Account::from_bytes(&tmp.into_bytes()).unwrap();
+    assert_eq!(dec.schnorr_sigs().unwrap().unwrap().len(), 2);
+    assert!(dec.verify_full(msg).unwrap());
+    //启用仲裁时只验证仲裁签名
+    let arb = Account::new_schnorr(3, 2, true, true).unwrap();
+    let mut tmp = arb.clone();
+    tmp.sign_with_index(0, msg).unwrap();
+    tmp.sign_with_index(1, msg).unwrap();
+    assert!(!tmp.verify_full(msg).unwrap());
+    tmp.sign_with_index(2, msg).unwrap();
+    let dec = Account::from_bytes(&tmp.into_bytes()).unwrap();
+    assert_eq!(dec.schnorr_sigs().unwrap().unwrap().len(), 1);
+    assert!(dec.verify_full(msg).unwrap());
+    //错误的签名返回错误
+    let mut bad = acc.clone();
+    bad.sign_with_index(0, msg).unwrap();
+    bad.sign_with_index(1, "bbb".as_bytes()).unwrap();
+    assert!(bad.verify_full(msg).is_err());
+    //不能混用公钥类型
+    let pubs = vec![PriKey::new().pubkey(), PriKey::new().xonly_pubkey()];
+    assert!(Account::with_pubs(1, false, &pubs).is_err());
+    assert!(Account::new(1, 1, false, true)
+        .unwrap()
+        .schnorr_sigs()
+        .is_err());
+    //钱包编码
+    let dec = Account::decode_from_bech32(&acc.encode_to_bech32().unwrap()).unwrap();
+    assert_eq!(dec, acc);
+    assert!(dec.check_pris_pubs());
+}
+
 #[test]
 fn test_account_encode_sign() {
     let wb = &mut Writer::default();
@@ -580,6 +938,66 @@ fn test_account_verify_true() {
     assert_eq!(true, acc.verify_full("aaa".as_bytes()).unwrap());
 }
 
+#[test]
+fn test_account_merge_sigs() {
+    let msg = "aaa".as_bytes();
+    let acc = Account::new(3, 2, false, true).unwrap();
+    let mut a1 = acc.public();
+    let mut a2 = acc.clone();
+    a2.sign_with_index(0, msg).unwrap();
+    a2.sign_with_index(1, "bbb".as_bytes()).unwrap();
+    //存在错误的签名时不合并
+    assert!(a1.merge_sigs(&a2, msg).is_err());
+    assert_eq!(a1.sigs_size(), 0);
+    //已有的错误签名被正确的签名替换
+    let mut a3 = acc.clone();
+    a3.sign_with_index(1, msg).unwrap();
+    a2.merge_sigs(&a3, msg).unwrap();
+    assert!(a2.verify_full(msg).unwrap());
+    a1.merge_sigs(&a2.public(), msg).unwrap();
+    a1.merge_sigs(&a2, msg).unwrap();
+    assert!(a1.verify_full(msg).unwrap());
+    //正确的签名不被替换
+    let bytes = |acc: &Account| -> Vec<Vec<u8>> {
+        acc.sigs[..2]
+            .iter()
+            .map(|v| v.as_ref().unwrap().into_bytes())
+            .collect()
+    };
+    let sigs = bytes(&a1);
+    let mut a4 = acc.clone();
+    a4.sign_full(msg).unwrap();
+    a1.merge_sigs(&a4, msg).unwrap();
+    assert_eq!(bytes(&a1), sigs);
+    assert!(a1.sigs[2].is_some());
+    assert!(a1
+        .merge_sigs(&Account::new(3, 2, false, true).unwrap(), msg)
+        .is_err());
+}
+
+#[test]
+fn test_account_verify_arb() {
+    let msg = "aaa".as_bytes();
+    let acc = Account::new(3, 2, true, true).unwrap();
+    assert!(acc.use_arb());
+    //仲裁单独签名通过
+    let mut tmp = acc.clone();
+    tmp.sign_with_index(2, msg).unwrap();
+    assert!(tmp.verify_full(msg).unwrap());
+    //非仲裁公钥的签名不能通过
+    let mut tmp = acc.clone();
+    tmp.sign_with_index(0, msg).unwrap();
+    tmp.sign_with_index(1, msg).unwrap();
+    assert!(tmp.verify_full(msg).is_err());
+    //仲裁签名和非仲裁签名
+    tmp.sign_with_index(2, msg).unwrap();
+    assert!(tmp.verify_full(msg).unwrap());
+    //签名数据不一致
+    let mut tmp = acc.clone();
+    tmp.sign_with_index(2, "bbb".as_bytes()).unwrap();
+    assert!(tmp.verify_full(msg).is_err());
+}
+
 #[test]
 fn test_account_verify_false() {
     let mut acc = Account::new(5, 2, false, true).unwrap();
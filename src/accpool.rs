@@ -1,7 +1,13 @@
-use crate::account::{Account, AccountPool};
+use crate::account::{Account, AccountPool, CoinSelector, HasAddress};
 use crate::errors::Error;
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::hasher::Hasher;
+use crate::iobuf::{Reader, Serializer, Writer};
+use crate::keystore::{KdfParams, Keystore, SealKey};
+use crate::util;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 /// 基于测试账户管理
 #[derive(Debug)]
 pub struct AccTestPool {
@@ -31,8 +37,8 @@ impl AccountPool for AccTestPool {
             .get(id)
             .map_or(Error::msg("not found account"), |v| Ok(v.clone()))
     }
-    fn list_keys(&self) -> &Vec<String> {
-        &self.keys
+    fn list_keys(&self) -> Vec<String> {
+        self.keys.clone()
     }
     fn len(&self) -> usize {
         self.pool.len()
@@ -43,11 +49,11 @@ impl AccountPool for AccTestPool {
         }
         self.account(&self.keys[idx])
     }
-    fn key(&self, idx: usize) -> Result<&String, Error> {
+    fn key(&self, idx: usize) -> Result<String, Error> {
         if idx >= self.len() {
             return Error::msg("idx error");
         }
-        Ok(&self.keys[idx])
+        Ok(self.keys[idx].clone())
     }
 }
 
@@ -57,3 +63,766 @@ fn test_acc_test_pool() {
     let keys = p.list_keys();
     assert_eq!(keys.len(), 3);
 }
+
+/// 钱包账户文件扩展名
+const WALLET_EXT: &str = "acc";
+/// 持久化的金额锁定文件
+const LOCKS_FILE: &str = "locks.dat";
+/// 钱包密钥校验文件
+const KEY_FILE: &str = "wallet.key";
+/// 删除的账户文件移动到这个子目录
+const TRASH_DIR: &str = "trash";
+
+/// 钱包账户元数据
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AccountMeta {
+    /// 账户标签
+    pub label: String,
+    /// 创建时的区块高度,扫描交易时可从这个高度开始
+    pub height: u32,
+    /// 创建时间
+    pub time: i64,
+    /// 加入钱包的顺序
+    pub seq: u32,
+}
+
+impl Serializer for AccountMeta {
+    fn encode(&self, w: &mut Writer) {
+        w.usize(self.label.len());
+        w.put_bytes(self.label.as_bytes());
+        w.u32(self.height);
+        w.i64(self.time);
+        w.u32(self.seq);
+    }
+    fn decode(r: &mut Reader) -> Result<AccountMeta, Error> {
+        let size = r.usize()?;
+        let label = String::from_utf8(r.get_bytes(size)?).map_or_else(Error::std, Ok)?;
+        Ok(AccountMeta {
+            label,
+            height: r.u32()?,
+            time: r.i64()?,
+            seq: r.u32()?,
+        })
+    }
+}
+
+/// 钱包条目,只读地址没有账户信息
+#[derive(Debug, Clone)]
+enum Entry {
+    /// 只包含公钥的只读账户,旧版本保存的明文完整账户解锁时迁移为加密账户
+    Account(Arc<Account>),
+    /// 加密保存的完整账户
+    Keystore(Keystore),
+    /// 只读地址
+    Address(Hasher),
+}
+
+/// 钱包内存中的账户
+#[derive(Debug, Default)]
+struct Wallet {
+    pool: HashMap<String, (AccountMeta, Entry)>,
+    keys: Vec<String>,
+    //锁定的交易输出,值为是否持久化
+    locks: BTreeMap<(Hasher, u16), bool>,
+    //解锁后从钱包密码推导的密钥,锁定时为None
+    key: Option<SealKey>,
+}
+
+/// 基于文件的钱包账户管理
+/// 每个账户保存为目录下的 地址.acc 文件,包含元数据和账户数据
+/// 完整账户使用钱包密钥加密保存(Keystore),只读账户和地址明文保存
+/// 钱包密钥由set_password设置的密码推导,wallet.key文件用来校验密码
+/// 打开后为锁定状态,解锁后才能签名和创建导入完整账户
+/// 账户数据由读写锁保护,可以在链使用的同时创建导入和删除
+#[derive(Debug)]
+pub struct WalletPool {
+    dir: String,
+    params: KdfParams,
+    inner: RwLock<Wallet>,
+}
+
+impl WalletPool {
+    /// 打开钱包目录并加载所有账户,目录不存在时创建
+    pub fn open(dir: &str) -> Result<Self, Error> {
+        WalletPool::open_with_params(dir, KdfParams::default())
+    }
+    /// 使用指定的kdf参数打开钱包,参数用于新加密的账户
+    pub fn open_with_params(dir: &str, params: KdfParams) -> Result<Self, Error> {
+        params.check()?;
+        util::miss_create_dir(dir)?;
+        let mut wallet = Wallet::default();
+        let mut items = vec![];
+        for entry in fs::read_dir(dir).map_or_else(Error::std, Ok)? {
+            let path = entry.map_or_else(Error::std, Ok)?.path();
+            if path.extension().is_none_or(|v| v != WALLET_EXT) {
+                continue;
+            }
+            let path = path.to_str().ok_or(Error::error("wallet path error"))?;
+            items.push(WalletPool::load_file(path)?);
+        }
+        //按加入顺序排列
+        items.sort_by_key(|(meta, _)| meta.seq);
+        for (meta, entry) in items.into_iter() {
+            let addr = match entry {
+                Entry::Account(ref acc) => acc.encode()?,
+                Entry::Keystore(ref ks) => ks.account().encode()?,
+                Entry::Address(ref hv) => hv.string()?,
+            };
+            wallet.keys.push(addr.clone());
+            wallet.pool.insert(addr, (meta, entry));
+        }
+        let pool = WalletPool {
+            dir: dir.into(),
+            params,
+            inner: RwLock::new(wallet),
+        };
+        let path = pool.locks_path()?;
+        if Path::new(&path).exists() {
+            let locks = util::read_file(&path, |buf| {
+                let mut r = Reader::new(buf);
+                let mut locks = BTreeMap::new();
+                for _ in 0..r.u32()? {
+                    let tx: Hasher = r.decode()?;
+                    locks.insert((tx, r.u16()?), true);
+                }
+                Ok(locks)
+            })?;
+            pool.do_write(|wallet| {
+                wallet.locks = locks;
+                Ok(())
+            })?;
+        }
+        Ok(pool)
+    }
+    /// lock read process
+    fn do_read<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&Wallet) -> Result<R, Error>,
+    {
+        self.inner.read().map_or_else(Error::std, |ref v| f(v))
+    }
+    /// lock write process
+    fn do_write<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Wallet) -> Result<R, Error>,
+    {
+        self.inner.write().map_or_else(Error::std, |ref mut v| f(v))
+    }
+    /// 加载账户文件
+    fn load_file(path: &str) -> Result<(AccountMeta, Entry), Error> {
+        util::read_file(path, |buf| {
+            let mut r = Reader::new(buf);
+            let meta: AccountMeta = r.decode()?;
+            if Keystore::is_keystore(r.bytes()) {
+                let ks = Keystore::decode_from_reader(&mut r)?;
+                return Ok((meta, Entry::Keystore(ks)));
+            }
+            //账户公钥数量不为0,0表示只读地址
+            if r.bytes().first() == Some(&0) {
+                r.u8()?;
+                return Ok((meta, Entry::Address(r.decode()?)));
+            }
+            let acc = Account::decode_from_reader(&mut r)?;
+            Ok((meta, Entry::Account(Arc::new(acc))))
+        })
+    }
+    /// 保存账户文件,先写临时文件再替换
+    fn save_file(&self, addr: &str, meta: &AccountMeta, entry: &Entry) -> Result<(), Error> {
+        let mut wb = Writer::default();
+        wb.encode(meta);
+        match entry {
+            Entry::Account(acc) if !acc.is_watch_only() => {
+                return Error::msg("plaintext account can't save");
+            }
+            Entry::Keystore(ks) => ks.encode_to_writer(&mut wb),
+            Entry::Account(acc) => {
+                //账户数据校验和只包含账户部分
+                let mut ab = Writer::default();
+                acc.encode_to_writer(&mut ab)?;
+                wb.put_writer(&ab);
+            }
+            Entry::Address(hv) => {
+                wb.u8(0);
+                wb.encode(hv);
+            }
+        }
+        let path = self.path(addr)?;
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, wb.bytes()).map_or_else(Error::std, Ok)?;
+        fs::rename(&tmp, &path).map_or_else(Error::std, Ok)
+    }
+    /// 删除的账户文件路径,回收目录不存在时创建
+    fn trash_path(&self, addr: &str) -> Result<String, Error> {
+        let dir = Path::new(&self.dir).join(TRASH_DIR);
+        let dir = dir.to_str().ok_or(Error::error("wallet path error"))?;
+        util::miss_create_dir(dir)?;
+        Path::new(dir)
+            .join(format!("{}.{}.{}", addr, util::timestamp(), WALLET_EXT))
+            .to_str()
+            .map_or(Error::msg("wallet path error"), |v| Ok(v.into()))
+    }
+    /// 金额锁定文件路径
+    fn locks_path(&self) -> Result<String, Error> {
+        Path::new(&self.dir)
+            .join(LOCKS_FILE)
+            .to_str()
+            .map_or(Error::msg("wallet path error"), |v| Ok(v.into()))
+    }
+    /// 保存需要持久化的金额锁定
+    fn save_locks(&self, locks: &BTreeMap<(Hasher, u16), bool>) -> Result<(), Error> {
+        let mut wb = Writer::default();
+        let keys: Vec<&(Hasher, u16)> = locks.iter().filter(|v| *v.1).map(|v| v.0).collect();
+        wb.u32(keys.len() as u32);
+        for (tx, idx) in keys {
+            wb.encode(tx);
+            wb.u16(*idx);
+        }
+        let path = self.locks_path()?;
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, wb.bytes()).map_or_else(Error::std, Ok)?;
+        fs::rename(&tmp, &path).map_or_else(Error::std, Ok)
+    }
+    /// 钱包密钥校验文件路径
+    fn key_path(&self) -> Result<String, Error> {
+        Path::new(&self.dir)
+            .join(KEY_FILE)
+            .to_str()
+            .map_or(Error::msg("wallet path error"), |v| Ok(v.into()))
+    }
+    /// 使用钱包密钥加密完整账户,钱包锁定时返回错误
+    fn seal_entry(&self, wallet: &Wallet, entry: Entry) -> Result<Entry, Error> {
+        match (entry, &wallet.key) {
+            (Entry::Account(acc), Some(key)) if !acc.is_watch_only() => {
+                Ok(Entry::Keystore(Keystore::with_key(&acc, key)?))
+            }
+            (Entry::Account(acc), None) if !acc.is_watch_only() => Error::msg("wallet locked"),
+            (entry, _) => Ok(entry),
+        }
+    }
+    /// 账户文件路径
+    fn path(&self, addr: &str) -> Result<String, Error> {
+        Path::new(&self.dir)
+            .join(format!("{}.{}", addr, WALLET_EXT))
+            .to_str()
+            .map_or(Error::msg("wallet path error"), |v| Ok(v.into()))
+    }
+    /// 添加条目并保存
+    fn insert_entry(
+        &self,
+        addr: String,
+        entry: Entry,
+        label: &str,
+        height: u32,
+    ) -> Result<String, Error> {
+        self.do_write(|wallet| {
+            if wallet.pool.contains_key(&addr) {
+                return Error::msg("account exists");
+            }
+            let entry = self.seal_entry(wallet, entry)?;
+            let meta = AccountMeta {
+                label: label.into(),
+                height,
+                time: util::timestamp(),
+                seq: wallet
+                    .pool
+                    .values()
+                    .map(|(v, _)| v.seq + 1)
+                    .max()
+                    .unwrap_or(0),
+            };
+            self.save_file(&addr, &meta, &entry)?;
+            wallet.keys.push(addr.clone());
+            wallet.pool.insert(addr.clone(), (meta, entry));
+            Ok(addr)
+        })
+    }
+    /// 添加账户,返回账户地址
+    /// 账户需要包含所有私钥,或者不包含私钥作为只读账户
+    /// 完整账户需要钱包解锁,使用钱包密码加密保存
+    pub fn insert(&self, acc: &Account, label: &str, height: u32) -> Result<String, Error> {
+        if !acc.is_watch_only() && !acc.check_pris_pubs() {
+            return Error::msg("import account pris error");
+        }
+        let entry = Entry::Account(Arc::new(acc.clone()));
+        self.insert_entry(acc.encode()?, entry, label, height)
+    }
+    /// 添加只读地址,返回地址
+    pub fn insert_address(&self, hv: &Hasher, label: &str, height: u32) -> Result<String, Error> {
+        self.insert_entry(hv.string()?, Entry::Address(hv.clone()), label, height)
+    }
+    /// 获取账户元数据
+    pub fn meta(&self, id: &str) -> Result<AccountMeta, Error> {
+        self.do_read(|wallet| {
+            wallet.pool.get(id).map_or(
+                Error::msg("not found account"),
+                |(meta, _)| Ok(meta.clone()),
+            )
+        })
+    }
+    /// 使用密钥解密所有完整账户,先全部解密,失败时不修改钱包
+    /// 不是这个密钥加密的账户使用密码解密后重新加密,返回值最后一项为是否需要保存
+    fn reseal_entries(
+        wallet: &Wallet,
+        key: &SealKey,
+        password: &str,
+    ) -> Result<Vec<(String, AccountMeta, Entry, bool)>, Error> {
+        let mut entries = vec![];
+        for (addr, (meta, entry)) in wallet.pool.iter() {
+            let (ks, migrate) = match entry {
+                Entry::Keystore(ks) if ks.is_sealed_with(key) => {
+                    let mut ks = ks.clone();
+                    ks.unlock_with_key(key)?;
+                    (ks, false)
+                }
+                Entry::Keystore(ks) => {
+                    let mut ks = ks.clone();
+                    ks.unlock(password)?;
+                    ks.seal_with_key(key)?;
+                    (ks, true)
+                }
+                Entry::Account(acc) if !acc.is_watch_only() => {
+                    (Keystore::with_key(acc, key)?, true)
+                }
+                _ => continue,
+            };
+            entries.push((addr.clone(), meta.clone(), Entry::Keystore(ks), migrate));
+        }
+        Ok(entries)
+    }
+    /// 更新解密后的账户并保存密钥
+    fn apply_entries(
+        wallet: &mut Wallet,
+        entries: Vec<(String, AccountMeta, Entry, bool)>,
+        key: SealKey,
+    ) {
+        for (addr, meta, entry, _) in entries.into_iter() {
+            wallet.pool.insert(addr, (meta, entry));
+        }
+        wallet.key = Some(key);
+    }
+}
+
+impl AccountPool for WalletPool {
+    /// 钱包锁定时只返回公钥,不能签名
+    /// 旧版本的明文完整账户在解锁迁移前同样不返回私钥
+    fn account(&self, id: &str) -> Result<Arc<Account>, Error> {
+        self.do_read(|wallet| match wallet.pool.get(id) {
+            Some((_, Entry::Account(acc))) if wallet.key.is_none() => Ok(Arc::new(acc.public())),
+            Some((_, Entry::Account(acc))) => Ok(acc.clone()),
+            Some((_, Entry::Keystore(ks))) => Ok(Arc::new(ks.account().clone())),
+            Some((_, Entry::Address(_))) => Error::msg("watch address no account"),
+            None => Error::msg("not found account"),
+        })
+    }
+    fn list_keys(&self) -> Vec<String> {
+        self.do_read(|wallet| Ok(wallet.keys.clone()))
+            .unwrap_or_default()
+    }
+    fn len(&self) -> usize {
+        self.do_read(|wallet| Ok(wallet.keys.len())).unwrap_or(0)
+    }
+    fn value(&self, idx: usize) -> Result<Arc<Account>, Error> {
+        self.account(&self.key(idx)?)
+    }
+    fn key(&self, idx: usize) -> Result<String, Error> {
+        self.do_read(|wallet| {
+            wallet
+                .keys
+                .get(idx)
+                .map_or(Error::msg("idx error"), |v| Ok(v.clone()))
+        })
+    }
+    fn is_watch_only(&self, id: &str) -> Result<bool, Error> {
+        self.do_read(|wallet| match wallet.pool.get(id) {
+            Some((_, Entry::Account(acc))) => Ok(acc.is_watch_only()),
+            Some((_, Entry::Keystore(_))) => Ok(false),
+            Some((_, Entry::Address(_))) => Ok(true),
+            None => Error::msg("not found account"),
+        })
+    }
+    fn create(
+        &self,
+        num: u8,
+        less: u8,
+        arb: bool,
+        label: &str,
+        height: u32,
+    ) -> Result<String, Error> {
+        let acc = Account::new(num, less, arb, true)?;
+        self.insert(&acc, label, height)
+    }
+    fn import(&self, value: &str, label: &str, height: u32) -> Result<String, Error> {
+        //地址作为只读地址导入
+        if let Ok(hv) = Account::decode(value) {
+            return self.insert_address(&hv, label, height);
+        }
+        let acc = Account::decode_from_bech32(value)?;
+        self.insert(&acc, label, height)
+    }
+    /// 账户文件移动到回收目录,文件名加上删除时间,需要时可以手动恢复
+    fn remove(&self, id: &str) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            if !wallet.pool.contains_key(id) {
+                return Error::msg("not found account");
+            }
+            let trash = self.trash_path(id)?;
+            fs::rename(self.path(id)?, trash).map_or_else(Error::std, Ok)?;
+            wallet.keys.retain(|v| v != id);
+            wallet.pool.remove(id);
+            Ok(())
+        })
+    }
+    fn rename(&self, id: &str, label: &str) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            let (meta, entry) = wallet
+                .pool
+                .get_mut(id)
+                .map_or(Error::msg("not found account"), Ok)?;
+            let mut tmp = meta.clone();
+            tmp.label = label.into();
+            self.save_file(id, &tmp, entry)?;
+            *meta = tmp;
+            Ok(())
+        })
+    }
+    fn lock_coin(&self, tx: &Hasher, idx: u16, persist: bool) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            let key = (tx.clone(), idx);
+            if wallet.locks.contains_key(&key) {
+                return Error::msg("coin already locked");
+            }
+            wallet.locks.insert(key.clone(), persist);
+            if persist {
+                if let Err(err) = self.save_locks(&wallet.locks) {
+                    wallet.locks.remove(&key);
+                    return Err(err);
+                }
+            }
+            Ok(())
+        })
+    }
+    fn unlock_coin(&self, tx: &Hasher, idx: u16) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            let key = (tx.clone(), idx);
+            match wallet.locks.remove(&key) {
+                Some(true) => self.save_locks(&wallet.locks).inspect_err(|_| {
+                    wallet.locks.insert(key, true);
+                }),
+                Some(false) => Ok(()),
+                None => Error::msg("coin not locked"),
+            }
+        })
+    }
+    fn is_locked_coin(&self, tx: &Hasher, idx: u16) -> bool {
+        self.do_read(|wallet| Ok(wallet.locks.contains_key(&(tx.clone(), idx))))
+            .unwrap_or(false)
+    }
+    fn list_locked_coins(&self) -> Vec<(Hasher, u16)> {
+        self.do_read(|wallet| Ok(wallet.locks.keys().cloned().collect()))
+            .unwrap_or_default()
+    }
+    fn select_lock_coins(&self, persist: bool, f: &mut CoinSelector) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            let keys = {
+                let locks = &wallet.locks;
+                f(&|tx, idx| locks.contains_key(&(tx.clone(), idx)))?
+            };
+            let mut locks = wallet.locks.clone();
+            for key in keys.into_iter() {
+                if locks.insert(key, persist).is_some() {
+                    return Error::msg("coin already locked");
+                }
+            }
+            if persist {
+                self.save_locks(&locks)?;
+            }
+            wallet.locks = locks;
+            Ok(())
+        })
+    }
+    fn release_coins(&self, keys: &[(Hasher, u16)]) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            let mut save = false;
+            for key in keys.iter() {
+                save |= wallet.locks.remove(key) == Some(true);
+            }
+            if save {
+                self.save_locks(&wallet.locks)?;
+            }
+            Ok(())
+        })
+    }
+    /// 设置钱包密码,只能设置一次,设置后钱包为解锁状态
+    /// 已存在的完整账户使用钱包密钥重新加密,旧版本单独加密的账户需要使用相同的密码
+    fn set_password(&self, password: &str) -> Result<(), Error> {
+        if password.is_empty() {
+            return Error::msg("wallet password empty");
+        }
+        let path = self.key_path()?;
+        if Path::new(&path).exists() {
+            return Error::msg("wallet password already set");
+        }
+        //推导密钥不需要持有钱包锁
+        let key = SealKey::new(password, self.params)?;
+        self.do_write(|wallet| {
+            let entries = WalletPool::reseal_entries(wallet, &key, password)?;
+            for (addr, meta, entry, _) in entries.iter() {
+                self.save_file(addr, meta, entry)?;
+            }
+            key.save(&path)?;
+            WalletPool::apply_entries(wallet, entries, key);
+            Ok(())
+        })
+    }
+    /// 使用钱包密码解锁,只推导一次密钥,所有账户使用这个密钥解密
+    /// 旧版本的明文完整账户和单独加密的账户解锁时使用钱包密钥重新加密保存
+    fn unlock(&self, password: &str) -> Result<(), Error> {
+        let path = self.key_path()?;
+        if !Path::new(&path).exists() {
+            return Error::msg("wallet password not set");
+        }
+        //推导密钥不需要持有钱包锁,密码错误时返回错误
+        let key = SealKey::load(&path, password)?;
+        self.do_write(|wallet| {
+            let entries = WalletPool::reseal_entries(wallet, &key, password)?;
+            for (addr, meta, entry, _) in entries.iter().filter(|v| v.3) {
+                self.save_file(addr, meta, entry)?;
+            }
+            WalletPool::apply_entries(wallet, entries, key);
+            Ok(())
+        })
+    }
+    fn lock(&self) -> Result<(), Error> {
+        self.do_write(|wallet| {
+            for (_, entry) in wallet.pool.values_mut() {
+                if let Entry::Keystore(ks) = entry {
+                    ks.lock();
+                }
+            }
+            wallet.key = None;
+            Ok(())
+        })
+    }
+    fn is_locked(&self) -> bool {
+        self.do_read(|wallet| Ok(wallet.key.is_none()))
+            .unwrap_or(true)
+    }
+}
+
+#[test]
+fn test_wallet_pool() {
+    use crate::bytes::IntoBytes;
+    use tempdir::TempDir;
+    let tmp = TempDir::new("wallet").unwrap();
+    let dir = tmp.path().join("wallet");
+    let dir = dir.to_str().unwrap();
+    let params = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    assert_eq!(wallet.len(), 0);
+    //锁定时不能创建完整账户
+    assert!(wallet.is_locked());
+    assert!(wallet.create(1, 1, false, "mining", 10).is_err());
+    //没有设置密码时不能解锁,空密码不能设置
+    assert!(wallet.unlock("pass").is_err());
+    assert!(wallet.unlock("").is_err());
+    assert!(wallet.is_locked());
+    assert!(wallet.set_password("").is_err());
+    wallet.set_password("pass").unwrap();
+    assert!(wallet.set_password("other").is_err());
+    assert!(!wallet.is_locked());
+    let a1 = wallet.create(1, 1, false, "mining", 10).unwrap();
+    let a2 = wallet.create(3, 2, true, "escrow", 20).unwrap();
+    let acc = Account::new(2, 2, false, true).unwrap();
+    let a3 = wallet.insert(&acc, "imported", 30).unwrap();
+    assert!(wallet.insert(&acc, "again", 30).is_err());
+    assert!(wallet.insert(&acc.public(), "public", 30).is_err());
+    //只读账户和只读地址
+    let cold = Account::new(1, 1, false, true).unwrap();
+    let a4 = wallet.insert(&cold.public(), "cold", 40).unwrap();
+    let a5 = wallet.insert_address(&cold.hash().unwrap(), "addr", 50);
+    assert!(a5.is_err());
+    let hv = Account::new(1, 1, false, true).unwrap().hash().unwrap();
+    let a5 = wallet.insert_address(&hv, "addr", 50).unwrap();
+    //文件中不包含明文私钥
+    let pri = acc.get_pri(0).unwrap().into_bytes();
+    let buf = fs::read(wallet.path(&a3).unwrap()).unwrap();
+    assert!(!buf.windows(pri.len()).any(|v| v == &pri[..]));
+    //重新打开后按顺序加载,加密账户为锁定状态
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    assert_eq!(
+        wallet.list_keys(),
+        vec![a1.clone(), a2.clone(), a3.clone(), a4.clone(), a5.clone()]
+    );
+    assert!(wallet.is_locked());
+    assert!(!wallet.account(&a3).unwrap().check_pris_pubs());
+    assert_eq!(wallet.account(&a3).unwrap().hash(), acc.hash());
+    assert!(wallet.unlock("bad").is_err());
+    assert!(wallet.unlock("").is_err());
+    assert!(wallet.is_locked());
+    wallet.unlock("pass").unwrap();
+    //所有账户使用同一个钱包密钥加密
+    wallet
+        .do_read(|w| {
+            let key = w.key.as_ref().unwrap();
+            for (_, entry) in w.pool.values() {
+                if let Entry::Keystore(ks) = entry {
+                    assert!(ks.is_sealed_with(key));
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert!(wallet.is_watch_only(&a4).unwrap());
+    assert!(wallet.is_watch_only(&a5).unwrap());
+    assert!(!wallet.is_watch_only(&a3).unwrap());
+    assert_eq!(*wallet.account(&a4).unwrap(), cold);
+    assert!(wallet.account(&a5).is_err());
+    assert_eq!(*wallet.value(2).unwrap(), acc);
+    assert!(wallet.value(2).unwrap().check_pris_pubs());
+    let meta = wallet.meta(&a2).unwrap();
+    assert_eq!(meta.label, "escrow");
+    assert_eq!(meta.height, 20);
+    assert_eq!(meta.seq, 1);
+    assert!(wallet.account(&a1).unwrap().check_pris_pubs());
+    //锁定后清除私钥
+    wallet.lock().unwrap();
+    assert!(!wallet.account(&a1).unwrap().check_pris_pubs());
+}
+
+#[test]
+fn test_wallet_pool_legacy_account() {
+    use tempdir::TempDir;
+    let tmp = TempDir::new("wallet").unwrap();
+    let dir = tmp.path().join("wallet");
+    let dir = dir.to_str().unwrap();
+    let params = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    //旧版本明文保存的完整账户
+    let acc = Account::new(1, 1, false, true).unwrap();
+    let addr = acc.encode().unwrap();
+    let mut wb = Writer::default();
+    wb.encode(&AccountMeta::default());
+    let mut ab = Writer::default();
+    acc.encode_to_writer(&mut ab).unwrap();
+    wb.put_writer(&ab);
+    fs::write(wallet.path(&addr).unwrap(), wb.bytes()).unwrap();
+    //锁定时不返回私钥
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    assert!(wallet.is_locked());
+    assert!(!wallet.is_watch_only(&addr).unwrap());
+    assert!(!wallet.account(&addr).unwrap().check_pris_pubs());
+    assert_eq!(wallet.account(&addr).unwrap().hash(), acc.hash());
+    //设置密码后使用钱包密钥加密保存
+    assert!(wallet.unlock("pass").is_err());
+    wallet.set_password("pass").unwrap();
+    assert_eq!(*wallet.account(&addr).unwrap(), acc);
+    let buf = fs::read(wallet.path(&addr).unwrap()).unwrap();
+    let mut r = Reader::new(&buf);
+    let _: AccountMeta = r.decode().unwrap();
+    assert!(Keystore::is_keystore(r.bytes()));
+    wallet.lock().unwrap();
+    assert!(!wallet.account(&addr).unwrap().check_pris_pubs());
+    //旧版本单独加密的账户在解锁时使用钱包密钥重新加密
+    let old = Account::new(1, 1, false, true).unwrap();
+    let oaddr = old.encode().unwrap();
+    let ks = Keystore::with_params(&old, "pass", params).unwrap();
+    wallet
+        .save_file(&oaddr, &AccountMeta::default(), &Entry::Keystore(ks))
+        .unwrap();
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    wallet.unlock("pass").unwrap();
+    assert_eq!(*wallet.account(&oaddr).unwrap(), old);
+    let key = SealKey::load(&wallet.key_path().unwrap(), "pass").unwrap();
+    match WalletPool::load_file(&wallet.path(&oaddr).unwrap()).unwrap() {
+        (_, Entry::Keystore(ks)) => assert!(ks.is_sealed_with(&key)),
+        _ => panic!("keystore entry"),
+    }
+}
+
+#[test]
+fn test_wallet_pool_mutable() {
+    use std::thread;
+    use tempdir::TempDir;
+    let tmp = TempDir::new("wallet").unwrap();
+    let dir = tmp.path().join("wallet");
+    let dir = dir.to_str().unwrap();
+    let params = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+    let pool: Arc<dyn AccountPool> = Arc::new(WalletPool::open_with_params(dir, params).unwrap());
+    pool.set_password("pass").unwrap();
+    //多线程同时创建和读取
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                let addr = pool.create(1, 1, false, &format!("t{}", i), 0).unwrap();
+                assert!(pool.list_keys().contains(&addr));
+                pool.account(&addr).unwrap();
+                addr
+            })
+        })
+        .collect();
+    let addrs: Vec<String> = handles.into_iter().map(|v| v.join().unwrap()).collect();
+    assert_eq!(pool.len(), 4);
+    //导入bech32编码的账户
+    let acc = Account::new(2, 2, false, true).unwrap();
+    let addr = pool
+        .import(&acc.encode_to_bech32().unwrap(), "cold", 5)
+        .unwrap();
+    assert_eq!(pool.key(4).unwrap(), addr);
+    assert!(pool.import("aps1invalid", "bad", 0).is_err());
+    //修改标签和删除
+    pool.rename(&addr, "hot").unwrap();
+    assert!(pool.rename("btx1none", "hot").is_err());
+    pool.remove(&addrs[0]).unwrap();
+    assert!(pool.account(&addrs[0]).is_err());
+    //账户文件移动到回收目录
+    let trash: Vec<_> = fs::read_dir(tmp.path().join("wallet").join(TRASH_DIR))
+        .unwrap()
+        .map(|v| v.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(trash.len(), 1);
+    assert!(trash[0].starts_with(&addrs[0]));
+    assert!(pool.remove(&addrs[0]).is_err());
+    //重新打开后数据一致
+    let wallet = WalletPool::open_with_params(dir, params).unwrap();
+    wallet.unlock("pass").unwrap();
+    assert_eq!(wallet.list_keys(), pool.list_keys());
+    assert_eq!(wallet.meta(&addr).unwrap().label, "hot");
+    assert_eq!(*wallet.account(&addr).unwrap(), acc);
+}
+
+#[test]
+fn test_wallet_pool_coin_lock() {
+    use tempdir::TempDir;
+    let tmp = TempDir::new("wallet").unwrap();
+    let dir = tmp.path().join("wallet");
+    let dir = dir.to_str().unwrap();
+    let wallet = WalletPool::open(dir).unwrap();
+    let (t1, t2) = (Hasher::hash(b"tx1"), Hasher::hash(b"tx2"));
+    wallet.lock_coin(&t1, 0, true).unwrap();
+    wallet.lock_coin(&t1, 1, false).unwrap();
+    wallet.lock_coin(&t2, 0, true).unwrap();
+    assert!(wallet.lock_coin(&t1, 0, false).is_err());
+    assert!(wallet.is_locked_coin(&t1, 1));
+    assert!(!wallet.is_locked_coin(&t2, 1));
+    assert_eq!(wallet.list_locked_coins().len(), 3);
+    wallet.unlock_coin(&t2, 0).unwrap();
+    assert!(wallet.unlock_coin(&t2, 0).is_err());
+    //重新打开后只保留持久化的锁定
+    let wallet = WalletPool::open(dir).unwrap();
+    assert_eq!(wallet.list_locked_coins(), vec![(t1.clone(), 0)]);
+    assert_eq!(wallet.len(), 0);
+    assert!(AccTestPool::new().lock_coin(&t1, 0, false).is_err());
+}
@@ -397,9 +397,14 @@ impl Block {
         if self.txs[0].ins.len() != 1 {
             return Error::msg("ins count == 0,coinbase miss");
         }
-        if self.txs[0].ins[0].script.get_type()? != SCRIPT_TYPE_CB {
+        let script = &self.txs[0].ins[0].script;
+        if script.get_type()? != SCRIPT_TYPE_CB {
             return Error::msg("ins type error,coinbase miss");
         }
+        //coinbase脚本长度不能超过MAX_SCRIPT_CB_SIZE
+        script.check()?;
+        //coinbase必须包含区块高度
+        script.get_cb_height()?;
         Ok(())
     }
     /// 获取coinbase交易金额
@@ -412,6 +417,15 @@ impl Block {
         }
         self.txs[0].coinbase_fee()
     }
+    /// 检测coinbase中的高度是否为链接的高度
+    /// 保证coinbase交易id的唯一性
+    pub fn check_cb_height(&self, height: u32) -> Result<(), Error> {
+        self.check_coinbase()?;
+        if self.txs[0].ins[0].script.get_cb_height()? != height {
+            return Error::msg("coinbase height error");
+        }
+        Ok(())
+    }
     /// 检测同一个区块内的重复消费
     pub fn check_rep_cost_coin(&self) -> Result<(), Error> {
         let mut map = HashSet::new();
@@ -494,7 +508,7 @@ impl Script {
     /// 为计算id和签名写入相关数据
     pub fn encode_sign(&self, wb: &mut Writer) -> Result<(), Error> {
         match self.get_type()? {
-            SCRIPT_TYPE_CB | SCRIPT_TYPE_OUT => wb.put_bytes(self.bytes()),
+            SCRIPT_TYPE_CB | SCRIPT_TYPE_OUT | SCRIPT_TYPE_DATA => wb.put_bytes(self.bytes()),
             SCRIPT_TYPE_IN => {
                 //写入op类型
                 wb.put_bytes(&[OP_TYPE, SCRIPT_TYPE_IN]);
@@ -530,6 +544,18 @@ impl Script {
         script.check()?;
         Ok(script)
     }
+    /// 获取coinbase脚本中的区块高度
+    pub fn get_cb_height(&self) -> Result<u32, Error> {
+        if self.get_type()? != SCRIPT_TYPE_CB {
+            return Error::msg("ScriptFmtErr");
+        }
+        let mut reader = self.reader();
+        reader.advance(2)?;
+        if reader.u8()? != OP_NUMBER_4 {
+            return Error::msg("coinbase height miss");
+        }
+        reader.u32()
+    }
     /// 根据账号创建标准输入脚本
     pub fn new_script_in(acc: &Account) -> Result<Self, Error> {
         let mut script = Script::from(SCRIPT_TYPE_IN);
@@ -537,6 +563,40 @@ impl Script {
         script.check()?;
         Ok(script)
     }
+    /// 创建账户前带数据的输入脚本,例如htlc原像
+    pub fn new_script_in_with(datas: &[Vec<u8>], acc: &Account) -> Result<Self, Error> {
+        let mut script = Script::from(SCRIPT_TYPE_IN);
+        for data in datas.iter() {
+            script.data(data);
+        }
+        script.put(acc);
+        script.check()?;
+        Ok(script)
+    }
+    /// 获取输入脚本中账户前的数据和账户
+    pub fn get_in_datas(&self) -> Result<(Vec<Vec<u8>>, Account), Error> {
+        self.check()?;
+        let mut r = self.reader();
+        //0 OP_TYPE
+        if r.u8()? != OP_TYPE {
+            return Error::msg("OP_TYPE error");
+        }
+        //1 SCRIPT_TYPE_IN
+        if r.u8()? != SCRIPT_TYPE_IN {
+            return Error::msg("SCRIPT_TYPE_IN error");
+        }
+        //OP_DATA_1 - OP_DATA_4,最后一个为账户
+        let mut datas = vec![];
+        while r.remaining() > 0 {
+            let op = r.u8()?;
+            datas.push(Exector::read_binary(&mut r, op)?);
+        }
+        let acc = match datas.pop() {
+            Some(d) => Account::from_bytes(&d)?,
+            None => return Error::msg("account miss"),
+        };
+        Ok((datas, acc))
+    }
     /// 根据账户hash创建标准输出脚本
     pub fn new_script_out(hasher: &Hasher) -> Result<Self, Error> {
         let mut script = Script::from(SCRIPT_TYPE_OUT);
@@ -548,6 +608,143 @@ impl Script {
         script.check()?;
         Ok(script)
     }
+    /// 解析标准输出脚本中的账户hash,脚本必须和new_script_out一致
+    pub fn get_out_hash(&self) -> Result<Hasher, Error> {
+        let mut r = self.reader();
+        if r.u8()? != OP_TYPE || r.u8()? != SCRIPT_TYPE_OUT {
+            return Error::msg("SCRIPT_TYPE_OUT error");
+        }
+        if r.u8()? != OP_VERIFY_INOUT || r.u8()? != OP_HASHER {
+            return Error::msg("out script error");
+        }
+        let op = r.u8()?;
+        let hv = Hasher::from_bytes(&Exector::read_binary(&mut r, op)?)?;
+        if r.u8()? != OP_EQUAL_VERIFY || r.u8()? != OP_CHECKSIG_VERIFY || r.remaining() != 0 {
+            return Error::msg("out script error");
+        }
+        Ok(hv)
+    }
+    /// 创建数据输出脚本
+    pub fn new_script_data(data: &[u8]) -> Result<Self, Error> {
+        if data.is_empty() {
+            return Error::msg("data empty");
+        }
+        let mut script = Script::from(SCRIPT_TYPE_DATA);
+        script.data(data);
+        script.check()?;
+        Ok(script)
+    }
+    /// 是否是数据输出脚本
+    pub fn is_data(&self) -> bool {
+        self.get_type() == Ok(SCRIPT_TYPE_DATA)
+    }
+    /// 获取数据输出脚本中的数据
+    pub fn get_data(&self) -> Result<Vec<u8>, Error> {
+        let mut r = self.reader();
+        if r.u8()? != OP_TYPE || r.u8()? != SCRIPT_TYPE_DATA {
+            return Error::msg("SCRIPT_TYPE_DATA error");
+        }
+        let op = r.u8()?;
+        let data = Exector::read_binary(&mut r, op)?;
+        if r.remaining() != 0 {
+            return Error::msg("data script error");
+        }
+        Ok(data)
+    }
+    /// 根据赎回脚本hash创建脚本hash输出脚本
+    pub fn new_script_sh(hasher: &Hasher) -> Result<Self, Error> {
+        let mut script = Script::from(SCRIPT_TYPE_OUT);
+        script.op(OP_VERIFY_INOUT);
+        script.put(hasher);
+        script.op(OP_CHECKSCRIPT_VERIFY);
+        script.check()?;
+        Ok(script)
+    }
+    /// 解析脚本hash输出脚本中的赎回脚本hash
+    pub fn get_sh(&self) -> Result<Hasher, Error> {
+        let mut r = self.reader();
+        if r.u8()? != OP_TYPE || r.u8()? != SCRIPT_TYPE_OUT {
+            return Error::msg("SCRIPT_TYPE_OUT error");
+        }
+        if r.u8()? != OP_VERIFY_INOUT {
+            return Error::msg("OP_VERIFY_INOUT error");
+        }
+        let op = r.u8()?;
+        let hv = Hasher::from_bytes(&Exector::read_binary(&mut r, op)?)?;
+        if r.u8()? != OP_CHECKSCRIPT_VERIFY || r.remaining() != 0 {
+            return Error::msg("sh script error");
+        }
+        Ok(hv)
+    }
+    /// 赎回脚本对应的脚本hash地址
+    pub fn get_sh_address(&self) -> Result<String, Error> {
+        if self.get_type()? != SCRIPT_TYPE_OUT {
+            return Error::msg("redeem script type error");
+        }
+        self.check()?;
+        Account::encode_with_hasher(consts::SH_HRP, &Hasher::hash(self.bytes()))
+    }
+    /// 创建哈希时间锁输出脚本
+    pub fn new_script_htlc(htlc: &Htlc) -> Result<Self, Error> {
+        let mut script = Script::from(SCRIPT_TYPE_OUT);
+        script.op(OP_VERIFY_INOUT);
+        script.put(&htlc.hashlock);
+        script.put(&htlc.recipient);
+        script.put(&htlc.refund);
+        //锁定时间可能>=2^31,使用8字节数字避免被读成负数
+        script.i64(htlc.lock as i64);
+        script.op(OP_CHECKHTLC_VERIFY);
+        script.op(OP_CHECKSIG_VERIFY);
+        script.check()?;
+        Ok(script)
+    }
+    /// 解析哈希时间锁输出脚本
+    pub fn get_htlc(&self) -> Result<Htlc, Error> {
+        let mut r = self.reader();
+        if r.u8()? != OP_TYPE || r.u8()? != SCRIPT_TYPE_OUT {
+            return Error::msg("SCRIPT_TYPE_OUT error");
+        }
+        if r.u8()? != OP_VERIFY_INOUT {
+            return Error::msg("OP_VERIFY_INOUT error");
+        }
+        let mut hvs = vec![];
+        for _ in 0..3 {
+            let op = r.u8()?;
+            hvs.push(Hasher::from_bytes(&Exector::read_binary(&mut r, op)?)?);
+        }
+        if r.u8()? != OP_NUMBER_8 {
+            return Error::msg("OP_NUMBER_8 error");
+        }
+        let lock: u32 = r.i64()?.try_into().map_or_else(Error::std, Ok)?;
+        if r.u8()? != OP_CHECKHTLC_VERIFY || r.u8()? != OP_CHECKSIG_VERIFY {
+            return Error::msg("htlc script error");
+        }
+        if r.remaining() != 0 {
+            return Error::msg("htlc script error");
+        }
+        Ok(Htlc {
+            hashlock: hvs[0].clone(),
+            recipient: hvs[1].clone(),
+            refund: hvs[2].clone(),
+            lock,
+        })
+    }
+}
+
+/// 哈希时间锁合约参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct Htlc {
+    pub hashlock: Hasher,  //sha256(原像)
+    pub recipient: Hasher, //提供原像可消费的账户地址
+    pub refund: Hasher,    //锁定时间后可退款的账户地址
+    pub lock: u32,         //退款锁定时间,规则同Tx::lock
+}
+
+impl Htlc {
+    /// 原像对应的hashlock
+    pub fn hashlock(preimage: &[u8]) -> Hasher {
+        Hasher::sum(preimage)
+    }
 }
 
 ///交易
@@ -559,16 +756,20 @@ pub struct Tx {
     pub ins: Vec<TxIn>,
     ///输出列表
     pub outs: Vec<TxOut>,
+    ///锁定时间,版本>=TX_VER_LOCK时有效
+    ///小于LOCKTIME_THRESHOLD为区块高度,否则为unix时间戳,0不锁定
+    pub lock: u32,
 }
 
 impl fmt::Display for Tx {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(
             f,
-            "## ver={} ,ins len={} ,outs len={}",
+            "## ver={} ,ins len={} ,outs len={} ,lock={}",
             self.ver,
             self.ins.len(),
-            self.outs.len()
+            self.outs.len(),
+            self.lock
         )?;
         for (i, inv) in self.ins.iter().enumerate() {
             writeln!(f, "#{} in={}", i, inv.string().unwrap())?;
@@ -677,8 +878,34 @@ impl Tx {
         for out in self.outs.iter() {
             out.encode_sign(wb)?;
         }
+        if self.has_lock() {
+            wb.u32(self.lock);
+        }
         Ok(())
     }
+    /// 交易版本是否支持锁定时间和相对锁定
+    pub fn has_lock(&self) -> bool {
+        self.ver >= consts::TX_VER_LOCK
+    }
+    /// 锁定时间是否为区块高度
+    pub fn is_lock_height(&self) -> bool {
+        self.lock < consts::LOCKTIME_THRESHOLD
+    }
+    /// 检测锁定时间在height高度和mtp中位时间下是否已经解除
+    /// 所有输入的序列号都为SEQUENCE_FINAL时锁定时间不生效
+    pub fn is_final(&self, height: u32, mtp: i64) -> bool {
+        if !self.has_lock() || self.lock == 0 {
+            return true;
+        }
+        if self.ins.iter().all(|v| v.seq == consts::SEQUENCE_FINAL) {
+            return true;
+        }
+        if self.is_lock_height() {
+            self.lock <= height
+        } else {
+            self.lock as i64 <= mtp
+        }
+    }
     // 获取交易id
     pub fn id(&self) -> Result<Hasher, Error> {
         let mut wb = Writer::default();
@@ -731,6 +958,49 @@ fn test_tx_hash() {
     tx.id().unwrap();
 }
 
+#[test]
+fn test_tx_lock_serializer() {
+    let acc = Account::new(1, 1, false, true).unwrap();
+    let mut tx = Tx::default();
+    tx.ver = consts::TX_VER_LOCK;
+    tx.lock = 100;
+    let mut inv = TxIn::default();
+    inv.out = Hasher::hash(&[1]);
+    inv.script = Script::new_script_in(&acc).unwrap();
+    inv.seq = consts::SEQUENCE_TYPE_FLAG | 2;
+    tx.ins.push(inv);
+    let mut out = TxOut::default();
+    out.value = 1;
+    out.script = Script::new_script_out(&acc.hash().unwrap()).unwrap();
+    tx.outs.push(out);
+    let mut wb = Writer::default();
+    wb.encode(&tx);
+    let tx2: Tx = wb.reader().decode().unwrap();
+    assert_eq!(tx, tx2);
+    //锁定时间参与交易id计算
+    let id = tx.id().unwrap();
+    tx.lock = 101;
+    assert_ne!(id, tx.id().unwrap());
+    assert!(!tx.is_final(100, 0));
+    assert!(tx.is_final(101, 0));
+    assert_eq!(tx.ins[0].get_seq_lock(), Some((true, 1024)));
+    tx.ins[0].seq = consts::SEQUENCE_DISABLE_FLAG;
+    assert_eq!(tx.ins[0].get_seq_lock(), None);
+    //所有输入序列号为SEQUENCE_FINAL时锁定时间不生效
+    tx.ins[0].seq = consts::SEQUENCE_FINAL;
+    assert!(tx.is_final(100, 0));
+    assert_eq!(tx.ins[0].get_seq_lock(), None);
+    tx.ins.push(tx.ins[0].clone());
+    tx.ins[1].seq = 0;
+    assert!(!tx.is_final(100, 0));
+    tx.ins.pop();
+    tx.ins[0].seq = consts::SEQUENCE_DISABLE_FLAG;
+    //时间锁定
+    tx.lock = consts::LOCKTIME_THRESHOLD + 10;
+    assert!(!tx.is_final(1000, (consts::LOCKTIME_THRESHOLD + 9) as i64));
+    assert!(tx.is_final(1000, (consts::LOCKTIME_THRESHOLD + 10) as i64));
+}
+
 impl Serializer for Tx {
     fn encode(&self, wb: &mut Writer) {
         wb.u32(self.ver);
@@ -742,6 +1012,9 @@ impl Serializer for Tx {
         for out in self.outs.iter() {
             out.encode(wb);
         }
+        if self.has_lock() {
+            wb.u32(self.lock);
+        }
     }
     fn decode(r: &mut Reader) -> Result<Tx, Error> {
         let mut v = Tx::default();
@@ -754,13 +1027,19 @@ impl Serializer for Tx {
             let iv: TxOut = r.decode()?;
             v.outs.push(iv);
         }
+        if v.has_lock() {
+            v.lock = r.u32()?;
+        }
         Ok(v)
     }
 }
 
 impl PartialEq for Tx {
     fn eq(&self, other: &Self) -> bool {
-        self.ver == other.ver && self.ins == other.ins && self.outs == other.outs
+        self.ver == other.ver
+            && self.ins == other.ins
+            && self.outs == other.outs
+            && self.lock == other.lock
     }
 }
 
@@ -770,6 +1049,7 @@ impl Default for Tx {
             ver: 1,
             ins: vec![],
             outs: vec![],
+            lock: 0,
         }
     }
 }
@@ -780,6 +1060,7 @@ impl Clone for Tx {
             ver: self.ver,
             ins: self.ins.clone(),
             outs: self.outs.clone(),
+            lock: self.lock,
         }
     }
 }
@@ -793,7 +1074,8 @@ pub struct TxIn {
     pub idx: u16,
     ///输入脚本
     pub script: Script,
-    ///序列号
+    ///序列号,交易版本>=TX_VER_LOCK时为相对锁定
+    ///最高位为1不启用,SEQUENCE_TYPE_FLAG为1按时间锁定否则按高度锁定
     pub seq: u32,
 }
 
@@ -831,21 +1113,9 @@ impl HasAddress for TxIn {
     fn get_address(&self) -> Result<Hasher, Error> {
         self.script.check()?;
         let typ = self.script.get_type()?;
-        //暂时只支持 SCRIPT_TYPE_IN 类型
+        //暂时只支持 SCRIPT_TYPE_IN 类型,地址为最后一个数据中的账户
         if typ == SCRIPT_TYPE_IN {
-            let mut r = self.script.reader();
-            //0 OP_TYPE
-            if r.u8()? != OP_TYPE {
-                return Error::msg("OP_TYPE error");
-            }
-            //1 SCRIPT_TYPE_IN
-            if r.u8()? != SCRIPT_TYPE_IN {
-                return Error::msg("SCRIPT_TYPE_OUT error");
-            }
-            //3 OP_DATA_1 - OP_DATA_4
-            let op = r.u8()?;
-            let d = Exector::read_binary(&mut r, op)?;
-            let acc = Account::from_bytes(&d)?;
+            let (_, acc) = self.script.get_in_datas()?;
             return acc.get_address();
         }
         Error::msg("script type error,not SCRIPT_TYPE_IN")
@@ -884,6 +1154,19 @@ impl TxIn {
         }
         Ok(self.get_tx_out(ctx)?.value)
     }
+    /// 获取相对锁定 (是否按时间锁定,锁定值)
+    /// 按时间锁定时锁定值单位为秒,未启用返回None
+    pub fn get_seq_lock(&self) -> Option<(bool, u32)> {
+        if self.seq & consts::SEQUENCE_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = self.seq & consts::SEQUENCE_MASK;
+        if self.seq & consts::SEQUENCE_TYPE_FLAG != 0 {
+            Some((true, value << consts::SEQUENCE_GRANULARITY))
+        } else {
+            Some((false, value))
+        }
+    }
     /// 是否是coinbase输入
     pub fn is_coinbase(&self) -> bool {
         self.out == Hasher::zero()
@@ -966,6 +1249,14 @@ impl Checker for TxOut {
         if !consts::is_valid_amount(self.value) {
             return Error::msg("InvalidAmount");
         }
+        //数据输出金额必须为0,不解析地址
+        if self.is_data() {
+            if self.value != 0 {
+                return Error::msg("InvalidAmount");
+            }
+            self.script.check()?;
+            return self.script.get_data().map(|_| ());
+        }
         //检测脚本类型
         if self.script.get_type()? != SCRIPT_TYPE_OUT {
             return Error::msg("ScriptFmtErr");
@@ -980,6 +1271,16 @@ impl HasAddress for TxOut {
     fn get_address(&self) -> Result<Hasher, Error> {
         self.script.check()?;
         let typ = self.script.get_type()?;
+        //哈希时间锁输出使用脚本hash作为地址
+        if typ == SCRIPT_TYPE_OUT && self.script.get_htlc().is_ok() {
+            return Ok(Hasher::hash(self.script.bytes()));
+        }
+        //脚本hash输出使用赎回脚本hash作为地址
+        if typ == SCRIPT_TYPE_OUT {
+            if let Ok(hv) = self.script.get_sh() {
+                return Ok(hv);
+            }
+        }
         //暂时只支持 SCRIPT_TYPE_OUT 类型
         if typ == SCRIPT_TYPE_OUT {
             let mut r = self.script.reader();
@@ -1013,6 +1314,10 @@ impl HasAddress for TxOut {
 }
 
 impl TxOut {
+    /// 是否是数据输出,数据输出不可消费
+    pub fn is_data(&self) -> bool {
+        self.script.is_data()
+    }
     /// 签名用编码数据
     pub fn encode_sign(&self, wb: &mut Writer) -> Result<(), Error> {
         wb.i64(self.value);
@@ -1070,6 +1375,22 @@ impl Serializer for TxOut {
     }
 }
 
+#[test]
+fn test_txout_data() {
+    let data = Hasher::hash("document".as_bytes());
+    let mut out = TxOut::default();
+    out.script = Script::new_script_data(data.as_bytes()).unwrap();
+    assert!(out.is_data());
+    assert_eq!(out.script.get_data().unwrap(), data.as_bytes());
+    assert!(out.get_address().is_err());
+    assert!(Script::new_script_data(&[]).is_err());
+    assert!(Script::new_script_data(&[0u8; MAX_SCRIPT_DATA_SIZE]).is_err());
+    //多余的数据
+    let mut script = Script::new_script_data(data.as_bytes()).unwrap();
+    script.op(OP_TRUE);
+    assert!(script.get_data().is_err());
+}
+
 #[test]
 fn test_block() {
     let mut s = Script::new(32);
@@ -1,12 +1,19 @@
-use crate::account::HasAddress;
-use crate::accpool::AccTestPool;
+use crate::account::{AccountPool, HasAddress};
+use crate::accpool::WalletPool;
 use crate::errors::Error;
 use crate::hasher::Hasher;
 use crate::index::Chain;
+use crate::keystore::KdfParams;
+use crate::policy::Policy;
+use crate::util;
 use std::convert::TryInto;
+use std::env;
 use std::sync::Arc;
 use tempdir::TempDir;
 
+/// 发布配置读取钱包密码的环境变量
+pub const WALLET_PASSWORD_ENV: &str = "BTX_WALLET_PASSWORD";
+
 #[derive(Clone, Debug)]
 pub struct Config {
     /// 数据文件目录
@@ -25,6 +32,8 @@ pub struct Config {
     pub ver: u16,
     ///发布订阅线程数量
     pub pbnum: usize,
+    /// 交易池接收策略
+    pub policy: Policy,
 }
 
 impl Config {
@@ -36,8 +45,20 @@ impl Config {
     {
         let tmp = TempDir::new("btx").unwrap();
         let dir = tmp.path().to_str().unwrap();
-        //创建本地账号池
-        let accpool = AccTestPool::new();
+        //创建本地钱包,包含3个测试账户,测试使用低强度的kdf参数
+        let params = KdfParams {
+            log_n: 10,
+            r: 8,
+            p: 1,
+        };
+        let accpool = WalletPool::open_with_params(&format!("{}/wallet", dir), params).unwrap();
+        let accpool = Arc::new(accpool);
+        accpool.set_password("test").unwrap();
+        for i in 0..3 {
+            accpool
+                .create(1, 1, false, &format!("test{}", i), 0)
+                .unwrap();
+        }
         //2号账户用来存放区块奖励
         let acc = accpool.value(2).unwrap();
         let addr = acc.string().unwrap();
@@ -53,6 +74,7 @@ impl Config {
                 pow_span: 2016,
                 halving: 210000,
                 pbnum: 4,
+                policy: Policy::default(),
             },
             accpool,
         )
@@ -67,7 +89,17 @@ impl Config {
         tf(&idx.config().unwrap(), idx).unwrap();
     }
     /// 发布配置
+    /// 钱包密码从环境变量WALLET_PASSWORD_ENV读取,未设置时钱包保持锁定
     pub fn release<F>(tf: F) -> Result<(), Error>
+    where
+        F: FnOnce(Arc<Chain>) -> Result<(), Error>,
+    {
+        let password = env::var(WALLET_PASSWORD_ENV).ok();
+        Config::release_with_password(password.as_deref(), tf)
+    }
+    /// 发布配置,password不为None时解锁钱包,钱包密码需要先使用set_password设置
+    /// 钱包锁定时只能使用只读账户,不能签名和创建导入完整账户
+    pub fn release_with_password<F>(password: Option<&str>, tf: F) -> Result<(), Error>
     where
         F: FnOnce(Arc<Chain>) -> Result<(), Error>,
     {
@@ -84,10 +116,15 @@ impl Config {
             pow_span: 2016,
             halving: 210000,
             pbnum: 4,
+            policy: Policy::default(),
         };
-        //暂时用测试的
-        let accpool = AccTestPool::new();
-        tf(Chain::new(&conf, accpool)?)
+        //加载钱包账户
+        util::miss_create_dir(&conf.dir)?;
+        let wallet = WalletPool::open(&format!("{}/wallet", conf.dir))?;
+        if let Some(password) = password {
+            wallet.unlock(password)?;
+        }
+        tf(Chain::new(&conf, Arc::new(wallet))?)
     }
 }
 
@@ -4,6 +4,8 @@ pub const PK_HRP: &str = "pk";
 pub const ACC_HRP: &str = "aps";
 /// 地址前缀
 pub const ADDR_HRP: &str = "btx";
+/// 脚本hash地址前缀
+pub const SH_HRP: &str = "bsh";
 /// 一个coin的缩放比例
 pub const COIN: i64 = 1000000;
 /// 获取coin
@@ -14,6 +16,10 @@ pub fn coin(c: usize) -> i64 {
 pub const MAX_MONEY: i64 = 21000000 * COIN;
 /// 账户最大密钥数
 pub const MAX_ACCOUNT_KEY_SIZE: u8 = 16;
+/// DER编码签名最大长度
+pub const MAX_SIG_SIZE: usize = 72;
+/// 分层确定性密钥BIP44路径中的币种
+pub const HD_COIN_TYPE: u32 = 0;
 /// 检测金额是否在正常的范围内
 pub fn is_valid_amount(v: i64) -> bool {
     v >= 0 && v <= MAX_MONEY
@@ -26,3 +32,22 @@ pub const MAX_TX_COUNT: u16 = 0xFFFF;
 pub const COINBASE_MATURITY: u32 = 100;
 /// 区块最大大小
 pub const MAX_BLOCK_SIZE: usize = 1024 * 1024 * 4;
+
+/// 支持锁定时间的交易版本
+pub const TX_VER_LOCK: u32 = 2;
+/// 支持Schnorr签名账户的交易版本,同时支持锁定时间
+pub const TX_VER_SCHNORR: u32 = 3;
+/// 锁定时间小于此值为区块高度,否则为unix时间戳
+pub const LOCKTIME_THRESHOLD: u32 = 500000000;
+/// 计算中位时间使用的区块数量
+pub const MEDIAN_TIME_SPAN: u32 = 11;
+/// 所有输入的序列号都为此值时不检测交易锁定时间,同时不启用相对锁定
+pub const SEQUENCE_FINAL: u32 = 0xFFFFFFFF;
+/// 序列号最高位为1时不启用相对锁定
+pub const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+/// 序列号此位为1时为相对时间锁定,否则为相对高度锁定
+pub const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+/// 序列号中相对锁定值
+pub const SEQUENCE_MASK: u32 = 0x0000FFFF;
+/// 相对时间锁定的单位 2^9 = 512秒
+pub const SEQUENCE_GRANULARITY: u32 = 9;
@@ -3,46 +3,121 @@ use crate::consts::PK_HRP;
 use crate::errors;
 use crate::hasher::Hasher;
 use core::{fmt, str};
+use hex::FromHex;
+use k256::elliptic_curve::bigint::U256;
+use k256::elliptic_curve::group::Group;
+use k256::elliptic_curve::ops::{LinearCombinationExt, Reduce};
+use k256::elliptic_curve::PrimeField;
+use k256::{schnorr::VerifyingKey, FieldBytes, ProjectivePoint, Scalar};
+use lru::LruCache;
 use secp256k1::rand::rngs::OsRng;
-use secp256k1::{Error, Message, PublicKey, Secp256k1, SecretKey, SignOnly, Signature, VerifyOnly};
+use secp256k1::schnorrsig;
+use secp256k1::{
+    All, Error, Message, PublicKey, Secp256k1, SecretKey, SignOnly, Signature, VerifyOnly,
+};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 lazy_static! {
     static ref VO: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
     static ref SO: Secp256k1<SignOnly> = Secp256k1::signing_only();
+    //当前库的schnorr验签需要完整上下文
+    static ref SV: Secp256k1<All> = Secp256k1::new();
 }
 
-//验证
-fn secp256k1_verify(msg: &[u8], sig: &SigValue, pubkey: &PublicKey) -> Result<bool, Error> {
+/// Schnorr签名长度
+pub const SCHNORR_SIG_SIZE: usize = 64;
+/// x-only公钥长度
+pub const XONLY_PUBKEY_SIZE: usize = 32;
+
+/// 签名算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigScheme {
+    /// DER编码的ECDSA签名,33字节压缩公钥
+    Ecdsa,
+    /// BIP340 Schnorr签名,32字节x-only公钥
+    Schnorr,
+}
+
+//签名消息,签名前进行一次Hasher::hash
+fn sign_message(msg: &[u8]) -> Result<Message, Error> {
     let uv = Hasher::hash(msg);
-    let msg = Message::from_slice(uv.as_bytes())?;
-    Ok(VO.verify(&msg, &sig.inner, &pubkey).is_ok())
+    Message::from_slice(uv.as_bytes())
+}
+
+//验证
+fn secp256k1_verify(msg: &[u8], sig: &SigValue, pubkey: &KeyInner) -> Result<bool, Error> {
+    Ok(verify_message(&sign_message(msg)?, sig, pubkey))
+}
+
+//验证已hash的消息
+fn verify_message(msg: &Message, sig: &SigValue, pubkey: &KeyInner) -> bool {
+    match (pubkey, &sig.inner) {
+        (KeyInner::Ecdsa(pk), SigInner::Ecdsa(sig)) => VO.verify(msg, sig, pk).is_ok(),
+        (KeyInner::Schnorr(pk), SigInner::Schnorr(sig)) => {
+            SV.schnorrsig_verify(sig, msg, pk).is_ok()
+        }
+        //签名算法和公钥类型不一致
+        _ => false,
+    }
 }
 
 //签名
 fn secp256k1_sign(msg: &[u8], seckey: &SecretKey) -> Result<Signature, Error> {
-    let uv = Hasher::hash(msg);
-    let msg = Message::from_slice(uv.as_bytes())?;
-    Ok(SO.sign(&msg, seckey))
+    Ok(SO.sign(&sign_message(msg)?, seckey))
+}
+
+//Schnorr签名
+fn schnorr_sign(msg: &[u8], seckey: &SecretKey) -> Result<schnorrsig::Signature, Error> {
+    let keypair = schnorrsig::KeyPair::from_secret_key(&SO, *seckey);
+    Ok(SO.schnorrsig_sign(&sign_message(msg)?, &keypair))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SigInner {
+    Ecdsa(Signature),
+    Schnorr(schnorrsig::Signature),
 }
 
 ///签名结果
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SigValue {
-    inner: Signature,
+    inner: SigInner,
 }
 
-impl Clone for SigValue {
-    fn clone(&self) -> Self {
-        SigValue { inner: self.inner }
+impl SigValue {
+    /// 签名算法
+    pub fn scheme(&self) -> SigScheme {
+        match self.inner {
+            SigInner::Ecdsa(_) => SigScheme::Ecdsa,
+            SigInner::Schnorr(_) => SigScheme::Schnorr,
+        }
+    }
+    /// 从DER编码的ECDSA签名数据创建
+    pub fn with_bytes(bb: &[u8]) -> Result<SigValue, errors::Error> {
+        SigValue::with_scheme(SigScheme::Ecdsa, bb)
+    }
+    /// 按签名算法解析签名数据,算法由对应的公钥类型决定,不从数据长度推断
+    pub fn with_scheme(scheme: SigScheme, bb: &[u8]) -> Result<SigValue, errors::Error> {
+        let inner = match scheme {
+            SigScheme::Ecdsa => Signature::from_der(bb).map(SigInner::Ecdsa),
+            SigScheme::Schnorr if bb.len() != SCHNORR_SIG_SIZE => {
+                return errors::Error::msg("schnorr sign size error");
+            }
+            SigScheme::Schnorr => schnorrsig::Signature::from_slice(bb).map(SigInner::Schnorr),
+        };
+        inner.map_or_else(errors::Error::std, |v| Ok(SigValue { inner: v }))
     }
 }
 
+/// DER编码的ECDSA签名,Schnorr签名使用SigValue::with_scheme解析
 impl str::FromStr for SigValue {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match Signature::from_str(s) {
-            Ok(inner) => Ok(SigValue { inner: inner }),
-            _ => Err(Error::InvalidSecretKey),
+        match Vec::<u8>::from_hex(s) {
+            Ok(ref bb) => SigValue::with_bytes(bb).map_err(|_| Error::InvalidSignature),
+            _ => Err(Error::InvalidSignature),
         }
     }
 }
@@ -55,60 +130,62 @@ impl fmt::Display for SigValue {
 
 impl fmt::LowerHex for SigValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let sig = self.inner.serialize_der();
-        for v in sig.iter() {
+        for v in self.into_bytes().iter() {
             write!(f, "{:02x}", v)?;
         }
         Ok(())
     }
 }
 
+/// DER编码的ECDSA签名,Schnorr签名使用SigValue::with_scheme解析
 impl FromBytes for SigValue {
     fn from_bytes(bb: &Vec<u8>) -> Result<Self, errors::Error> {
-        let inner = Signature::from_der(&bb).unwrap();
-        Ok(SigValue { inner: inner })
+        SigValue::with_bytes(bb)
     }
 }
 
 impl IntoBytes for SigValue {
     fn into_bytes(&self) -> Vec<u8> {
-        let sig = self.inner.serialize_der();
-        sig.as_ref().to_vec()
+        match self.inner {
+            SigInner::Ecdsa(ref sig) => sig.serialize_der().as_ref().to_vec(),
+            SigInner::Schnorr(ref sig) => sig[..].to_vec(),
+        }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub struct PubKey {
-    inner: PublicKey,
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum KeyInner {
+    Ecdsa(PublicKey),
+    Schnorr(schnorrsig::PublicKey),
 }
 
-impl Clone for PubKey {
-    fn clone(&self) -> Self {
-        PubKey { inner: self.inner }
-    }
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PubKey {
+    inner: KeyInner,
 }
 
 impl str::FromStr for PubKey {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match PublicKey::from_str(s) {
-            Ok(inner) => Ok(PubKey { inner: inner }),
-            _ => Err(Error::InvalidSecretKey),
+        match Vec::<u8>::from_hex(s) {
+            Ok(ref bb) => PubKey::with_bytes(bb).map_err(|_| Error::InvalidPublicKey),
+            _ => Err(Error::InvalidPublicKey),
         }
     }
 }
 
 impl FromBytes for PubKey {
     fn from_bytes(bb: &Vec<u8>) -> Result<Self, errors::Error> {
-        let inner = PublicKey::from_slice(&bb).unwrap();
-        Ok(PubKey { inner: inner })
+        PubKey::with_bytes(bb)
     }
 }
 
 impl IntoBytes for PubKey {
     fn into_bytes(&self) -> Vec<u8> {
-        let sig = self.inner.serialize();
-        sig.to_vec()
+        match self.inner {
+            KeyInner::Ecdsa(ref pk) => pk.serialize().to_vec(),
+            KeyInner::Schnorr(ref pk) => pk.serialize().to_vec(),
+        }
     }
 }
 
@@ -120,7 +197,10 @@ impl fmt::Display for PubKey {
 
 impl fmt::LowerHex for PubKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.inner.fmt(f)
+        match self.inner {
+            KeyInner::Ecdsa(ref pk) => fmt::LowerHex::fmt(pk, f),
+            KeyInner::Schnorr(ref pk) => fmt::LowerHex::fmt(pk, f),
+        }
     }
 }
 
@@ -131,6 +211,13 @@ impl PubKey {
     pub fn verify(&self, msg: &[u8], sig: &SigValue) -> Result<bool, Error> {
         secp256k1_verify(msg, sig, &self.inner)
     }
+    /// 公钥对应的签名算法
+    pub fn scheme(&self) -> SigScheme {
+        match self.inner {
+            KeyInner::Ecdsa(_) => SigScheme::Ecdsa,
+            KeyInner::Schnorr(_) => SigScheme::Schnorr,
+        }
+    }
     /// 编码公钥
     /// pk 开头的为公钥id
     pub fn encode(&self) -> Result<String, bech32::Error> {
@@ -153,8 +240,31 @@ pub fn bech32_decode(s: &str) -> Result<(String, Vec<u8>), bech32::Error> {
 impl PubKey {
     /// 将公钥再次hash用来生成账户地址
     pub fn hash(&self) -> Hasher {
-        let v = self.inner.serialize();
-        Hasher::hash(&v[..])
+        Hasher::hash(&self.into_bytes())
+    }
+    /// 从公钥数据创建公钥,33字节为压缩公钥,32字节为x-only公钥
+    pub fn with_bytes(bb: &[u8]) -> Result<PubKey, errors::Error> {
+        let inner = if bb.len() == XONLY_PUBKEY_SIZE {
+            schnorrsig::PublicKey::from_slice(bb).map(KeyInner::Schnorr)
+        } else {
+            PublicKey::from_slice(bb).map(KeyInner::Ecdsa)
+        };
+        inner.map_or_else(errors::Error::std, |v| Ok(PubKey { inner: v }))
+    }
+    /// 公钥加上tweak*G,用于密钥推导,只支持压缩公钥
+    pub fn add_tweak(&self, tweak: &[u8]) -> Result<PubKey, errors::Error> {
+        match self.inner {
+            KeyInner::Ecdsa(mut inner) => {
+                inner
+                    .add_exp_assign(&VO, tweak)
+                    .map_or_else(errors::Error::std, |_| {
+                        Ok(PubKey {
+                            inner: KeyInner::Ecdsa(inner),
+                        })
+                    })
+            }
+            KeyInner::Schnorr(_) => errors::Error::msg("xonly pubkey not support tweak"),
+        }
     }
 }
 
@@ -173,22 +283,63 @@ impl PriKey {
     ///签名指定的数据
     pub fn sign(&self, msg: &[u8]) -> Result<SigValue, Error> {
         match secp256k1_sign(msg, &self.inner) {
-            Ok(sig) => Ok(SigValue { inner: sig }),
+            Ok(sig) => Ok(SigValue {
+                inner: SigInner::Ecdsa(sig),
+            }),
             Err(err) => Err(err),
         }
     }
+    ///使用Schnorr签名指定的数据
+    pub fn sign_schnorr(&self, msg: &[u8]) -> Result<SigValue, Error> {
+        schnorr_sign(msg, &self.inner).map(|sig| SigValue {
+            inner: SigInner::Schnorr(sig),
+        })
+    }
+    ///按签名算法签名
+    pub fn sign_with(&self, scheme: SigScheme, msg: &[u8]) -> Result<SigValue, Error> {
+        match scheme {
+            SigScheme::Ecdsa => self.sign(msg),
+            SigScheme::Schnorr => self.sign_schnorr(msg),
+        }
+    }
+    /// 私钥加上tweak(mod n),用于密钥推导
+    pub fn add_tweak(&self, tweak: &[u8]) -> Result<PriKey, errors::Error> {
+        let mut inner = self.inner;
+        inner
+            .add_assign(tweak)
+            .map_or_else(errors::Error::std, |_| Ok(PriKey { inner }))
+    }
+    /// 从32字节数据创建私钥
+    pub fn with_bytes(bb: &[u8]) -> Result<PriKey, errors::Error> {
+        SecretKey::from_slice(bb).map_or_else(errors::Error::std, |v| Ok(PriKey { inner: v }))
+    }
     //推导对应的公钥
     pub fn pubkey(&self) -> PubKey {
         let ctx = Secp256k1::new();
         let inner = PublicKey::from_secret_key(&ctx, &self.inner);
-        PubKey { inner: inner }
+        PubKey {
+            inner: KeyInner::Ecdsa(inner),
+        }
+    }
+    //推导对应的x-only公钥
+    pub fn xonly_pubkey(&self) -> PubKey {
+        let keypair = schnorrsig::KeyPair::from_secret_key(&SO, self.inner);
+        PubKey {
+            inner: KeyInner::Schnorr(schnorrsig::PublicKey::from_keypair(&SO, &keypair)),
+        }
+    }
+    //按签名算法推导公钥
+    pub fn pubkey_with(&self, scheme: SigScheme) -> PubKey {
+        match scheme {
+            SigScheme::Ecdsa => self.pubkey(),
+            SigScheme::Schnorr => self.xonly_pubkey(),
+        }
     }
 }
 
 impl FromBytes for PriKey {
     fn from_bytes(bb: &Vec<u8>) -> Result<Self, errors::Error> {
-        let inner = SecretKey::from_slice(&bb).unwrap();
-        Ok(PriKey { inner: inner })
+        PriKey::with_bytes(bb)
     }
 }
 
@@ -235,6 +386,197 @@ impl PriKey {
     }
 }
 
+/// 签名验证缓存,交易进入交易池和区块链接时共享
+/// 只缓存验证通过的(消息hash,公钥,签名),脚本规则每次仍然执行,规则变化不影响缓存结果
+pub struct SigCache {
+    lru: Mutex<LruCache<Hasher, ()>>,
+    hits: AtomicUsize,
+}
+
+impl Default for SigCache {
+    fn default() -> Self {
+        SigCache::new(1024 * 64)
+    }
+}
+
+impl SigCache {
+    /// 创建指定大小的缓存
+    pub fn new(cap: usize) -> Self {
+        SigCache {
+            lru: Mutex::new(LruCache::new(cap)),
+            hits: AtomicUsize::new(0),
+        }
+    }
+    //缓存key
+    fn key(msg: &Message, pk: &[u8], sig: &[u8]) -> Hasher {
+        let mut buf = msg[..].to_vec();
+        buf.extend_from_slice(pk);
+        buf.extend_from_slice(sig);
+        Hasher::hash(&buf)
+    }
+    fn contains(&self, key: &Hasher) -> bool {
+        let hit = match self.lru.lock() {
+            Ok(mut lru) => lru.get(key).is_some(),
+            Err(_) => false,
+        };
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+    //是否已缓存,不计入命中次数
+    fn peek(&self, key: &Hasher) -> bool {
+        self.lru.lock().is_ok_and(|lru| lru.contains(key))
+    }
+    fn insert(&self, key: Hasher) {
+        if let Ok(mut lru) = self.lru.lock() {
+            lru.put(key, ());
+        }
+    }
+    /// 验签,已经验证通过的签名直接返回
+    pub fn verify(&self, msg: &[u8], pk: &PubKey, sig: &SigValue) -> bool {
+        let msg = match sign_message(msg) {
+            Ok(msg) => msg,
+            Err(_) => return false,
+        };
+        let key = SigCache::key(&msg, &pk.into_bytes(), &sig.into_bytes());
+        if self.contains(&key) {
+            return true;
+        }
+        let ok = verify_message(&msg, sig, &pk.inner);
+        if ok {
+            self.insert(key);
+        }
+        ok
+    }
+    /// 缓存的签名数量
+    pub fn len(&self) -> usize {
+        self.lru.lock().map_or(0, |v| v.len())
+    }
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// 命中次数
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+    /// 清空缓存
+    pub fn clear(&self) {
+        if let Ok(mut lru) = self.lru.lock() {
+            lru.clear();
+        }
+    }
+}
+
+/// BIP340 Schnorr批量验签
+/// 所有签名合并为一次多标量乘法: (Σa·s)G = Σa·R + Σ(a·e)P,任意一个签名错误整批失败
+/// 批量失败时需要逐个验签定位错误的签名
+#[derive(Default)]
+pub struct SchnorrBatch {
+    items: Vec<(Message, Vec<u8>, Vec<u8>)>,
+}
+
+impl SchnorrBatch {
+    /// 添加待验证的签名,公钥和签名必须都是Schnorr
+    pub fn push(&mut self, msg: &[u8], pk: &PubKey, sig: &SigValue) -> Result<(), errors::Error> {
+        if pk.scheme() != SigScheme::Schnorr || sig.scheme() != SigScheme::Schnorr {
+            return errors::Error::msg("schnorr batch scheme error");
+        }
+        let msg = sign_message(msg).map_or_else(errors::Error::std, Ok)?;
+        self.items.push((msg, pk.into_bytes(), sig.into_bytes()));
+        Ok(())
+    }
+    /// 待验证的签名数量
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    //x-only坐标恢复为y为偶数的点
+    fn lift_x(x: &[u8]) -> Option<ProjectivePoint> {
+        VerifyingKey::from_bytes(x)
+            .ok()
+            .map(|v| ProjectivePoint::from(*v.as_affine()))
+    }
+    //BIP340挑战值 e = hash_challenge(r || P || m) mod n
+    fn challenge(r: &[u8], pk: &[u8], msg: &Message) -> Scalar {
+        let tag = Sha256::digest(b"BIP0340/challenge");
+        let mut h = Sha256::new();
+        h.update(tag);
+        h.update(tag);
+        h.update(r);
+        h.update(pk);
+        h.update(&msg[..]);
+        <Scalar as Reduce<U256>>::reduce_bytes(FieldBytes::from_slice(&h.finalize()))
+    }
+    /// 批量验签,全部正确返回true,空批次返回true
+    pub fn verify(&self) -> bool {
+        //随机系数由全部输入确定,第一个系数为1
+        let mut seed = vec![];
+        for (msg, pk, sig) in self.items.iter() {
+            seed.extend_from_slice(&msg[..]);
+            seed.extend_from_slice(pk);
+            seed.extend_from_slice(sig);
+        }
+        let seed = Hasher::hash(&seed);
+        let mut terms = Vec::with_capacity(self.items.len() * 2 + 1);
+        let mut sum = Scalar::ZERO;
+        for (i, (msg, pk, sig)) in self.items.iter().enumerate() {
+            let (r, s) = sig.split_at(32);
+            let (pp, rp) = match (Self::lift_x(pk), Self::lift_x(r)) {
+                (Some(pp), Some(rp)) => (pp, rp),
+                _ => return false,
+            };
+            //s必须小于曲线阶
+            let s: Option<Scalar> = Scalar::from_repr(*FieldBytes::from_slice(s)).into();
+            let s = match s {
+                Some(s) => s,
+                None => return false,
+            };
+            let a = if i == 0 {
+                Scalar::ONE
+            } else {
+                let mut buf = seed.as_bytes().to_vec();
+                buf.extend_from_slice(&(i as u32).to_le_bytes());
+                <Scalar as Reduce<U256>>::reduce_bytes(FieldBytes::from_slice(
+                    Hasher::hash(&buf).as_bytes(),
+                ))
+            };
+            let e = Self::challenge(r, pk, msg);
+            sum += a * s;
+            terms.push((rp, -a));
+            terms.push((pp, -(a * e)));
+        }
+        terms.push((ProjectivePoint::GENERATOR, sum));
+        ProjectivePoint::lincomb_ext(terms.as_slice())
+            .is_identity()
+            .into()
+    }
+    /// 批量验签,通过后写入签名缓存,之后的逐个验签直接命中缓存
+    /// 缓存中已有的签名不再参与验证
+    pub fn verify_with_cache(&self, cache: &SigCache) -> bool {
+        let mut batch = SchnorrBatch::default();
+        let mut keys = vec![];
+        for (msg, pk, sig) in self.items.iter() {
+            let key = SigCache::key(msg, pk, sig);
+            if !cache.peek(&key) {
+                batch.items.push((*msg, pk.clone(), sig.clone()));
+                keys.push(key);
+            }
+        }
+        if !batch.verify() {
+            return false;
+        }
+        for key in keys {
+            cache.insert(key);
+        }
+        true
+    }
+}
+
 #[test]
 fn test_pubkey_serialize() {
     use crate::iobuf::Writer;
@@ -262,3 +604,92 @@ fn test_signer() {
     assert!(!pv.verify("adfs1".as_bytes(), &signature).unwrap());
     assert!(pv.verify("adfs".as_bytes(), &signature).unwrap());
 }
+
+#[test]
+fn test_schnorr_signer() {
+    let kv = PriKey::new();
+    let pv = kv.xonly_pubkey();
+    assert_eq!(pv.scheme(), SigScheme::Schnorr);
+    assert_eq!(pv.into_bytes().len(), XONLY_PUBKEY_SIZE);
+    assert_eq!(PubKey::with_bytes(&pv.into_bytes()).unwrap(), pv);
+    let sig = kv.sign_schnorr(b"adfs").unwrap();
+    assert_eq!(sig.into_bytes().len(), SCHNORR_SIG_SIZE);
+    assert_eq!(
+        SigValue::with_scheme(SigScheme::Schnorr, &sig.into_bytes())
+            .unwrap()
+            .scheme(),
+        SigScheme::Schnorr
+    );
+    //不按长度推断算法
+    assert!(SigValue::with_bytes(&sig.into_bytes()).is_err());
+    assert!(SigValue::with_scheme(SigScheme::Schnorr, &sig.into_bytes()[1..]).is_err());
+    assert!(pv.verify(b"adfs", &sig).unwrap());
+    assert!(!pv.verify(b"adfs1", &sig).unwrap());
+    //签名算法和公钥类型不一致时验签失败
+    assert!(!kv.pubkey().verify(b"adfs", &sig).unwrap());
+    assert!(!pv.verify(b"adfs", &kv.sign(b"adfs").unwrap()).unwrap());
+    assert!(pv.add_tweak(&[1; 32]).is_err());
+    let hex = format!("{}", kv.sign(b"adfs").unwrap());
+    assert_eq!(format!("{}", hex.parse::<SigValue>().unwrap()), hex);
+    assert_eq!(format!("{}", pv).parse::<PubKey>().unwrap(), pv);
+}
+
+#[test]
+fn test_sig_cache() {
+    let cache = SigCache::new(2);
+    let kv = PriKey::new();
+    let sig = kv.sign(b"a").unwrap();
+    assert!(cache.verify(b"a", &kv.pubkey(), &sig));
+    assert_eq!(cache.hits(), 0);
+    assert!(cache.verify(b"a", &kv.pubkey(), &sig));
+    assert_eq!(cache.hits(), 1);
+    //验证失败的不缓存
+    assert!(!cache.verify(b"b", &kv.pubkey(), &sig));
+    assert!(!cache.verify(b"b", &kv.pubkey(), &sig));
+    assert_eq!(cache.len(), 1);
+    //schnorr签名使用相同的缓存
+    let sig = kv.sign_schnorr(b"c").unwrap();
+    assert!(cache.verify(b"c", &kv.xonly_pubkey(), &sig));
+    assert!(cache.verify(b"c", &kv.xonly_pubkey(), &sig));
+    assert_eq!(cache.hits(), 2);
+    //超过容量淘汰最早的
+    let sig = kv.sign(b"d").unwrap();
+    assert!(cache.verify(b"d", &kv.pubkey(), &sig));
+    assert_eq!(cache.len(), 2);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_schnorr_batch() {
+    let keys: Vec<PriKey> = (0..4).map(|_| PriKey::new()).collect();
+    let sigs: Vec<SigValue> = keys
+        .iter()
+        .map(|kv| kv.sign_schnorr(b"msg").unwrap())
+        .collect();
+    let mut batch = SchnorrBatch::default();
+    assert!(batch.verify());
+    for (kv, sig) in keys.iter().zip(sigs.iter()) {
+        batch.push(b"msg", &kv.xonly_pubkey(), sig).unwrap();
+        assert!(batch.verify());
+    }
+    assert_eq!(batch.len(), 4);
+    //通过后写入缓存
+    let cache = SigCache::default();
+    assert!(batch.verify_with_cache(&cache));
+    assert_eq!(cache.len(), 4);
+    assert!(cache.verify(b"msg", &keys[1].xonly_pubkey(), &sigs[1]));
+    assert_eq!(cache.hits(), 1);
+    //已缓存的签名不计入命中
+    assert!(batch.verify_with_cache(&cache));
+    assert_eq!(cache.hits(), 1);
+    //任意一个签名错误整批失败,且不写入缓存
+    let cache = SigCache::default();
+    let sig = keys[0].sign_schnorr(b"other").unwrap();
+    batch.push(b"bad", &keys[0].xonly_pubkey(), &sig).unwrap();
+    assert!(!batch.verify_with_cache(&cache));
+    assert!(cache.is_empty());
+    //ECDSA签名不能加入批次
+    let sig = keys[0].sign(b"a").unwrap();
+    assert!(batch.push(b"a", &keys[0].pubkey(), &sig).is_err());
+}
@@ -19,6 +19,10 @@ impl Error {
     {
         Self::msg(&err.to_string())
     }
+    /// 错误信息
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
     /// 格式化
     pub fn fmt<T>(args: fmt::Arguments<'_>) -> Result<T, Self> {
         let str = fmt::format(args);
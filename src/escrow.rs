@@ -0,0 +1,213 @@
+use crate::account::{Account, HasAddress};
+use crate::block::{Tx, TxIn, TxOut};
+use crate::crypto::{PriKey, PubKey};
+use crate::errors::Error;
+use crate::hasher::Hasher;
+use crate::index::{Chain, CoinAttr};
+use crate::psbt::PartialTx;
+use crate::script::*;
+
+/// 担保参与方,对应担保账户中的公钥位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EscrowRole {
+    Buyer,   //买家
+    Seller,  //卖家
+    Arbiter, //仲裁,仲裁账户的最后一个公钥
+}
+
+impl EscrowRole {
+    /// 在担保账户中的公钥索引
+    pub fn index(&self) -> usize {
+        match self {
+            EscrowRole::Buyer => 0,
+            EscrowRole::Seller => 1,
+            EscrowRole::Arbiter => 2,
+        }
+    }
+}
+
+/// 基于脚本hash的担保
+/// 赎回脚本允许两个账户消费:
+/// 买卖双方2-2多重签名账户,共同签名释放金额
+/// 三方启用仲裁的账户,按Account::verify_full规则由仲裁单独签名裁决金额去向
+#[derive(Debug, Clone)]
+pub struct Escrow {
+    parties: Account, //买卖双方账户
+    arbiter: Account, //仲裁账户
+    redeem: Script,   //赎回脚本
+}
+
+impl Escrow {
+    /// 使用三方公钥创建担保赎回脚本
+    pub fn new(buyer: &PubKey, seller: &PubKey, arbiter: &PubKey) -> Result<Self, Error> {
+        let parties = Account::with_pubs(2, false, &[buyer.clone(), seller.clone()])?;
+        let pubs = [buyer.clone(), seller.clone(), arbiter.clone()];
+        let arbiter = Account::with_pubs(2, true, &pubs)?;
+        //账户为双方账户,否则必须为仲裁账户,最后验签
+        let mut redeem = Script::from(SCRIPT_TYPE_OUT);
+        redeem.op(OP_VERIFY_INOUT);
+        redeem.op(OP_HASHER);
+        redeem.put(&parties.hash()?);
+        redeem.op(OP_EQUAL);
+        redeem.op(OP_NOTIF);
+        redeem.op(OP_HASHER);
+        redeem.put(&arbiter.hash()?);
+        redeem.op(OP_EQUAL_VERIFY);
+        redeem.op(OP_ENDIF);
+        redeem.op(OP_CHECKSIG_VERIFY);
+        redeem.check()?;
+        Ok(Escrow {
+            parties,
+            arbiter,
+            redeem,
+        })
+    }
+    /// 获取赎回脚本
+    pub fn redeem(&self) -> &Script {
+        &self.redeem
+    }
+    /// 按参与方获取消费账户,买卖双方使用双方账户
+    pub fn account(&self, role: EscrowRole) -> &Account {
+        match role {
+            EscrowRole::Arbiter => &self.arbiter,
+            _ => &self.parties,
+        }
+    }
+    /// 创建消费担保金额到addr的待签名交易,fee为交易费
+    /// resolve为true时使用仲裁账户,否则使用买卖双方账户
+    pub fn spend(
+        &self,
+        ctx: &Chain,
+        coin: &CoinAttr,
+        addr: &str,
+        fee: i64,
+        resolve: bool,
+    ) -> Result<PartialTx, Error> {
+        if coin.get_address()? != self.get_address()? {
+            return Error::msg("coin not escrow address");
+        }
+        if fee < 0 || fee >= coin.coin() {
+            return Error::msg("fee error");
+        }
+        let acc = if resolve {
+            &self.arbiter
+        } else {
+            &self.parties
+        };
+        let mut tx = Tx::default();
+        tx.ins.push(TxIn {
+            out: coin.tx().clone(),
+            idx: coin.idx(),
+            script: Script::new_script_in_with(&[self.redeem.bytes().to_vec()], acc)?,
+            ..TxIn::default()
+        });
+        tx.outs.push(TxOut {
+            value: coin.coin() - fee,
+            script: Script::new_script_out(&Account::decode(addr)?)?,
+        });
+        PartialTx::new(ctx, &tx)
+    }
+    /// 参与方签名担保交易的所有输入
+    pub fn sign(&self, ptx: &mut PartialTx, role: EscrowRole, pk: &PriKey) -> Result<(), Error> {
+        for i in 0..ptx.accs.len() {
+            let acc = &ptx.accs[i];
+            //仲裁账户只需要仲裁签名
+            if acc == &self.arbiter && role != EscrowRole::Arbiter {
+                return Error::msg("role can't sign arbiter input");
+            }
+            if acc != &self.arbiter && acc != &self.parties {
+                return Error::msg("input not escrow account");
+            }
+            ptx.sign_with_index(i, role.index(), pk)?;
+        }
+        Ok(())
+    }
+}
+
+impl HasAddress for Escrow {
+    /// 赎回脚本hash
+    fn get_address(&self) -> Result<Hasher, Error> {
+        Ok(Hasher::hash(self.redeem.bytes()))
+    }
+    /// 脚本hash地址,向这个地址转账即为注资
+    fn string(&self) -> Result<String, Error> {
+        self.redeem.get_sh_address()
+    }
+}
+
+#[test]
+fn test_escrow_release_resolve() {
+    use crate::config::Config;
+    use crate::consts;
+    use crate::index::FullSigner;
+    use std::convert::TryFrom;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let (buyer, seller, arbiter) = (PriKey::new(), PriKey::new(), PriKey::new());
+        let escrow = Escrow::new(&buyer.pubkey(), &seller.pubkey(), &arbiter.pubkey())?;
+        assert!(escrow.string()?.starts_with(consts::SH_HRP));
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //买家注资三笔
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&escrow.string()?, 10 * consts::COIN)?;
+        txh.add_out(&escrow.string()?, 20 * consts::COIN)?;
+        txh.add_out(&escrow.string()?, 15 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let fund = Tx::try_from(&txh)?;
+        idx.append(&fund)?;
+        let mut blk = idx.create_block("fund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        let mut coins = idx.coins(&escrow)?;
+        assert_eq!(coins.len(), 3);
+        coins.sort_by_key(|v| v.coin());
+        let to = accpool.value(1)?.string()?;
+        //买卖双方共同释放
+        let mut ptx = escrow.spend(&idx, &coins[0], &to, consts::COIN, false)?;
+        escrow.sign(&mut ptx, EscrowRole::Buyer, &buyer)?;
+        assert!(!ptx.is_complete());
+        //私钥和角色不对应
+        assert!(escrow
+            .sign(&mut ptx.clone(), EscrowRole::Seller, &arbiter)
+            .is_err());
+        escrow.sign(&mut ptx, EscrowRole::Seller, &seller)?;
+        assert!(ptx.is_complete());
+        idx.append(&ptx.finalize()?)?;
+        //仲裁单独裁决
+        let mut ptx = escrow.spend(&idx, &coins[1], &to, consts::COIN, true)?;
+        assert!(escrow
+            .sign(&mut ptx.clone(), EscrowRole::Buyer, &buyer)
+            .is_err());
+        escrow.sign(&mut ptx, EscrowRole::Arbiter, &arbiter)?;
+        assert!(ptx.is_complete());
+        idx.append(&ptx.finalize()?)?;
+        //买卖双方不能使用仲裁账户消费
+        let mut ptx = escrow.spend(&idx, &coins[2], &to, consts::COIN, true)?;
+        ptx.sign_with_index(0, 0, &buyer)?;
+        ptx.sign_with_index(0, 1, &seller)?;
+        assert!(!ptx.is_complete());
+        //其他账户不能消费
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_sh_in(&coins[2], escrow.redeem(), &vec![], &to)?;
+        txh.set_cost_fee(consts::COIN)?;
+        assert!(idx.append(&Tx::try_from(&txh)?).is_err());
+        let mut blk = idx.create_block("release", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(idx.coins(&escrow)?.len(), 1);
+        Ok(())
+    });
+}
@@ -3,6 +3,7 @@ use crate::errors;
 use crate::iobuf::{Reader, Serializer, Writer};
 use core::fmt;
 use hex::{FromHex, ToHex};
+use ripemd::Ripemd160;
 use sha2::{Digest, Sha256};
 pub const SIZE: usize = 32;
 use crate::account::HasAddress;
@@ -300,6 +301,9 @@ impl Hasher {
 
 impl FromBytes for Hasher {
     fn from_bytes(bb: &Vec<u8>) -> Result<Self, errors::Error> {
+        if bb.len() != SIZE {
+            return errors::Error::msg("hasher bytes size error");
+        }
         let mut inner = [0u8; SIZE];
         inner.copy_from_slice(&bb);
         Ok(Hasher { inner: inner })
@@ -344,6 +348,12 @@ impl fmt::Display for Hasher {
     }
 }
 
+impl std::hash::Hash for Hasher {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
 impl PartialOrd for Hasher {
     #[inline]
     fn partial_cmp(&self, other: &Hasher) -> Option<Ordering> {
@@ -409,3 +419,37 @@ fn test_wirter_u256() {
     let v2: Hasher = rb.get().unwrap();
     assert_eq!(v1, v2);
 }
+
+/// RIPEMD-160
+pub fn ripemd160(input: &[u8]) -> [u8; 20] {
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&<Ripemd160 as ripemd::Digest>::digest(input));
+    out
+}
+
+/// HASH160 = ripemd160(sha256(input))
+pub fn hash160(input: &[u8]) -> [u8; 20] {
+    ripemd160(Hasher::sum(input).as_bytes())
+}
+
+#[test]
+fn test_ripemd160() {
+    let check = |input: &str, hex: &str| {
+        assert_eq!(ripemd160(input.as_bytes()).encode_hex::<String>(), hex);
+    };
+    check("", "9c1185a5c5e9fc54612808977ee8f548b2258d31");
+    check("abc", "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc");
+    check("message digest", "5d0689ef49d2fae572b881b123a85ffa21595f36");
+    check(
+        "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+        "9b752e45573d4b39f4dbd3323cab82bf63326bfb",
+    );
+    //私钥为1的压缩公钥
+    let pubkey =
+        Vec::from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+    assert_eq!(
+        hash160(&pubkey).encode_hex::<String>(),
+        "751e76e8199196d454941c45d1b3a323f1433bd6"
+    );
+}
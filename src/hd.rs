@@ -0,0 +1,437 @@
+use crate::account::Account;
+use crate::bytes::IntoBytes;
+use crate::consts;
+use crate::crypto::{PriKey, PubKey};
+use crate::errors::Error;
+use crate::hasher::{hash160, Hasher};
+use crate::iobuf::{Reader, Writer};
+use core::{fmt, str};
+use num_bigint::BigUint;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+/// 强化推导索引起始值
+pub const HARDENED: u32 = 0x80000000;
+/// 扩展私钥版本
+const XPRV_VERSION: u32 = 0x0488ADE4;
+/// 扩展公钥版本
+const XPUB_VERSION: u32 = 0x0488B21E;
+
+/// Base58字符表
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// HMAC-SHA512
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> Result<[u8; 64], Error> {
+    let pkey = PKey::hmac(key).map_or_else(Error::std, Ok)?;
+    let mut signer = Signer::new(MessageDigest::sha512(), &pkey).map_or_else(Error::std, Ok)?;
+    signer.update(data).map_or_else(Error::std, Ok)?;
+    let buf = signer.sign_to_vec().map_or_else(Error::std, Ok)?;
+    let mut out = [0u8; 64];
+    out.copy_from_slice(&buf);
+    Ok(out)
+}
+
+/// Base58Check编码,校验码为数据double sha256的前4个字节
+pub fn base58check_encode(data: &[u8]) -> String {
+    let mut buf = data.to_vec();
+    buf.extend_from_slice(&Hasher::hash(data).as_bytes()[..4]);
+    //前导0编码为1,其余按大整数转为58进制
+    let zeros = buf.iter().take_while(|v| **v == 0).count();
+    let mut s = String::from(BASE58_ALPHABET[0] as char).repeat(zeros);
+    if zeros < buf.len() {
+        for d in BigUint::from_bytes_be(&buf[zeros..]).to_radix_be(58) {
+            s.push(BASE58_ALPHABET[d as usize] as char);
+        }
+    }
+    s
+}
+
+/// Base58Check解码并检测校验码
+pub fn base58check_decode(s: &str) -> Result<Vec<u8>, Error> {
+    let mut digits = vec![];
+    for c in s.bytes() {
+        match BASE58_ALPHABET.iter().position(|v| *v == c) {
+            Some(v) => digits.push(v as u8),
+            None => return Error::msg("base58 char error"),
+        }
+    }
+    let zeros = digits.iter().take_while(|v| **v == 0).count();
+    let mut buf = vec![0u8; zeros];
+    if zeros < digits.len() {
+        match BigUint::from_radix_be(&digits[zeros..], 58) {
+            Some(v) => buf.extend(v.to_bytes_be()),
+            None => return Error::msg("base58 char error"),
+        }
+    }
+    if buf.len() < 4 {
+        return Error::msg("base58check length error");
+    }
+    let (data, sum) = buf.split_at(buf.len() - 4);
+    if &Hasher::hash(data).as_bytes()[..4] != sum {
+        return Error::msg("base58check checksum error");
+    }
+    Ok(data.to_vec())
+}
+
+/// 解析推导路径 m/44'/0'/0'/0/1, ' 或者 h 表示强化推导
+pub fn parse_path(path: &str) -> Result<Vec<u32>, Error> {
+    let mut iter = path.split('/');
+    if iter.next() != Some("m") {
+        return Error::msg("path must start with m");
+    }
+    let mut idxs = vec![];
+    for v in iter {
+        let (v, hardened) = match v.strip_suffix('\'').or(v.strip_suffix('h')) {
+            Some(v) => (v, true),
+            None => (v, false),
+        };
+        let i: u32 = v.parse().map_or_else(Error::std, Ok)?;
+        if i >= HARDENED {
+            return Error::msg("path index too large");
+        }
+        idxs.push(if hardened { i | HARDENED } else { i });
+    }
+    Ok(idxs)
+}
+
+/// BIP44账户路径 m/44'/HD_COIN_TYPE'/acc'
+pub fn account_path(acc: u32) -> String {
+    format!("m/44'/{}'/{}'", consts::HD_COIN_TYPE, acc)
+}
+
+/// 分层确定性扩展私钥
+/// 推导和字符串格式(版本xprv/xpub,HASH160父指纹,Base58Check)与BIP32一致
+/// 与BIP32的差异只在账户生成: 一个账户由多个密钥组成,见new_account
+#[derive(Debug, Clone)]
+pub struct ExtPriKey {
+    depth: u8,       //推导深度
+    parent: [u8; 4], //父公钥指纹
+    index: u32,      //推导索引
+    chain: [u8; 32], //链码
+    key: PriKey,     //私钥
+}
+
+/// 分层确定性扩展公钥,只能推导非强化子公钥,用于只读钱包
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtPubKey {
+    depth: u8,       //推导深度
+    parent: [u8; 4], //父公钥指纹
+    index: u32,      //推导索引
+    chain: [u8; 32], //链码
+    key: PubKey,     //公钥
+}
+
+/// 公钥指纹,取公钥HASH160前4个字节
+fn fingerprint(key: &PubKey) -> [u8; 4] {
+    let mut fp = [0u8; 4];
+    fp.copy_from_slice(&hash160(&key.into_bytes())[..4]);
+    fp
+}
+
+/// 推导结果拆分为tweak和链码
+fn split(i: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let (mut il, mut ir) = ([0u8; 32], [0u8; 32]);
+    il.copy_from_slice(&i[..32]);
+    ir.copy_from_slice(&i[32..]);
+    (il, ir)
+}
+
+impl ExtPriKey {
+    /// 从种子创建主密钥
+    pub fn from_seed(seed: &[u8]) -> Result<Self, Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Error::msg("seed length error");
+        }
+        let (il, ir) = split(hmac_sha512(b"Bitcoin seed", seed)?);
+        Ok(ExtPriKey {
+            depth: 0,
+            parent: [0; 4],
+            index: 0,
+            chain: ir,
+            key: PriKey::with_bytes(&il)?,
+        })
+    }
+    /// 获取私钥
+    pub fn key(&self) -> &PriKey {
+        &self.key
+    }
+    /// 获取链码
+    pub fn chain(&self) -> &[u8; 32] {
+        &self.chain
+    }
+    /// 推导子私钥,index >= HARDENED 为强化推导
+    pub fn derive(&self, index: u32) -> Result<Self, Error> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.key.into_bytes());
+        } else {
+            data.extend_from_slice(&self.key.pubkey().into_bytes());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let (il, ir) = split(hmac_sha512(&self.chain, &data)?);
+        Ok(ExtPriKey {
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Error::error("depth overflow"))?,
+            parent: fingerprint(&self.key.pubkey()),
+            index,
+            chain: ir,
+            key: self.key.add_tweak(&il)?,
+        })
+    }
+    /// 按路径推导,路径从当前密钥开始
+    pub fn derive_path(&self, path: &str) -> Result<Self, Error> {
+        let mut key = self.clone();
+        for index in parse_path(path)? {
+            key = key.derive(index)?;
+        }
+        Ok(key)
+    }
+    /// 获取对应的扩展公钥
+    pub fn public(&self) -> ExtPubKey {
+        ExtPubKey {
+            depth: self.depth,
+            parent: self.parent,
+            index: self.index,
+            chain: self.chain,
+            key: self.key.pubkey(),
+        }
+    }
+    /// 在BIP44账户级密钥上生成多密钥账户
+    /// 第j个密钥路径为 j/idx,同一个idx生成同一个账户
+    pub fn new_account(&self, num: u8, less: u8, arb: bool, idx: u32) -> Result<Account, Error> {
+        let mut pks = vec![];
+        for j in 0..num as u32 {
+            pks.push(self.derive(j)?.derive(idx)?.key);
+        }
+        let pubs: Vec<PubKey> = pks.iter().map(|v| v.pubkey()).collect();
+        let mut acc = Account::with_pubs(less, arb, &pubs)?;
+        for (j, pk) in pks.iter().enumerate() {
+            acc.set_pri(j, pk)?;
+        }
+        Ok(acc)
+    }
+    /// 编码BIP32格式数据
+    fn encode(&self, wb: &mut Writer) {
+        wb.put_bytes(&XPRV_VERSION.to_be_bytes());
+        wb.u8(self.depth);
+        wb.put_bytes(&self.parent);
+        wb.put_bytes(&self.index.to_be_bytes());
+        wb.put_bytes(&self.chain);
+        wb.u8(0);
+        wb.put_bytes(&self.key.into_bytes());
+    }
+}
+
+impl ExtPubKey {
+    /// 获取公钥
+    pub fn key(&self) -> &PubKey {
+        &self.key
+    }
+    /// 推导非强化子公钥
+    pub fn derive(&self, index: u32) -> Result<Self, Error> {
+        if index >= HARDENED {
+            return Error::msg("xpub can't derive hardened key");
+        }
+        let mut data = self.key.into_bytes();
+        data.extend_from_slice(&index.to_be_bytes());
+        let (il, ir) = split(hmac_sha512(&self.chain, &data)?);
+        Ok(ExtPubKey {
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Error::error("depth overflow"))?,
+            parent: fingerprint(&self.key),
+            index,
+            chain: ir,
+            key: self.key.add_tweak(&il)?,
+        })
+    }
+    /// 按路径推导,路径中不能包含强化索引
+    pub fn derive_path(&self, path: &str) -> Result<Self, Error> {
+        let mut key = self.clone();
+        for index in parse_path(path)? {
+            key = key.derive(index)?;
+        }
+        Ok(key)
+    }
+    /// 生成只包含公钥的账户,和ExtPriKey::new_account对应
+    pub fn new_account(&self, num: u8, less: u8, arb: bool, idx: u32) -> Result<Account, Error> {
+        let mut pubs = vec![];
+        for j in 0..num as u32 {
+            pubs.push(self.derive(j)?.derive(idx)?.key);
+        }
+        Account::with_pubs(less, arb, &pubs)
+    }
+    /// 编码BIP32格式数据
+    fn encode(&self, wb: &mut Writer) {
+        wb.put_bytes(&XPUB_VERSION.to_be_bytes());
+        wb.u8(self.depth);
+        wb.put_bytes(&self.parent);
+        wb.put_bytes(&self.index.to_be_bytes());
+        wb.put_bytes(&self.chain);
+        wb.put_bytes(&self.key.into_bytes());
+    }
+}
+
+/// BIP32格式数据头 (版本,深度,父指纹,索引,链码,密钥)
+type ExtHeader = (u32, u8, [u8; 4], u32, [u8; 32], Vec<u8>);
+
+/// 解码BIP32格式数据头
+fn decode_ext(s: &str) -> Result<ExtHeader, Error> {
+    let buf = base58check_decode(s)?;
+    if buf.len() != 78 {
+        return Error::msg("ext key length error");
+    }
+    let mut r = Reader::new(&buf);
+    let mut be = [0u8; 4];
+    be.copy_from_slice(&r.get_bytes(4)?);
+    let version = u32::from_be_bytes(be);
+    let depth = r.u8()?;
+    let mut parent = [0u8; 4];
+    parent.copy_from_slice(&r.get_bytes(4)?);
+    be.copy_from_slice(&r.get_bytes(4)?);
+    let index = u32::from_be_bytes(be);
+    let mut chain = [0u8; 32];
+    chain.copy_from_slice(&r.get_bytes(32)?);
+    Ok((version, depth, parent, index, chain, r.get_bytes(33)?))
+}
+
+impl fmt::Display for ExtPriKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut wb = Writer::default();
+        self.encode(&mut wb);
+        write!(f, "{}", base58check_encode(wb.bytes()))
+    }
+}
+
+impl str::FromStr for ExtPriKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (version, depth, parent, index, chain, key) = decode_ext(s)?;
+        if version != XPRV_VERSION || key[0] != 0 {
+            return Error::msg("xprv format error");
+        }
+        Ok(ExtPriKey {
+            depth,
+            parent,
+            index,
+            chain,
+            key: PriKey::with_bytes(&key[1..])?,
+        })
+    }
+}
+
+impl fmt::Display for ExtPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut wb = Writer::default();
+        self.encode(&mut wb);
+        write!(f, "{}", base58check_encode(wb.bytes()))
+    }
+}
+
+impl str::FromStr for ExtPubKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (version, depth, parent, index, chain, key) = decode_ext(s)?;
+        if version != XPUB_VERSION {
+            return Error::msg("xpub format error");
+        }
+        Ok(ExtPubKey {
+            depth,
+            parent,
+            index,
+            chain,
+            key: PubKey::with_bytes(&key)?,
+        })
+    }
+}
+
+#[test]
+fn test_hd_bip32_vector() {
+    use hex::{FromHex, ToHex};
+    let seed = Vec::from_hex("000102030405060708090a0b0c0d0e0f").unwrap();
+    let m = ExtPriKey::from_seed(&seed).unwrap();
+    let check = |k: &ExtPriKey, chain: &str, key: &str| {
+        assert_eq!(k.chain.encode_hex::<String>(), chain);
+        assert_eq!(k.key.into_bytes().encode_hex::<String>(), key);
+    };
+    check(
+        &m,
+        "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+        "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+    );
+    let k = m.derive_path("m/0'").unwrap();
+    check(
+        &k,
+        "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+        "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+    );
+    let k = m.derive_path("m/0h/1").unwrap();
+    check(
+        &k,
+        "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+        "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+    );
+    //BIP32序列化
+    assert_eq!(m.to_string(), "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi");
+    assert_eq!(m.public().to_string(), "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8");
+    assert_eq!(m.derive_path("m/0'").unwrap().to_string(), "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7");
+    assert_eq!(k.public().to_string(), "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ");
+    //RFC4231 超过分组长度的密钥
+    let mac = hmac_sha512(
+        &[0xaa; 131],
+        "Test Using Larger Than Block-Size Key - Hash Key First".as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(mac.encode_hex::<String>(), "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598");
+    //校验码错误
+    let mut bad = m.to_string();
+    bad.pop();
+    bad.push('j');
+    assert!(bad.parse::<ExtPriKey>().is_err());
+    assert!(base58check_decode("0OIl").is_err());
+    assert_eq!(
+        base58check_decode(&base58check_encode(&[0, 0, 1])).unwrap(),
+        vec![0, 0, 1]
+    );
+    //非强化推导公钥一致
+    let p = m.derive_path("m/0'").unwrap().public().derive(1).unwrap();
+    assert_eq!(p, k.public());
+    assert!(p.derive(HARDENED).is_err());
+    assert!(parse_path("0/1").is_err());
+    assert!(parse_path("m/2147483648").is_err());
+}
+
+#[test]
+fn test_hd_account_watch_only() {
+    let m = ExtPriKey::from_seed(&[7u8; 32]).unwrap();
+    let xprv = m.derive_path(&account_path(0)).unwrap();
+    let xpub = xprv.public();
+    //序列化
+    let xprv: ExtPriKey = xprv.to_string().parse().unwrap();
+    let xpub: ExtPubKey = xpub.to_string().parse().unwrap();
+    assert!(xpub.to_string().parse::<ExtPriKey>().is_err());
+    //相同路径生成相同账户
+    let acc = xprv.new_account(3, 2, false, 5).unwrap();
+    assert!(acc.check_pris_pubs());
+    assert_eq!(
+        acc,
+        m.derive_path(&account_path(0))
+            .unwrap()
+            .new_account(3, 2, false, 5)
+            .unwrap()
+    );
+    assert_ne!(acc, xprv.new_account(3, 2, false, 6).unwrap());
+    //只读钱包推导相同地址
+    let watch = xpub.new_account(3, 2, false, 5).unwrap();
+    assert_eq!(acc.hash().unwrap(), watch.hash().unwrap());
+    assert!(!watch.check_pris_pubs());
+    //签名
+    let mut acc = acc;
+    acc.sign_full("aaa".as_bytes()).unwrap();
+    assert!(acc.verify_full("aaa".as_bytes()).unwrap());
+}
@@ -1,27 +1,31 @@
 use crate::account::{Account, AccountPool, HasAddress};
-use crate::block::{Best, BlkAttr, Block, Checker, Tx, TxAttr, TxIn, TxOut};
-use crate::bytes::IntoBytes;
+use crate::block::{Best, BlkAttr, Block, Checker, Htlc, Tx, TxAttr, TxIn, TxOut};
+use crate::bytes::{FromBytes, IntoBytes};
 use crate::config::Config;
 use crate::consts;
+use crate::crypto::{SchnorrBatch, SigCache, SigScheme};
 use crate::errors::Error;
 use crate::hasher::Hasher;
 use crate::iobuf::Writer;
 use crate::iobuf::{Reader, Serializer};
 use crate::leveldb::{IBatch, LevelDB};
-use crate::script::{Ele, Exector, ExectorEnv, Script};
+use crate::policy::Policy;
+use crate::psbt::PartialTx;
+use crate::script::{Ele, Exector, ExectorEnv, Script, Trace};
 use crate::store::Store;
 use crate::util;
 use bytes::BufMut;
 use core::hash;
 use db_key::Key;
 use lru::LruCache;
-use std::cmp::{Eq, PartialEq};
+use std::cmp::{Eq, PartialEq, Reverse};
 use std::collections::btree_map;
 use std::collections::{BTreeMap, HashMap};
 use std::convert::{Into, TryFrom, TryInto};
 use std::iter::Rev;
 use std::path::Path;
 use std::slice;
+use std::sync::mpsc;
 use std::sync::RwLock;
 use std::sync::{Arc, Mutex};
 use threadpool::ThreadPool;
@@ -29,8 +33,9 @@ use threadpool::ThreadPool;
 /// 交易助手输出元素
 #[derive(Debug, Clone)]
 pub struct TxOutEle {
-    value: i64,   //输出金额
-    addr: String, //输出地址
+    value: i64,             //输出金额
+    addr: String,           //输出地址
+    script: Option<Script>, //自定义输出脚本,设置时忽略地址
 }
 
 impl TxOutEle {
@@ -38,6 +43,49 @@ impl TxOutEle {
         TxOutEle {
             value: coin,
             addr: addr.into(),
+            script: None,
+        }
+    }
+    /// 使用自定义脚本创建输出
+    pub fn with_script(script: &Script, coin: i64) -> Self {
+        TxOutEle {
+            value: coin,
+            addr: String::default(),
+            script: Some(script.clone()),
+        }
+    }
+    /// 是否是数据输出
+    fn is_data(&self) -> bool {
+        self.script.as_ref().is_some_and(|v| v.is_data())
+    }
+    /// 获取输出脚本
+    fn get_script(&self) -> Result<Script, Error> {
+        if let Some(script) = &self.script {
+            return Ok(script.clone());
+        }
+        //账户地址或者脚本hash地址
+        match Account::decode_with_hrp(&self.addr)? {
+            (hrp, hv) if hrp == consts::ADDR_HRP => Script::new_script_out(&hv),
+            (hrp, hv) if hrp == consts::SH_HRP => Script::new_script_sh(&hv),
+            _ => Error::msg("hrp not match"),
+        }
+    }
+}
+
+/// 交易助手自定义输入元素,用于消费非标准输出
+#[derive(Debug, Clone)]
+pub struct TxInEle {
+    coin: CoinAttr,      //消费的金额
+    addr: String,        //签名账户地址
+    datas: Vec<Vec<u8>>, //账户前放入的数据
+}
+
+impl TxInEle {
+    pub fn new(coin: &CoinAttr, addr: &str, datas: &[Vec<u8>]) -> Self {
+        TxInEle {
+            coin: coin.clone(),
+            addr: addr.into(),
+            datas: datas.to_vec(),
         }
     }
 }
@@ -47,7 +95,7 @@ impl TxOutEle {
 pub struct BlkHelper {
     ver: u16,
     outs: Vec<TxOutEle>, //输出金额
-    cbstr: String,       //coinbase信息
+    cbstr: Vec<u8>,      //coinbase信息
     txs: Vec<Arc<Tx>>,   //相关交易
     bits: u32,           //当前难度
     height: u32,         //当前高度
@@ -69,7 +117,12 @@ impl BlkHelper {
     }
     /// 设置交易费
     pub fn set_cbstr(&mut self, cbstr: &str) -> Result<&mut Self, Error> {
-        self.cbstr = cbstr.into();
+        self.cbstr = cbstr.as_bytes().to_vec();
+        Ok(self)
+    }
+    /// 设置coinbase自定义二进制数据
+    pub fn set_cbdata(&mut self, data: &[u8]) -> Result<&mut Self, Error> {
+        self.cbstr = data.to_vec();
         Ok(self)
     }
     /// 设置输出
@@ -79,10 +132,7 @@ impl BlkHelper {
     }
     /// 添加输出
     pub fn add_out(&mut self, addr: &str, coin: i64) -> Result<&mut Self, Error> {
-        self.outs.push(TxOutEle {
-            addr: addr.into(),
-            value: coin,
-        });
+        self.outs.push(TxOutEle::new(addr, coin));
         Ok(self)
     }
     /// 设置交易
@@ -99,7 +149,7 @@ impl BlkHelper {
         BlkHelper {
             ver: ver,
             outs: vec![],
-            cbstr: "".into(),
+            cbstr: vec![],
             bits: 0,
             height: 0,
             prev: Hasher::zero(),
@@ -126,7 +176,7 @@ impl TryFrom<&BlkHelper> for Block {
         let mut inv = TxIn::default();
         inv.out = Hasher::zero();
         inv.idx = 0;
-        inv.script = Script::new_script_cb(helper.height, helper.cbstr.as_bytes())?;
+        inv.script = Script::new_script_cb(helper.height, &helper.cbstr)?;
         inv.seq = 0;
         cb.ins.push(inv);
         //coinbase输出
@@ -134,10 +184,9 @@ impl TryFrom<&BlkHelper> for Block {
             if ele.value == 0 {
                 continue;
             }
-            let addr = Account::decode(&ele.addr)?;
             let mut outv = TxOut::default();
             outv.value = ele.value;
-            outv.script = Script::new_script_out(&addr)?;
+            outv.script = ele.get_script()?;
             cb.outs.push(outv);
         }
         //添加第一个coinbase交易
@@ -156,16 +205,80 @@ impl TryFrom<&BlkHelper> for Block {
     }
 }
 
+/// 自动选择金额的策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoinSelect {
+    /// 分支定界,寻找不需要找零的组合,找不到时按LargestFirst选择
+    BranchAndBound,
+    /// 优先使用金额大的
+    LargestFirst,
+    /// 优先使用区块高度低的
+    OldestFirst,
+}
+
+/// 分支定界最大尝试次数
+const BNB_MAX_TRIES: usize = 100000;
+
+/// 分支定界搜索有效金额之和在[lo,hi]之间的组合
+/// values需要从大到小排序,返回选中的索引
+fn branch_and_bound(values: &[i64], lo: i64, hi: i64) -> Option<Vec<usize>> {
+    //剩余金额之和,用于剪枝
+    let mut rest = vec![0; values.len() + 1];
+    for i in (0..values.len()).rev() {
+        rest[i] = rest[i + 1] + values[i];
+    }
+    fn search(
+        values: &[i64],
+        rest: &[i64],
+        i: usize,
+        sum: i64,
+        range: (i64, i64),
+        sel: &mut Vec<usize>,
+        tries: &mut usize,
+    ) -> bool {
+        let (lo, hi) = range;
+        *tries += 1;
+        if sum > hi || sum + rest[i] < lo || *tries > BNB_MAX_TRIES {
+            return false;
+        }
+        if sum >= lo {
+            return true;
+        }
+        if i >= values.len() {
+            return false;
+        }
+        //先尝试包含当前金额
+        sel.push(i);
+        if search(values, rest, i + 1, sum + values[i], range, sel, tries) {
+            return true;
+        }
+        sel.pop();
+        search(values, rest, i + 1, sum, range, sel, tries)
+    }
+    let mut sel = vec![];
+    let mut tries = 0;
+    if search(values, &rest, 0, 0, (lo, hi), &mut sel, &mut tries) {
+        Some(sel)
+    } else {
+        None
+    }
+}
+
 /// 交易助手
 /// 生成交易信息
 pub struct TxHelper<'a> {
     ver: u32,                          //交易版本
+    lock: u32,                         //交易锁定时间
+    seq: u32,                          //输入序列号
     coins: Vec<CoinAttr>,              //使用的金额作为输入信息
+    ins: Vec<TxInEle>,                 //自定义输入
     outs: Vec<TxOutEle>,               //输出金额
     tfee: i64,                         //交易费
     ctx: &'a Chain,                    //链对象
     kaddr: Option<Hasher>,             //找零地址
     signer: Option<Box<dyn TxSigner>>, //签名器,如果设置执行签名
+    clock: Option<bool>,               //选择金额时锁定,值为是否持久化
+    locked: Vec<(Hasher, u16)>,        //选择金额时锁定的输出
 }
 
 impl<'a> TryFrom<&TxHelper<'a>> for Tx {
@@ -178,17 +291,21 @@ impl<'a> TryFrom<&TxHelper<'a>> for Tx {
         let (mut ifee, mut ofee) = (0, 0);
         let mut tx = Tx::default();
         tx.ver = helper.ver;
+        tx.lock = helper.lock;
         //获取账户池
         let accpool = helper.ctx.get_account_pool()?;
         for coin in helper.coins.iter() {
             //金额对应的账户信息,如果没有将不能消费这个金额
             let acc = accpool.account(&coin.cpk.string()?)?;
+            if acc.scheme() == SigScheme::Schnorr {
+                tx.ver = tx.ver.max(consts::TX_VER_SCHNORR);
+            }
             let mut inv = TxIn::default();
             inv.out = coin.tx.clone();
             inv.idx = coin.idx;
             //未签名脚本
             inv.script = Script::new_script_in(&acc)?;
-            inv.seq = 0;
+            inv.seq = helper.seq;
             tx.ins.push(inv);
             ifee += coin.value;
             //如果未设置找零输出账户使用第一个
@@ -196,15 +313,31 @@ impl<'a> TryFrom<&TxHelper<'a>> for Tx {
                 kaddr = Some(acc.get_address()?);
             }
         }
+        for ele in helper.ins.iter() {
+            let acc = accpool.account(&ele.addr)?;
+            if acc.scheme() == SigScheme::Schnorr {
+                tx.ver = tx.ver.max(consts::TX_VER_SCHNORR);
+            }
+            tx.ins.push(TxIn {
+                out: ele.coin.tx.clone(),
+                idx: ele.coin.idx,
+                //未签名脚本
+                script: Script::new_script_in_with(&ele.datas, &acc)?,
+                seq: helper.seq,
+            });
+            ifee += ele.coin.value;
+            if kaddr.is_none() {
+                kaddr = Some(acc.get_address()?);
+            }
+        }
         for ele in helper.outs.iter() {
-            //不允许金额为0的输出
-            if ele.value == 0 {
+            //不允许金额为0的输出,数据输出除外
+            if ele.value == 0 && !ele.is_data() {
                 continue;
             }
-            let addr = Account::decode(&ele.addr)?;
             let mut outv = TxOut::default();
             outv.value = ele.value;
-            outv.script = Script::new_script_out(&addr)?;
+            outv.script = ele.get_script()?;
             tx.outs.push(outv);
             ofee += ele.value
         }
@@ -263,10 +396,7 @@ impl<'a> TxHelper<'a> {
     }
     /// 添加输出
     pub fn add_out(&mut self, addr: &str, coin: i64) -> Result<&mut Self, Error> {
-        self.outs.push(TxOutEle {
-            addr: addr.into(),
-            value: coin,
-        });
+        self.outs.push(TxOutEle::new(addr, coin));
         Ok(self)
     }
     /// 设置使用的金额
@@ -284,15 +414,266 @@ impl<'a> TxHelper<'a> {
         self.ver = ver;
         Ok(self)
     }
+    /// 添加自定义输入
+    pub fn add_in(&mut self, ele: &TxInEle) -> Result<&mut Self, Error> {
+        self.ins.push(ele.clone());
+        Ok(self)
+    }
+    /// 使用赎回脚本消费脚本hash金额,datas放在赎回脚本之前
+    pub fn add_sh_in(
+        &mut self,
+        coin: &CoinAttr,
+        redeem: &Script,
+        datas: &[Vec<u8>],
+        addr: &str,
+    ) -> Result<&mut Self, Error> {
+        let mut datas = datas.to_vec();
+        datas.push(redeem.bytes().to_vec());
+        self.add_in(&TxInEle::new(coin, addr, &datas))
+    }
+    /// 添加数据输出,金额为0
+    pub fn add_data(&mut self, data: &[u8]) -> Result<&mut Self, Error> {
+        let script = Script::new_script_data(data)?;
+        self.outs.push(TxOutEle::with_script(&script, 0));
+        Ok(self)
+    }
+    /// 添加哈希时间锁输出
+    pub fn add_htlc_out(&mut self, htlc: &Htlc, coin: i64) -> Result<&mut Self, Error> {
+        let script = Script::new_script_htlc(htlc)?;
+        self.outs.push(TxOutEle::with_script(&script, coin));
+        Ok(self)
+    }
+    /// 接收账户使用原像消费哈希时间锁金额
+    pub fn add_htlc_claim(
+        &mut self,
+        coin: &CoinAttr,
+        preimage: &[u8],
+        addr: &str,
+    ) -> Result<&mut Self, Error> {
+        self.add_in(&TxInEle::new(coin, addr, &[preimage.to_vec()]))
+    }
+    /// 退款账户在锁定时间后取回哈希时间锁金额
+    pub fn add_htlc_refund(
+        &mut self,
+        coin: &CoinAttr,
+        htlc: &Htlc,
+        addr: &str,
+    ) -> Result<&mut Self, Error> {
+        //交易锁定时间不能小于合约锁定时间
+        self.set_lock_time(self.lock.max(htlc.lock))?;
+        self.add_in(&TxInEle::new(coin, addr, &[]))
+    }
+    /// 设置交易锁定时间,交易版本至少为TX_VER_LOCK
+    /// 小于LOCKTIME_THRESHOLD为区块高度,否则为unix时间戳
+    pub fn set_lock_time(&mut self, lock: u32) -> Result<&mut Self, Error> {
+        self.ver = self.ver.max(consts::TX_VER_LOCK);
+        self.lock = lock;
+        Ok(self)
+    }
+    /// 设置所有输入的序列号(相对锁定),交易版本至少为TX_VER_LOCK
+    pub fn set_sequence(&mut self, seq: u32) -> Result<&mut Self, Error> {
+        self.ver = self.ver.max(consts::TX_VER_LOCK);
+        self.seq = seq;
+        Ok(self)
+    }
+    /// 按费率计算交易费,fee_rate为每1000字节的交易费
+    fn rate_fee(fee_rate: i64, size: usize) -> i64 {
+        fee_rate * size as i64 / 1000
+    }
+    /// 估算账户签名后的输入大小,签名按最大长度计算
+    fn input_size(acc: &Account, datas: &[Vec<u8>]) -> Result<usize, Error> {
+        let inv = TxIn {
+            script: Script::new_script_in_with(datas, acc)?,
+            ..TxIn::default()
+        };
+        //每个签名最大72字节和1字节长度,脚本数据长度前缀可能增加
+        Ok(inv.pack().len() + acc.pubs_size() as usize * (consts::MAX_SIG_SIZE + 1) + 3)
+    }
+    /// 从账户addr自动选择金额并按费率设置交易费,需要在添加输出后调用
+    /// fee_rate为每1000字节的交易费,交易大小按签名后估算
+    /// 剩余金额小于粉尘值时不找零,剩余金额作为交易费
+    pub fn select_coins(
+        &mut self,
+        addr: &str,
+        strategy: CoinSelect,
+        fee_rate: i64,
+    ) -> Result<&mut Self, Error> {
+        if fee_rate < 0 {
+            return Error::msg("fee rate error");
+        }
+        let accpool = self.ctx.get_account_pool()?;
+        let acc = accpool.account(addr)?;
+        let dust = self.ctx.config()?.policy.dust;
+        //没有输入时的交易大小
+        let mut tx = Tx {
+            ver: self.ver,
+            lock: self.lock,
+            ..Tx::default()
+        };
+        let mut need: i64 = 0;
+        for ele in self.outs.iter().filter(|v| v.value != 0 || v.is_data()) {
+            tx.outs.push(TxOut {
+                value: ele.value,
+                script: ele.get_script()?,
+            });
+            need += ele.value;
+        }
+        let mut fixed = tx.get_size();
+        //已经设置的输入
+        for coin in self.coins.iter() {
+            let acc = accpool.account(&coin.cpk.string()?)?;
+            fixed += Self::input_size(&acc, &[])?;
+            need -= coin.value;
+        }
+        for ele in self.ins.iter() {
+            let acc = accpool.account(&ele.addr)?;
+            fixed += Self::input_size(&acc, &ele.datas)?;
+            need -= ele.coin.value;
+        }
+        //找零输出大小
+        let kaddr = match self.kaddr {
+            Some(ref v) => v.clone(),
+            None => acc.get_address()?,
+        };
+        let outk = TxOut {
+            script: Script::new_script_out(&kaddr)?,
+            ..TxOut::default()
+        };
+        let ksize = outk.pack().len();
+        //每个金额扣除输入交易费后的有效金额
+        let isize = Self::input_size(&acc, &[])?;
+        let ifee = (fee_rate * isize as i64 + 999) / 1000;
+        let spendable: Vec<CoinAttr> = self
+            .ctx
+            .spendable_coins(&acc)?
+            .into_iter()
+            .filter(|v| v.value > ifee)
+            .filter(|v| !self.coins.iter().any(|c| c.tx == v.tx && c.idx == v.idx))
+            .collect();
+        let target = need + Self::rate_fee(fee_rate, fixed);
+        //不找零时允许多支付的交易费
+        let kcost = Self::rate_fee(fee_rate, ksize) + dust;
+        //跳过已锁定的金额选择,返回选中的金额和交易费
+        let select =
+            |locked: &dyn Fn(&Hasher, u16) -> bool| -> Result<(Vec<CoinAttr>, i64), Error> {
+                let mut coins: Vec<&CoinAttr> =
+                    spendable.iter().filter(|v| !locked(&v.tx, v.idx)).collect();
+                let mut sel = None;
+                if strategy == CoinSelect::BranchAndBound {
+                    coins.sort_by_key(|v| Reverse(v.value));
+                    let values: Vec<i64> = coins.iter().map(|v| v.value - ifee).collect();
+                    sel = branch_and_bound(&values, target, target + kcost);
+                }
+                //无精确匹配时按策略排序累加选择
+                let sel = match sel {
+                    Some(idxs) => idxs,
+                    None => {
+                        match strategy {
+                            CoinSelect::OldestFirst => coins.sort_by_key(|v| v.height),
+                            _ => coins.sort_by_key(|v| Reverse(v.value)),
+                        }
+                        let mut sum = 0;
+                        let mut idxs = vec![];
+                        for (i, coin) in coins.iter().enumerate() {
+                            if sum >= target {
+                                break;
+                            }
+                            sum += coin.value - ifee;
+                            idxs.push(i);
+                        }
+                        if sum < target {
+                            return Error::msg("coins not enough");
+                        }
+                        idxs
+                    }
+                };
+                //按选中的金额计算交易费
+                let size = fixed + isize * sel.len();
+                let remain = sel.iter().map(|&i| coins[i].value).sum::<i64>() - need;
+                let kfee = Self::rate_fee(fee_rate, size + ksize);
+                let tfee = if remain - kfee >= dust {
+                    kfee
+                } else if remain >= Self::rate_fee(fee_rate, size) {
+                    remain
+                } else {
+                    return Error::msg("coins not enough");
+                };
+                Ok((sel.iter().map(|&i| coins[i].clone()).collect(), tfee))
+            };
+        //设置锁定时在钱包锁内选择并锁定选中的金额和已添加的输入
+        let (coins, tfee) = match self.clock {
+            Some(persist) => {
+                let mut ret = None;
+                let used: Vec<(Hasher, u16)> = self
+                    .coins
+                    .iter()
+                    .chain(self.ins.iter().map(|v| &v.coin))
+                    .map(|v| (v.tx.clone(), v.idx))
+                    .collect();
+                accpool.select_lock_coins(persist, &mut |locked| {
+                    let (coins, tfee) = select(locked)?;
+                    let mut keys = used.clone();
+                    keys.extend(coins.iter().map(|v| (v.tx.clone(), v.idx)));
+                    ret = Some((coins, tfee, keys.clone()));
+                    Ok(keys)
+                })?;
+                let (coins, tfee, keys) = ret.ok_or(Error::error("select coins error"))?;
+                self.locked.extend(keys);
+                (coins, tfee)
+            }
+            None => select(&|tx, idx| accpool.is_locked_coin(tx, idx))?,
+        };
+        self.tfee = tfee;
+        self.coins.extend(coins);
+        Ok(self)
+    }
+    /// 自动选择金额时锁定选中的金额和已添加的输入,选择和锁定在钱包锁内完成
+    /// 避免并发创建的交易选择相同的金额,金额被区块中的交易消费后自动解锁
+    /// persist为true时保存锁定,重新打开钱包后仍然锁定
+    pub fn set_coin_lock(&mut self, persist: bool) -> Result<&mut Self, Error> {
+        self.clock = Some(persist);
+        Ok(self)
+    }
+    /// 选择金额时锁定的输出
+    pub fn locked_coins(&self) -> &Vec<(Hasher, u16)> {
+        &self.locked
+    }
+    /// 解除选择金额时的锁定,交易签名或者进入交易池失败时回滚
+    pub fn release_coin_lock(&mut self) -> Result<&mut Self, Error> {
+        self.ctx.get_account_pool()?.release_coins(&self.locked)?;
+        self.locked.clear();
+        Ok(self)
+    }
+    /// 生成交易并添加到交易池,失败时解除选择金额时的锁定
+    pub fn append(&mut self) -> Result<Hasher, Error> {
+        let ret = Tx::try_from(&*self).and_then(|tx| self.ctx.append(&tx));
+        if ret.is_err() {
+            self.release_coin_lock()?;
+        }
+        ret
+    }
+    /// 生成待签名交易,用于导出到离线签名器
+    /// 输入账户可以是只读账户,不能设置签名器
+    pub fn partial(&self) -> Result<PartialTx, Error> {
+        if self.signer.is_some() {
+            return Error::msg("partial tx can't set signer");
+        }
+        PartialTx::new(self.ctx, &Tx::try_from(self)?)
+    }
     pub fn new(ctx: &'a Chain) -> Self {
         TxHelper {
             ver: 1,
+            lock: 0,
+            seq: 0,
             coins: vec![],
+            ins: vec![],
             outs: vec![],
             tfee: 0,
             ctx: ctx,
             kaddr: None,
             signer: None,
+            clock: None,
+            locked: vec![],
         }
     }
 }
@@ -301,14 +682,12 @@ impl<'a> TxHelper<'a> {
 pub struct TxPool {
     byid: HashMap<IKey, Arc<Tx>>,       //按交易id存储
     byfee: BTreeMap<i64, Vec<Arc<Tx>>>, //按交易金额排序
+    policy: Policy,                     //接收策略
 }
 
 impl Default for TxPool {
     fn default() -> Self {
-        TxPool {
-            byid: HashMap::<IKey, Arc<Tx>>::default(),
-            byfee: BTreeMap::<i64, Vec<Arc<Tx>>>::default(),
-        }
+        Self::new(&Policy::default())
     }
 }
 
@@ -364,8 +743,20 @@ impl TxPool {
             fee: 0,
         }
     }
+    /// 使用接收策略创建交易池
+    pub fn new(policy: &Policy) -> Self {
+        TxPool {
+            byid: HashMap::<IKey, Arc<Tx>>::default(),
+            byfee: BTreeMap::<i64, Vec<Arc<Tx>>>::default(),
+            policy: policy.clone(),
+        }
+    }
+    /// 获取接收策略
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
     /// 检测交易是否可进行交易池
-    fn check_value(&self, tx: &Tx) -> Result<(), Error> {
+    fn check_value(&self, tx: &Tx, fee: i64) -> Result<(), Error> {
         //必须有输入和输出
         if tx.ins.len() == 0 || tx.outs.len() == 0 {
             return Error::msg("ins or outs empy");
@@ -393,14 +784,15 @@ impl TxPool {
         if !consts::is_valid_amount(ofee) {
             return Error::msg("out fee error");
         }
-        Ok(())
+        //共识规则之外的接收策略
+        self.policy.check_tx(tx, fee)
     }
     /// 添加交易,添加前需要检测是否合法
     /// fee要先计算出来
     /// 返回交易id
     pub fn push(&mut self, tx: &Tx, fee: i64) -> Result<Hasher, Error> {
         //检测是否可进入交易池
-        self.check_value(tx)?;
+        self.check_value(tx, fee)?;
         let id = tx.id()?;
         let ref key: IKey = id.as_ref().into();
         let rtx = Arc::new(tx.clone());
@@ -425,7 +817,7 @@ impl TxPool {
                 for vs in self.byfee.iter_mut() {
                     vs.1.retain(|vtx| {
                         if let Ok(ref tmp) = vtx.id() {
-                            tmp == id
+                            tmp != id
                         } else {
                             false
                         }
@@ -454,12 +846,16 @@ impl TxPool {
     }
     /// 获取账户输出金额
     /// 来自交易池的金额是不能直接消费的被标记为 COIN_ATTR_FLAGS_TXPOOL
-    pub fn coins(&self, acc: &Account) -> Result<Vec<CoinAttr>, Error> {
+    pub fn coins<T: HasAddress>(&self, acc: &T) -> Result<Vec<CoinAttr>, Error> {
         let addr = acc.get_address()?;
         let mut coins = vec![];
         for tx in self.byid.iter() {
             let id = tx.1.id()?;
             for (idx, outv) in tx.1.outs.iter().enumerate() {
+                //数据输出没有金额
+                if outv.is_data() {
+                    continue;
+                }
                 let mut coin = CoinAttr::default();
                 //只获取属于acc的金额
                 coin.cpk = outv.get_address()?;
@@ -501,6 +897,32 @@ impl TxPool {
     }
 }
 
+#[test]
+fn test_txpool_remove_same_fee() {
+    let acc = Account::new(1, 1, false, true).unwrap();
+    let mut pool = TxPool::default();
+    let mut ids = vec![];
+    for i in 0..3u16 {
+        let mut tx = Tx::default();
+        let mut inv = TxIn::default();
+        inv.out = Hasher::hash("ref".as_bytes());
+        inv.idx = i;
+        inv.script = Script::new_script_in(&acc).unwrap();
+        tx.ins.push(inv);
+        let mut outv = TxOut::default();
+        outv.value = consts::COIN;
+        outv.script = Script::new_script_out(&acc.hash().unwrap()).unwrap();
+        tx.outs.push(outv);
+        ids.push(pool.push(&tx, consts::COIN).unwrap());
+    }
+    //相同交易费的其他交易必须保留
+    pool.remove(&ids[1]).unwrap();
+    assert_eq!(pool.len(), 2);
+    let left: Vec<Hasher> = pool.iter().map(|(_, tx)| tx.id().unwrap()).collect();
+    assert_eq!(left, vec![ids[0].clone(), ids[2].clone()]);
+    assert!(pool.remove(&ids[1]).is_err());
+}
+
 #[derive(PartialEq, Eq, Clone, PartialOrd, Ord, Debug)]
 pub struct IKey(Vec<u8>);
 
@@ -664,6 +1086,14 @@ impl CoinAttr {
     pub fn coin(&self) -> i64 {
         self.value
     }
+    /// 所在交易id
+    pub fn tx(&self) -> &Hasher {
+        &self.tx
+    }
+    /// 所在交易的输出索引
+    pub fn idx(&self) -> u16 {
+        self.idx
+    }
     /// 是否来自交易池
     pub fn is_txpool(&self) -> bool {
         self.flags & COIN_ATTR_FLAGS_TXPOOL != 0
@@ -785,6 +1215,35 @@ fn test_block_index_coin_save() {
     });
 }
 
+#[test]
+fn test_block_coinbase_height() {
+    use crate::script::{MAX_SCRIPT_CB_SIZE, SCRIPT_TYPE_CB};
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let mut blk = idx.new_block("height", &acc.string()?)?;
+        assert_eq!(blk.txs[0].ins[0].script.get_cb_height()?, 1);
+        //coinbase高度和链接高度不一致
+        blk.txs[0].ins[0].script = Script::new_script_cb(2, "height".as_bytes())?;
+        blk.finish()?;
+        idx.compute_pow(&mut blk)?;
+        assert!(idx.link(&blk).is_err());
+        //coinbase脚本超过最大长度
+        let mut script = Script::from(SCRIPT_TYPE_CB);
+        script.u32(1);
+        script.data(&[0u8; MAX_SCRIPT_CB_SIZE]);
+        blk.txs[0].ins[0].script = script;
+        blk.finish()?;
+        idx.compute_pow(&mut blk)?;
+        assert!(idx.link(&blk).is_err());
+        blk.txs[0].ins[0].script = Script::new_script_cb(1, "height".as_bytes())?;
+        blk.finish()?;
+        idx.compute_pow(&mut blk)?;
+        assert_eq!(idx.link(&blk)?.height, 1);
+        Ok(())
+    });
+}
+
 /// 链事件通知
 /// 在链环境中执行
 pub trait ChainListener: Sync + Send {
@@ -807,6 +1266,7 @@ pub struct BlkIndexer {
     conf: Config,                      //配置信息
     pool: TxPool,                      //交易内存池,获取到的新交易存在,按交易费从高到低存放
     acp: Option<Arc<dyn AccountPool>>, //账户池
+    sigcache: Arc<SigCache>,           //签名验证缓存,交易池和区块链接共享
 }
 
 /// 签名验证数据缓存
@@ -814,6 +1274,7 @@ pub struct LinkExectorCache {
     pub ver: u32,     //交易版本
     pub outs: Hasher, //输出hash缓存 tx.outs
     pub refs: Hasher, //引用hash缓存 tx.ins.out tx.ins.idx
+    pub lock: u32,    //交易锁定时间
 }
 
 impl LinkExectorCache {
@@ -832,6 +1293,7 @@ impl LinkExectorCache {
             ver: tx.ver,
             outs: Hasher::hash(outs.bytes()),
             refs: Hasher::hash(refs.bytes()),
+            lock: tx.lock,
         })
     }
 }
@@ -867,6 +1329,9 @@ pub trait TxSigner {
         inv.encode_sign(&mut w)?; //输入
         outv.encode_sign(&mut w)?; //引用的输出
         w.encode(&cache.outs); //输出hash
+        if cache.ver >= consts::TX_VER_LOCK {
+            w.u32(cache.lock); //锁定时间
+        }
         Ok(w)
     }
     /// 签名交易
@@ -887,13 +1352,25 @@ pub trait TxSigner {
         outv: &TxOut,
         acc: &Account,
     ) -> Result<bool, Error>;
+    /// 使用签名缓存验签,跳过已经验证通过的签名
+    fn verify_tx_with_cache(
+        &self,
+        cache: &LinkExectorCache,
+        inv: &TxIn,
+        outv: &TxOut,
+        acc: &Account,
+        sigcache: &SigCache,
+    ) -> Result<bool, Error> {
+        let msg = self.get_sign_bytes(cache, inv, outv)?;
+        acc.verify_with_cache(msg.bytes(), sigcache)
+    }
 }
 
 /// 全签名验签处理
 pub struct FullSigner {}
 
 //根据当前交易获取签名验签
-pub fn new_tx_signer(_ctx: &mut BlkIndexer, _tx: &Tx) -> Box<dyn TxSigner> {
+pub fn new_tx_signer(_ctx: &mut BlkIndexer, _tx: &Tx) -> Box<dyn TxSigner + Send> {
     Box::new(FullSigner {})
 }
 
@@ -918,8 +1395,9 @@ impl TxSigner for FullSigner {
         let msg = self.get_sign_bytes(cache, inv, outv)?;
         //全部签名
         acc.sign_full(msg.bytes())?;
-        //返回包含签名的脚本
-        Script::new_script_in(&acc)
+        //返回包含签名的脚本,保留账户前的数据
+        let (datas, _) = inv.script.get_in_datas()?;
+        Script::new_script_in_with(&datas, &acc)
     }
     /// 验签,acc需要包含公钥
     fn verify_tx(
@@ -934,19 +1412,205 @@ impl TxSigner for FullSigner {
     }
 }
 
+/// 检测输入账户是否有权消费标准模板输出
+/// 标准输出:账户地址必须等于输出地址
+/// 哈希时间锁:账户地址必须是接收方或者退款方
+/// 脚本hash:赎回脚本hash必须一致,赎回脚本是标准模板时按同样规则检测
+/// 其他自定义脚本的消费权限只由脚本决定
+fn check_tx_spender(inv: &TxIn, script: &Script) -> Result<(), Error> {
+    let (mut datas, acc) = inv.script.get_in_datas()?;
+    let addr = acc.get_address()?;
+    if let Ok(hv) = script.get_out_hash() {
+        if addr != hv {
+            return Error::msg("cost addr != out addr");
+        }
+        return Ok(());
+    }
+    if let Ok(htlc) = script.get_htlc() {
+        if addr != htlc.recipient && addr != htlc.refund {
+            return Error::msg("cost addr != htlc addr");
+        }
+        return Ok(());
+    }
+    if let Ok(hv) = script.get_sh() {
+        //赎回脚本为账户前的最后一个数据
+        let redeem = match datas.pop() {
+            Some(d) => Script::from_bytes(&d)?,
+            None => return Error::msg("redeem script miss"),
+        };
+        if Hasher::hash(redeem.bytes()) != hv {
+            return Error::msg("redeem hash != out sh");
+        }
+        if redeem.get_out_hash().is_ok() || redeem.get_htlc().is_ok() {
+            return check_tx_spender(inv, &redeem);
+        }
+    }
+    Ok(())
+}
+
+/// 批量验证交易输入账户的Schnorr签名,通过后写入签名缓存,执行脚本时直接命中
+/// 签名数量不足的账户由脚本处理,不加入批次
+fn batch_tx_schnorr(
+    tx: &Tx,
+    outs: &[Option<TxOut>],
+    cache: &LinkExectorCache,
+    signer: &dyn TxSigner,
+    sigcache: &SigCache,
+) -> bool {
+    if tx.ver < consts::TX_VER_SCHNORR {
+        return false;
+    }
+    let mut batch = SchnorrBatch::default();
+    for (inv, outv) in tx.ins.iter().zip(outs.iter()) {
+        let outv = match outv {
+            Some(outv) => outv,
+            None => continue,
+        };
+        let acc = match inv.script.get_in_datas() {
+            Ok((_, acc)) if acc.scheme() == SigScheme::Schnorr => acc,
+            _ => continue,
+        };
+        let items = match acc.schnorr_sigs() {
+            Ok(Some(items)) => items,
+            _ => continue,
+        };
+        let msg = match signer.get_sign_bytes(cache, inv, outv) {
+            Ok(msg) => msg,
+            Err(_) => return false,
+        };
+        for (pb, sig) in items {
+            if batch.push(msg.bytes(), pb, sig).is_err() {
+                return false;
+            }
+        }
+    }
+    //单个签名没有必要批量验证
+    batch.len() > 1 && batch.verify_with_cache(sigcache)
+}
+
+/// 执行交易所有输入脚本,不访问链数据,可在工作线程执行
+/// outs:每个输入引用的输出,coinbase输入为None
+/// 失败时返回第一个失败的输入位置和错误
+fn check_tx_script(
+    tx: &Tx,
+    outs: &[Option<TxOut>],
+    signer: &dyn TxSigner,
+    sigcache: &SigCache,
+) -> Result<(), (usize, Error)> {
+    let cache = signer.get_sign_cache(tx).map_err(|err| (0, err))?;
+    //批量失败时由脚本逐个验签定位错误的输入
+    batch_tx_schnorr(tx, outs, &cache, signer, sigcache);
+    for (i, (inv, outv)) in tx.ins.iter().zip(outs.iter()).enumerate() {
+        //coinbase交易没有签名
+        let outv = match outv {
+            Some(outv) => outv,
+            None => continue,
+        };
+        with_txin_env(tx, inv, outv, &cache, signer, sigcache, |script, env| {
+            Exector::new().verify(script, env)
+        })
+        .map_err(|err| (i, err))?;
+    }
+    Ok(())
+}
+
+/// 使用区块链接的验签环境执行输入和引用输出连接后的脚本
+/// 交易池,区块链接和跟踪调试使用相同的环境,保证脚本结果一致
+fn with_txin_env<T, F>(
+    tx: &Tx,
+    inv: &TxIn,
+    outv: &TxOut,
+    cache: &LinkExectorCache,
+    signer: &dyn TxSigner,
+    sigcache: &SigCache,
+    f: F,
+) -> T
+where
+    F: FnOnce(&Script, &LinkExectorEnv) -> T,
+{
+    //验证签名环境
+    let env = &LinkExectorEnv {
+        tx,
+        inv,
+        outv,
+        cache,
+        singer: Box::new(signer),
+        sigcache: Some(sigcache),
+    };
+    //连接输入输出脚本允许检测脚本
+    let mut script = inv.script.clone();
+    let script = script.concat(&outv.script);
+    f(script, env)
+}
+
 /// 区块链接脚本执行签名验证
 struct LinkExectorEnv<'a> {
-    tx: &'a Tx,                    //当前交易
-    inv: &'a TxIn,                 //当前输入
-    outv: &'a TxOut,               //输入引用的输出
-    cache: &'a LinkExectorCache,   //执行交易缓存
-    singer: Box<&'a dyn TxSigner>, //签名验签
+    tx: &'a Tx,                     //当前交易
+    inv: &'a TxIn,                  //当前输入
+    outv: &'a TxOut,                //输入引用的输出
+    cache: &'a LinkExectorCache,    //执行交易缓存
+    singer: Box<&'a dyn TxSigner>,  //签名验签
+    sigcache: Option<&'a SigCache>, //签名验证缓存
+}
+
+impl<'a> LinkExectorEnv<'a> {
+    //验签,存在签名缓存时使用缓存
+    fn verify_acc(&self, acc: &Account) -> Result<bool, Error> {
+        match self.sigcache {
+            Some(sigcache) => self
+                .singer
+                .verify_tx_with_cache(self.cache, self.inv, self.outv, acc, sigcache),
+            None => self.singer.verify_tx(self.cache, self.inv, self.outv, acc),
+        }
+    }
 }
 
 impl<'a> ExectorEnv for LinkExectorEnv<'a> {
     fn verify_sign(&self, ele: &Ele) -> Result<bool, Error> {
         let acc: Account = ele.try_into()?;
-        self.singer.verify_tx(self.cache, self.inv, self.outv, &acc)
+        self.verify_acc(&acc)
+    }
+    fn verify_schnorr(&self, acc: &Account) -> Result<bool, Error> {
+        if self.tx.ver < consts::TX_VER_SCHNORR {
+            return Error::msg("schnorr sign tx ver error");
+        }
+        //签名数量不足返回false,存在错误的签名立即返回错误
+        self.verify_acc(acc)
+    }
+    fn check_lock_time(&self, lock: i64) -> Result<bool, Error> {
+        let (tx, lock) = (self.tx, lock as u32);
+        if !tx.has_lock() || tx.lock == 0 {
+            return Ok(false);
+        }
+        //输入序列号为SEQUENCE_FINAL时交易锁定时间可能不生效
+        if self.inv.seq == consts::SEQUENCE_FINAL {
+            return Ok(false);
+        }
+        //锁定类型必须一致
+        if (lock < consts::LOCKTIME_THRESHOLD) != tx.is_lock_height() {
+            return Ok(false);
+        }
+        //交易的锁定时间在链接时检测
+        Ok(lock <= tx.lock)
+    }
+    fn check_sequence(&self, seq: i64) -> Result<bool, Error> {
+        let seq = seq as u32;
+        //脚本未启用相对锁定
+        if seq & consts::SEQUENCE_DISABLE_FLAG != 0 {
+            return Ok(true);
+        }
+        if !self.tx.has_lock() {
+            return Ok(false);
+        }
+        let inv = TxIn {
+            seq,
+            ..TxIn::default()
+        };
+        //输入的相对锁定在链接时检测
+        match (inv.get_seq_lock(), self.inv.get_seq_lock()) {
+            (Some((t1, v1)), Some((t2, v2))) => Ok(t1 == t2 && v1 <= v2),
+            _ => Ok(false),
+        }
     }
 }
 
@@ -1002,8 +1666,9 @@ impl BlkIndexer {
             blk: Store::new(&blkdir, "blk", Self::MAX_FILE_SIZE)?,
             rev: Store::new(&blkdir, "rev", Self::MAX_FILE_SIZE)?,
             conf: conf.clone(),
-            pool: TxPool::default(),
+            pool: TxPool::new(&conf.policy),
             acp: None,
+            sigcache: Arc::new(SigCache::default()),
         })
     }
     /// 创建交易池迭代器
@@ -1073,7 +1738,7 @@ impl BlkIndexer {
         }
     }
     /// 获取账户对应的金额列表
-    fn coins(&self, acc: &Account) -> Result<Vec<CoinAttr>, Error> {
+    fn coins<T: HasAddress>(&self, acc: &T) -> Result<Vec<CoinAttr>, Error> {
         let mut coins: Vec<CoinAttr> = vec![];
         let hash = acc.get_address()?;
         let akey: IKey = hash.as_ref().into();
@@ -1098,6 +1763,16 @@ impl BlkIndexer {
         coins.append(&mut pcoins);
         Ok(coins)
     }
+    /// 获取下个区块可消费的金额
+    /// 不包含交易池中的金额,未成熟的coinbase金额和已经在交易池中消费的金额
+    fn spendable_coins<T: HasAddress>(&self, acc: &T) -> Result<Vec<CoinAttr>, Error> {
+        let next = self.best()?.next();
+        let coins = self.coins(acc)?;
+        Ok(coins
+            .into_iter()
+            .filter(|v| v.is_valid(next) && !self.pool.is_cost_coin(&v.tx, v.idx))
+            .collect())
+    }
     /// 获取属性信息
     fn attr<T>(&self, k: &IKey) -> Result<T, Error>
     where
@@ -1187,31 +1862,87 @@ impl BlkIndexer {
         }
         Ok(tfee)
     }
+    /// 获取交易每个输入引用的输出,coinbase输入为None
+    fn get_tx_sign_outs(&mut self, tx: &Tx) -> Result<Vec<Option<TxOut>>, Error> {
+        let mut outs = vec![];
+        for inv in tx.ins.iter() {
+            if inv.is_coinbase() {
+                outs.push(None);
+            } else {
+                outs.push(Some(self.get_txin_ref_txout(inv)?));
+            }
+        }
+        Ok(outs)
+    }
     /// 检测交易签名
+    /// 交易进入交易池时验证通过的签名进入缓存,区块链接时不再重复计算
     fn check_tx_sign(&mut self, tx: &Tx) -> Result<(), Error> {
-        //获取签名器缓存
+        let outs = self.get_tx_sign_outs(tx)?;
+        let signer = new_tx_signer(self, tx);
+        check_tx_script(tx, &outs, &*signer, &self.sigcache).map_err(|(_, err)| err)
+    }
+    /// 跟踪执行交易第idx个输入和引用输出的脚本
+    fn trace_txin(&mut self, tx: &Tx, idx: usize) -> Result<Trace, Error> {
+        let inv = tx
+            .ins
+            .get(idx)
+            .ok_or(Error::error("txin idx out of range"))?;
+        if inv.is_coinbase() {
+            return Error::msg("coinbase txin no script exec");
+        }
         let signer = new_tx_signer(self, tx);
         let cache = signer.get_sign_cache(&tx)?;
+        let outv = self.get_txin_ref_txout(inv)?;
+        //和check_tx_sign使用相同的环境,跟踪结果和共识结果一致
+        let trace = with_txin_env(
+            tx,
+            inv,
+            &outv,
+            &cache,
+            &*signer,
+            &self.sigcache,
+            |script, env| Exector::new().trace_verify(script, env),
+        );
+        Ok(trace)
+    }
+    /// 获取height高度及之前MEDIAN_TIME_SPAN个区块的中位时间(unix时间戳)
+    fn median_time(&self, height: u32) -> Result<i64, Error> {
+        let mut times = vec![];
+        let mut h = height as i64;
+        while h >= 0 && times.len() < consts::MEDIAN_TIME_SPAN as usize {
+            let id: Hasher = self.attr(&(h as u32).into())?;
+            let attr: BlkAttr = self.attr(&id.as_ref().into())?;
+            times.push(attr.bhv.get_timestamp());
+            h -= 1;
+        }
+        times.sort();
+        Ok(times[times.len() / 2] + consts::BASE_UTC_UNIX_TIME)
+    }
+    /// 检测交易在height高度是否满足锁定时间和输入的相对锁定
+    fn check_tx_lock(&mut self, height: u32, tx: &Tx) -> Result<(), Error> {
+        if !tx.has_lock() || tx.is_coinbase() || height == 0 {
+            return Ok(());
+        }
+        //时间锁定使用上个区块的中位时间
+        let mtp = self.median_time(height - 1)?;
+        if !tx.is_final(height, mtp) {
+            return Error::msg("tx locktime not final");
+        }
         for inv in tx.ins.iter() {
-            //coinbase交易没有签名
-            if inv.is_coinbase() {
-                continue;
-            }
-            //获取引用的输出
-            let outv = self.get_txin_ref_txout(&inv)?;
-            //验证签名环境
-            let env = &LinkExectorEnv {
-                tx: &tx,
-                inv: &inv,
-                outv: &outv,
-                cache: &cache,
-                singer: Box::new(&*signer),
+            let (istime, value) = match inv.get_seq_lock() {
+                Some(v) => v,
+                None => continue,
             };
-            //连接输入输出脚本允许检测脚本
-            let mut script = inv.script.clone();
-            let script = script.concat(&outv.script);
-            let mut exector = Exector::new();
-            exector.exec(&script, env)?;
+            let coin = self.get_txin_ref_coin(inv)?;
+            if istime {
+                //从金额所在区块的上个区块中位时间开始计算
+                let start = self.median_time(coin.height.max(1) - 1)?;
+                if start + value as i64 > mtp {
+                    return Error::msg("tx sequence lock not final");
+                }
+            } else if coin.height.saturating_add(value) > height {
+                return Error::msg("tx sequence lock not final");
+            }
         }
         Ok(())
     }
@@ -1219,19 +1950,19 @@ impl BlkIndexer {
     /// 返回tfee(交易费),cfee(coinbase输出)
     fn check_tx_amount(&mut self, height: u32, tx: &Tx) -> Result<(i64, i64), Error> {
         let (mut ofee, mut ifee, mut tfee, mut cfee) = (0, 0, 0, 0);
+        //检测锁定时间
+        self.check_tx_lock(height, tx)?;
         for inv in tx.ins.iter() {
             //coinbase交易不包含金额信息
             if inv.is_coinbase() {
                 continue;
             }
-            //获取消费地址hasher
-            let addr = inv.get_address()?;
-            //获取引用的输出
+            //获取引用的输出,签名由脚本验签检测
             let outv = self.get_txin_ref_txout(&inv)?;
-            //地址是否一致
-            if addr != outv.get_address()? {
-                return Error::msg("cost addr != out addr");
-            }
+            //消费账户是否和模板输出地址一致
+            check_tx_spender(inv, &outv.script)?;
+            //金额使用输出地址存储
+            let addr = outv.get_address()?;
             //获取引用的金额
             let coin = self.get_coin(&addr, &inv.out, inv.idx)?;
             //地址是否一致
@@ -1253,7 +1984,7 @@ impl BlkIndexer {
             if !consts::is_valid_amount(outv.value) {
                 return Error::msg("outv value error");
             }
-            if outv.value == 0 {
+            if outv.value == 0 && !outv.is_data() {
                 return Error::msg("outv value error");
             }
             if tx.is_coinbase() {
@@ -1306,7 +2037,14 @@ impl BlkIndexer {
     /// 检测区块的金额
     /// 这个检测的区块是新连入的区块,需要检测引用的coin是否在链中
     /// 并且检测签名是否正确
-    fn check_block_amount(&mut self, height: u32, blk: &Block) -> Result<(), Error> {
+    /// 交易输入脚本在线程池中并行验证,金额检测同时在当前线程执行
+    /// 按区块中交易顺序返回第一个错误,与线程执行顺序无关
+    fn check_block_amount(
+        &mut self,
+        height: u32,
+        blk: &Block,
+        tpool: Option<&ThreadPool>,
+    ) -> Result<(), Error> {
         //coinbase输出，输入, 输出, 交易费, 区块奖励
         //1.每个交易的输出<=输入,差值就是交易费用，可包含在coinbase输出中
         //3.coinbase输出 <= (区块奖励+交易费)
@@ -1314,11 +2052,59 @@ impl BlkIndexer {
         if !consts::is_valid_amount(rfee) {
             return Error::msg("rfee  error");
         }
+        //每个交易的签名检测错误
+        let mut errs: Vec<Option<Error>> = vec![None; blk.txs.len()];
+        let (sender, receiver) = mpsc::channel();
+        let mut jobs = 0;
+        for (i, tx) in blk.txs.iter().enumerate() {
+            if tx.ins.iter().all(|inv| inv.is_coinbase()) {
+                continue;
+            }
+            //引用的输出在当前线程获取
+            let outs = match self.get_tx_sign_outs(tx) {
+                Ok(outs) => outs,
+                Err(err) => {
+                    errs[i] = Some(err);
+                    continue;
+                }
+            };
+            let signer = new_tx_signer(self, tx);
+            let sigcache = self.sigcache.clone();
+            let sender = sender.clone();
+            let tx = tx.clone();
+            let job = move || {
+                let ret = check_tx_script(&tx, &outs, &*signer, &sigcache);
+                let _ = sender.send((i, ret));
+            };
+            match tpool {
+                Some(tpool) => tpool.execute(job),
+                None => job(),
+            }
+            jobs += 1;
+        }
+        drop(sender);
+        //检测交易金额,并返回交易费和coin输出金额(如果是coinbase交易)
+        let mut amounts = vec![];
         for tx in blk.txs.iter() {
+            amounts.push(self.check_tx_amount(height, tx));
+        }
+        //等待所有签名检测完成
+        for (i, ret) in receiver.iter() {
+            jobs -= 1;
+            if let Err((idx, err)) = ret {
+                let msg = format!("tx {} txin {} {}", i, idx, err.as_str());
+                errs[i] = Some(Error::error(&msg));
+            }
+        }
+        if jobs != 0 {
+            return Error::msg("check tx sign job error");
+        }
+        for (i, amount) in amounts.into_iter().enumerate() {
             //检测交易签名
-            self.check_tx_sign(&tx)?;
-            //检测交易金额,并返回交易费和coin输出金额(如果是coinbase交易)
-            let (tfeev, cfeev) = self.check_tx_amount(height, &tx)?;
+            if let Some(err) = errs[i].take() {
+                return Err(err);
+            }
+            let (tfeev, cfeev) = amount?;
             //累加交易费
             tfee += tfeev;
             if !consts::is_valid_amount(tfee) {
@@ -1346,7 +2132,8 @@ impl BlkIndexer {
     /// block id->block attr 区块id对应的区块信息
     /// blk data 区块数据
     /// rev data 回退数据
-    fn link(&mut self, blk: &Block) -> Result<Best, Error> {
+    /// tpool:签名验证使用的线程池,None时在当前线程验证
+    fn link(&mut self, blk: &Block, tpool: Option<&ThreadPool>) -> Result<Best, Error> {
         //检测基本数据
         blk.check_value(self)?;
         let id = blk.id()?;
@@ -1363,6 +2150,8 @@ impl BlkIndexer {
         if blk.header.bits != bits {
             return Error::msg("link block bits error");
         }
+        //coinbase高度必须和链接的高度一致
+        blk.check_cb_height(height)?;
         //开始写入
         let mut batch = IBatch::new(true);
         //最新best数据
@@ -1391,7 +2180,7 @@ impl BlkIndexer {
             }
         }
         //检测区块的金额和签名
-        self.check_block_amount(next.height, blk)?;
+        self.check_block_amount(next.height, blk, tpool)?;
         //高度对应的区块id
         batch.put(&next.height_key(), &next.id);
         //每个交易对应的区块信息和位置
@@ -1511,7 +2300,8 @@ impl BlkIndexer {
                 continue;
             }
             //获取引用的金额,这里是消费金额,必须存在
-            let coin = self.get_coin(&inv.get_address()?, &inv.out, inv.idx)?;
+            let outv = self.get_txin_ref_txout(inv)?;
+            let coin = self.get_coin(&outv.get_address()?, &inv.out, inv.idx)?;
             //在当前高度上是否成熟
             if !coin.is_valid(best.height) {
                 return Error::msg("ref coin not valid");
@@ -1521,6 +2311,10 @@ impl BlkIndexer {
         }
         //输出对应获取的金额
         for (i, outv) in tx.outs.iter().enumerate() {
+            //数据输出不可消费,不写入金额索引
+            if outv.is_data() {
+                continue;
+            }
             let mut coin = CoinAttr::default();
             coin.cpk = outv.get_address()?;
             coin.tx = tx.id()?;
@@ -1562,14 +2356,36 @@ impl BlkIndexer {
 /// 链线程安全封装
 pub struct Chain {
     idx: RwLock<BlkIndexer>,
-    event: Option<Box<dyn ChainListener>>,
+    event: RwLock<Vec<Arc<dyn ChainListener>>>,
     tpool: Mutex<ThreadPool>,
 }
 
 impl Chain {
-    /// 设置监听器
+    /// 设置监听器,替换已添加的所有监听器
     pub fn set_listener(&mut self, l: Box<dyn ChainListener>) {
-        self.event = Some(l);
+        if let Ok(mut v) = self.event.write() {
+            *v = vec![Arc::from(l)];
+        }
+    }
+    /// 添加监听器,链创建后也可添加
+    pub fn add_listener(&self, l: Arc<dyn ChainListener>) -> Result<(), Error> {
+        let mut ls = self.event.write().map_or_else(Error::std, Ok)?;
+        ls.push(l);
+        Ok(())
+    }
+    /// 按添加顺序通知所有监听器
+    fn notify<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: Fn(&dyn ChainListener) -> Result<(), Error>,
+    {
+        let ls = self
+            .event
+            .read()
+            .map_or_else(Error::std, |v| Ok(v.clone()))?;
+        for l in ls.iter() {
+            f(l.as_ref())?;
+        }
+        Ok(())
     }
     /// 计算某高度下的奖励
     pub fn compute_reward(&self, h: u32) -> Result<i64, Error> {
@@ -1587,6 +2403,10 @@ impl Chain {
     pub fn get_account_pool(&self) -> Result<Arc<dyn AccountPool>, Error> {
         self.do_read(|v| v.get_account_pool())
     }
+    /// 获取签名验证缓存
+    pub fn sig_cache(&self) -> Result<Arc<SigCache>, Error> {
+        self.do_read(|v| Ok(v.sigcache.clone()))
+    }
     /// 创建交易助手
     pub fn new_tx_helper(&self) -> TxHelper {
         TxHelper::new(self)
@@ -1612,7 +2432,12 @@ impl Chain {
             helper.add_out(addr, idx.compute_reward(0)?)?;
             let mut blk = Block::try_from(&helper)?;
             idx.compute_pow(&mut blk)?;
-            idx.link(&blk)?;
+            idx.link(&blk, None)?;
+            Ok(blk)
+        })
+        .and_then(|blk| {
+            self.release_coins(&blk.txs)?;
+            self.notify(|e| e.on_link_block(self, &blk))?;
             Ok(blk)
         })
     }
@@ -1638,7 +2463,7 @@ impl Chain {
     pub fn new(conf: &Config, acp: Arc<dyn AccountPool>) -> Result<Arc<Self>, Error> {
         let chain = Chain {
             idx: RwLock::new(BlkIndexer::new(conf)?),
-            event: None,
+            event: RwLock::new(vec![]),
             tpool: Mutex::new(ThreadPool::new(conf.pbnum)),
         };
         chain.do_write(|v| v.set_account_pool(acp))?;
@@ -1660,9 +2485,14 @@ impl Chain {
         self.do_read(|v| v.attr(k))
     }
     /// 获取账户对应的金额列表
-    pub fn coins(&self, acc: &Account) -> Result<Vec<CoinAttr>, Error> {
+    /// acc可以是账户或者地址hash,只读账户也可以查询
+    pub fn coins<T: HasAddress>(&self, acc: &T) -> Result<Vec<CoinAttr>, Error> {
         self.do_read(|v| v.coins(acc))
     }
+    /// 获取账户在下个区块可消费的金额
+    pub fn spendable_coins<T: HasAddress>(&self, acc: &T) -> Result<Vec<CoinAttr>, Error> {
+        self.do_read(|v| v.spendable_coins(acc))
+    }
     /// 获取交易信息
     pub fn get_tx(&self, k: &IKey) -> Result<Tx, Error> {
         let attr: TxAttr = self.attr(k)?;
@@ -1679,6 +2509,14 @@ impl Chain {
         //复制返回
         Ok(outv.clone())
     }
+    /// 获取输入引用的金额
+    pub fn get_txin_ref_coin(&self, inv: &TxIn) -> Result<CoinAttr, Error> {
+        self.do_write(|v| v.get_txin_ref_coin(inv))
+    }
+    /// 跟踪执行交易第idx个输入的脚本,用于诊断被拒绝的交易
+    pub fn trace_txin(&self, tx: &Tx, idx: usize) -> Result<Trace, Error> {
+        self.do_write(|v| v.trace_txin(tx, idx))
+    }
     /// 从属性加载区块信息
     pub fn load(&self, attr: &BlkAttr) -> Result<Arc<Block>, Error> {
         self.do_write(|v| v.load(attr))
@@ -1690,34 +2528,41 @@ impl Chain {
     }
     /// 链接一个新区块到链上
     pub fn link(&self, blk: &Block) -> Result<Best, Error> {
-        let best = self.do_write(|ctx| ctx.link(blk))?;
-        if let Some(e) = &self.event {
-            e.on_link_block(self, blk)?;
-        }
+        let best = self.do_write(|ctx| {
+            let tpool = self.tpool.lock().map_or_else(Error::std, Ok)?;
+            ctx.link(blk, Some(&tpool))
+        })?;
+        self.release_coins(&blk.txs)?;
+        self.notify(|e| e.on_link_block(self, blk))?;
         Ok(best)
     }
+    /// 清除交易消费的金额锁定,在链锁外调用
+    fn release_coins(&self, txs: &[Tx]) -> Result<(), Error> {
+        let keys: Vec<(Hasher, u16)> = txs
+            .iter()
+            .flat_map(|tx| tx.ins.iter())
+            .filter(|v| !v.is_coinbase())
+            .map(|v| (v.out.clone(), v.idx))
+            .collect();
+        self.get_account_pool()?.release_coins(&keys)
+    }
     /// 弹出一个区块
     pub fn pop(&self) -> Result<Arc<Block>, Error> {
         let blk = self.do_write(|v| v.pop())?;
-        if let Some(e) = &self.event {
-            e.on_pop_block(self, &*blk)?;
-        }
+        self.notify(|e| e.on_pop_block(self, &blk))?;
         Ok(blk)
     }
     /// 添加交易到交易池
     pub fn append(&self, tx: &Tx) -> Result<Hasher, Error> {
         let id = self.do_write(|v| v.append(tx))?;
-        if let Some(e) = &self.event {
-            e.on_append_tx(self, tx)?;
-        }
+        self.notify(|e| e.on_append_tx(self, tx))?;
         Ok(id)
     }
-    /// 从交易池移除交易
+    /// 从交易池移除交易,解除交易消费的金额锁定
     pub fn remove(&self, id: &Hasher) -> Result<Arc<Tx>, Error> {
         let tx = self.do_write(|v| v.remove(id))?;
-        if let Some(e) = &self.event {
-            e.on_remove_tx(self, &*tx)?;
-        }
+        self.release_coins(std::slice::from_ref(&*tx))?;
+        self.notify(|e| e.on_remove_tx(self, &tx))?;
         Ok(tx)
     }
     /// 从交易池获取交易创建区块
@@ -1734,7 +2579,12 @@ impl Chain {
             let mut bsiz = blk.get_size();
             //coinbase可输出交易额
             let mut cbfee = idx.compute_reward(blk.hhv)?;
-            for (fee, tx) in idx.get_txp_iter() {
+            let txs: Vec<(i64, Arc<Tx>)> = idx.get_txp_iter().collect();
+            for (fee, tx) in txs {
+                //跳过锁定时间未到的交易
+                if idx.check_tx_lock(blk.hhv, &tx).is_err() {
+                    continue;
+                }
                 bsiz += tx.get_size();
                 if bsiz > consts::MAX_BLOCK_SIZE {
                     break;
@@ -1924,23 +2774,899 @@ fn test_tx_helper() {
 }
 
 #[test]
-fn test_indexer_thread() {
-    use std::sync::Arc;
-    use std::{thread, time};
+fn test_tx_lock_time() {
+    use crate::config::Config;
+    use crate::consts;
     Config::test(|_, idx| {
-        let indexer = Arc::new(idx);
-        for _ in 0..10 {
-            let idx = indexer.clone();
-            let accpool = idx.get_account_pool().unwrap();
-            thread::spawn(move || {
-                let acc = accpool.value(0).unwrap();
-                let b1 = idx.new_link_block("", &acc.string().unwrap()).unwrap();
-                let id = b1.id().unwrap();
-                let b2 = idx.get(&id.as_ref().into()).unwrap();
-                assert_eq!(b1, *b2);
-            });
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(2)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
         }
-        thread::sleep(time::Duration::from_secs(1));
-        Ok(())
-    })
+        let best = idx.best()?;
+        let coins = idx.coins(&acc)?;
+        let acc1 = accpool.value(0)?;
+        let new_tx = |lock: u32, seq: u32| -> Result<Tx, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.add_coin(&coins[1])?;
+            txh.add_out(&acc1.string()?, 10 * consts::COIN)?;
+            txh.set_lock_time(lock)?;
+            txh.set_sequence(seq)?;
+            txh.set_cost_fee(consts::COIN)?;
+            Tx::try_from(&txh)
+        };
+        //锁定到下下个高度
+        let tx = new_tx(best.next() + 1, consts::SEQUENCE_DISABLE_FLAG)?;
+        assert_eq!(tx.ver, consts::TX_VER_LOCK);
+        assert!(idx.append(&tx).is_err());
+        //锁定时间未到
+        let tx = new_tx(consts::LOCKTIME_THRESHOLD + u32::MAX / 2, 0)?;
+        assert!(idx.append(&tx).is_err());
+        //金额所在区块高度为1,相对锁定200个区块
+        let tx = new_tx(0, 200)?;
+        assert!(idx.append(&tx).is_err());
+        //相对锁定100个区块
+        let tx = new_tx(best.next(), 100)?;
+        idx.append(&tx)?;
+        let mut blk = idx.create_block("locktime", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        assert_eq!(blk.txs.len(), 2);
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        let coins = idx.coins(&acc1)?;
+        assert_eq!(1, coins.len());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_htlc_claim_refund() {
+    use crate::config::Config;
+    use crate::consts;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(2)?;
+        let (acc0, acc1) = (accpool.value(0)?, accpool.value(1)?);
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let best = idx.best()?;
+        let preimage = "swap secret".as_bytes();
+        let htlc = Htlc {
+            hashlock: Htlc::hashlock(preimage),
+            recipient: acc0.hash()?,
+            refund: acc1.hash()?,
+            lock: best.next() + 3,
+        };
+        //锁定10个coin到htlc
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_htlc_out(&htlc, 10 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let fund = Tx::try_from(&txh)?;
+        assert_eq!(fund.outs[0].script.get_htlc()?, htlc);
+        idx.append(&fund)?;
+        let mut blk = idx.create_block("fund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        let mut inv = TxIn::default();
+        inv.out = fund.id()?;
+        inv.idx = 0;
+        let coin = idx.get_txin_ref_coin(&inv)?;
+        assert_eq!(coin.cpk, Hasher::hash(fund.outs[0].script.bytes()));
+        let new_tx = |f: &dyn Fn(&mut TxHelper) -> Result<(), Error>| -> Result<Tx, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.set_cost_fee(consts::COIN)?;
+            f(&mut txh)?;
+            Tx::try_from(&txh)
+        };
+        //错误的原像
+        let tx = new_tx(&|txh| {
+            txh.add_htlc_claim(&coin, "bad".as_bytes(), &acc0.string()?)?;
+            Ok(())
+        })?;
+        assert!(idx.append(&tx).is_err());
+        //非接收账户
+        let tx = new_tx(&|txh| {
+            txh.add_htlc_claim(&coin, preimage, &acc.string()?)?;
+            Ok(())
+        })?;
+        assert!(idx.append(&tx).is_err());
+        //接收账户使用原像消费
+        let tx = new_tx(&|txh| {
+            txh.add_htlc_claim(&coin, preimage, &acc0.string()?)?;
+            Ok(())
+        })?;
+        let (datas, _) = tx.ins[0].script.get_in_datas()?;
+        assert_eq!(datas, vec![preimage.to_vec()]);
+        idx.append(&tx)?;
+        idx.remove(&tx.id()?)?;
+        //锁定时间未到不能退款
+        let refund = new_tx(&|txh| {
+            txh.add_htlc_refund(&coin, &htlc, &acc1.string()?)?;
+            Ok(())
+        })?;
+        assert_eq!(refund.lock, htlc.lock);
+        assert!(idx.append(&refund).is_err());
+        //序列号为SEQUENCE_FINAL时交易锁定时间不生效,脚本锁定检测失败
+        let skip = new_tx(&|txh| {
+            txh.add_htlc_refund(&coin, &htlc, &acc1.string()?)?;
+            txh.set_sequence(consts::SEQUENCE_FINAL)?;
+            Ok(())
+        })?;
+        assert!(skip.is_final(0, 0));
+        assert!(idx.append(&skip).is_err());
+        for _ in 0..2 {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        idx.append(&refund)?;
+        let mut blk = idx.create_block("refund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        //找零到退款账户
+        let coins = idx.coins(&acc1)?;
+        assert_eq!(1, coins.len());
+        assert_eq!(9 * consts::COIN, coins[0].value);
+        Ok(())
+    });
+}
+
+#[test]
+fn test_htlc_refund_lock_u32() {
+    use crate::consts;
+    let (acc0, acc1) = (
+        Account::new(1, 1, false, true).unwrap(),
+        Account::new(1, 1, false, true).unwrap(),
+    );
+    //2^31以上的时间戳锁定不能被当作负数
+    let htlc = Htlc {
+        hashlock: Htlc::hashlock("secret".as_bytes()),
+        recipient: acc0.hash().unwrap(),
+        refund: acc1.hash().unwrap(),
+        lock: 1 << 31,
+    };
+    let mut outv = TxOut::default();
+    outv.script = Script::new_script_htlc(&htlc).unwrap();
+    assert_eq!(outv.script.get_htlc().unwrap(), htlc);
+    let refund = |lock: u32| -> Result<usize, Error> {
+        let mut tx = Tx {
+            ver: consts::TX_VER_LOCK,
+            lock,
+            ..Tx::default()
+        };
+        let mut inv = TxIn::default();
+        inv.out = Hasher::hash("fund".as_bytes());
+        inv.script = Script::new_script_in(&acc1)?;
+        tx.ins.push(inv);
+        let signer = FullSigner {};
+        let cache = signer.get_sign_cache(&tx)?;
+        let msg = signer.get_sign_bytes(&cache, &tx.ins[0], &outv)?;
+        let mut acc = acc1.clone();
+        acc.sign_full(msg.bytes())?;
+        tx.ins[0].script = Script::new_script_in(&acc)?;
+        let env = LinkExectorEnv {
+            tx: &tx,
+            inv: &tx.ins[0],
+            outv: &outv,
+            cache: &cache,
+            singer: Box::new(&signer),
+            sigcache: None,
+        };
+        let mut script = tx.ins[0].script.clone();
+        let script = script.concat(&outv.script);
+        Exector::new().verify(script, &env)
+    };
+    refund(1 << 31).unwrap();
+    refund(u32::MAX).unwrap();
+    assert!(refund((1 << 31) - 1).is_err());
+}
+
+#[test]
+fn test_check_tx_spender() {
+    let (acc0, acc1, acc2) = (
+        Account::new(1, 1, false, true).unwrap(),
+        Account::new(1, 1, false, true).unwrap(),
+        Account::new(1, 1, false, true).unwrap(),
+    );
+    let new_in = |datas: &Vec<Vec<u8>>, acc: &Account| {
+        let mut inv = TxIn::default();
+        inv.script = Script::new_script_in_with(datas, acc).unwrap();
+        inv
+    };
+    //标准输出
+    let out = Script::new_script_out(&acc0.hash().unwrap()).unwrap();
+    assert!(check_tx_spender(&new_in(&vec![], &acc0), &out).is_ok());
+    let err = check_tx_spender(&new_in(&vec![], &acc1), &out).unwrap_err();
+    assert_eq!(err, Error::error("cost addr != out addr"));
+    //哈希时间锁只允许接收方和退款方
+    let htlc = Script::new_script_htlc(&Htlc {
+        hashlock: Htlc::hashlock("secret".as_bytes()),
+        recipient: acc0.hash().unwrap(),
+        refund: acc1.hash().unwrap(),
+        lock: 1000,
+    })
+    .unwrap();
+    assert!(check_tx_spender(&new_in(&vec![], &acc0), &htlc).is_ok());
+    assert!(check_tx_spender(&new_in(&vec![], &acc1), &htlc).is_ok());
+    assert!(check_tx_spender(&new_in(&vec![], &acc2), &htlc).is_err());
+    //脚本hash检测赎回脚本中的模板
+    let sh = Script::new_script_sh(&Hasher::hash(htlc.bytes())).unwrap();
+    let datas = vec![htlc.bytes().to_vec()];
+    assert!(check_tx_spender(&new_in(&datas, &acc0), &sh).is_ok());
+    assert!(check_tx_spender(&new_in(&datas, &acc2), &sh).is_err());
+    let datas = vec![out.bytes().to_vec()];
+    let err = check_tx_spender(&new_in(&datas, &acc0), &sh).unwrap_err();
+    assert_eq!(err, Error::error("redeem hash != out sh"));
+    //自定义脚本消费权限由脚本决定
+    let mut custom = Script::from(crate::script::SCRIPT_TYPE_OUT);
+    custom.op(crate::script::OP_VERIFY_INOUT);
+    custom.op(crate::script::OP_CHECKSIG_VERIFY);
+    assert!(check_tx_spender(&new_in(&vec![], &acc2), &custom).is_ok());
+}
+
+#[test]
+fn test_sh_out_redeem() {
+    use crate::config::Config;
+    use crate::consts;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(2)?;
+        let (acc0, acc1) = (accpool.value(0)?, accpool.value(1)?);
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //使用哈希时间锁作为赎回脚本
+        let preimage = "sh secret".as_bytes();
+        let redeem = Script::new_script_htlc(&Htlc {
+            hashlock: Htlc::hashlock(preimage),
+            recipient: acc0.hash()?,
+            refund: acc1.hash()?,
+            lock: 1000,
+        })?;
+        let addr = redeem.get_sh_address()?;
+        assert!(addr.starts_with(consts::SH_HRP));
+        //自定义赎回脚本: 签名后剩余数据必须等于ok
+        let mut custom = Script::from(crate::script::SCRIPT_TYPE_OUT);
+        custom.op(crate::script::OP_VERIFY_INOUT);
+        custom.op(crate::script::OP_CHECKSIG_VERIFY);
+        custom.data("ok".as_bytes());
+        custom.op(crate::script::OP_EQUAL);
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&addr, 10 * consts::COIN)?;
+        txh.add_out(&custom.get_sh_address()?, 10 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let fund = Tx::try_from(&txh)?;
+        assert_eq!(fund.outs[0].script.get_sh()?, Hasher::hash(redeem.bytes()));
+        idx.append(&fund)?;
+        let mut blk = idx.create_block("fund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        let mut inv = TxIn::default();
+        inv.out = fund.id()?;
+        inv.idx = 0;
+        let coin = idx.get_txin_ref_coin(&inv)?;
+        assert_eq!(coin.cpk, Hasher::hash(redeem.bytes()));
+        let new_tx = |coin: &CoinAttr, redeem: &Script, preimage: &[u8]| -> Result<Tx, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.add_sh_in(coin, redeem, &vec![preimage.to_vec()], &acc0.string()?)?;
+            txh.set_cost_fee(consts::COIN)?;
+            Tx::try_from(&txh)
+        };
+        //错误的原像
+        assert!(idx
+            .append(&new_tx(&coin, &redeem, "bad".as_bytes())?)
+            .is_err());
+        //赎回脚本hash不匹配
+        let other = Script::new_script_out(&acc0.hash()?)?;
+        assert!(idx.append(&new_tx(&coin, &other, preimage)?).is_err());
+        let tx = new_tx(&coin, &redeem, preimage)?;
+        idx.append(&tx)?;
+        let trace = idx.trace_txin(&tx, 0)?;
+        assert!(trace.is_ok());
+        //自定义赎回脚本条件为false时不能消费
+        inv.idx = 1;
+        let coin = idx.get_txin_ref_coin(&inv)?;
+        assert!(idx
+            .append(&new_tx(&coin, &custom, "bad".as_bytes())?)
+            .is_err());
+        idx.append(&new_tx(&coin, &custom, "ok".as_bytes())?)?;
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_data_out() {
+    use crate::config::Config;
+    use crate::consts;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let doc = Hasher::hash("document".as_bytes());
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_data(doc.as_bytes())?;
+        txh.set_cost_fee(consts::COIN)?;
+        let tx = Tx::try_from(&txh)?;
+        assert_eq!(tx.outs.len(), 2);
+        assert!(tx.outs[0].is_data());
+        assert_eq!(tx.outs[0].value, 0);
+        idx.append(&tx)?;
+        //交易池中的数据输出不计入金额
+        assert_eq!(idx.coins(&acc)?.len(), coins.len() + 1);
+        let mut blk = idx.create_block("data", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(
+            idx.get_tx(&tx.id()?.as_ref().into())?.outs[0]
+                .script
+                .get_data()?,
+            doc.as_bytes()
+        );
+        //数据输出不进入金额索引
+        let mut inv = TxIn::default();
+        inv.out = tx.id()?;
+        inv.idx = 0;
+        assert!(idx.get_txin_ref_coin(&inv).is_err());
+        inv.idx = 1;
+        assert!(idx.get_txin_ref_coin(&inv).is_ok());
+        //金额不为0的数据输出无效
+        let mut bad = tx.clone();
+        bad.outs[0].value = 1;
+        assert!(idx.append(&bad).is_err());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_trace_txin() {
+    use crate::config::Config;
+    use crate::consts;
+    use crate::script::OP_CHECKSIG_VERIFY;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&acc.string()?, 10 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let mut tx = Tx::try_from(&txh)?;
+        let trace = idx.trace_txin(&tx, 0)?;
+        assert!(trace.is_ok());
+        assert!(idx.trace_txin(&tx, 1).is_err());
+        //篡改输出金额后签名验证失败
+        tx.outs[0].value += 1;
+        let trace = idx.trace_txin(&tx, 0)?;
+        assert!(!trace.is_ok());
+        let step = trace.steps.last().unwrap();
+        assert_eq!(step.op, OP_CHECKSIG_VERIFY);
+        assert_eq!(trace.error.as_ref().unwrap().0, step.pos);
+        assert!(idx.append(&tx).is_err());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_indexer_thread() {
+    use std::sync::Arc;
+    use std::{thread, time};
+    Config::test(|_, idx| {
+        let indexer = Arc::new(idx);
+        for _ in 0..10 {
+            let idx = indexer.clone();
+            let accpool = idx.get_account_pool().unwrap();
+            thread::spawn(move || {
+                let acc = accpool.value(0).unwrap();
+                let b1 = idx.new_link_block("", &acc.string().unwrap()).unwrap();
+                let id = b1.id().unwrap();
+                let b2 = idx.get(&id.as_ref().into()).unwrap();
+                assert_eq!(b1, *b2);
+            });
+        }
+        thread::sleep(time::Duration::from_secs(1));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_watch_only_account() {
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //冷钱包账户只导入公钥,另一个只导入地址
+        let cold = Account::new(1, 1, false, true)?;
+        let watch = accpool.import(&cold.public().encode_to_bech32()?, "cold", 0)?;
+        let other = Account::new(1, 1, false, true)?.string()?;
+        accpool.import(&other, "other", 0)?;
+        assert!(accpool.is_watch_only(&watch)?);
+        assert!(accpool.is_watch_only(&other)?);
+        assert!(!accpool.is_watch_only(&acc.string()?)?);
+        assert!(accpool.account(&other).is_err());
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&watch, 10 * consts::COIN)?;
+        txh.add_out(&other, 5 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        idx.append(&Tx::try_from(&txh)?)?;
+        let mut blk = idx.create_block("fund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        //只读账户和地址都可以查询金额
+        let coins = idx.coins(&accpool.account(&watch)?)?;
+        assert_eq!(coins.len(), 1);
+        assert_eq!(coins[0].coin(), 10 * consts::COIN);
+        assert_eq!(idx.coins(&Account::decode(&other)?)?.len(), 1);
+        //只读账户不能签名
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&acc.string()?, 9 * consts::COIN)?;
+        assert!(Tx::try_from(&txh).is_err());
+        assert!(txh.partial().is_err());
+        //生成未签名交易导出到离线签名
+        let mut txh = idx.new_tx_helper();
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&acc.string()?, 9 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let buf = txh.partial()?.pack();
+        let mut ptx: PartialTx = Reader::unpack(buf.bytes())?;
+        ptx.sign_with_index(0, 0, cold.get_pri(0).unwrap())?;
+        idx.append(&ptx.finalize()?)?;
+        //只有地址没有公钥不能创建交易
+        let coins = idx.coins(&Account::decode(&other)?)?;
+        let mut txh = idx.new_tx_helper();
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&acc.string()?, 4 * consts::COIN)?;
+        assert!(txh.partial().is_err());
+        let mut blk = idx.create_block("spend", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(idx.coins(&accpool.account(&watch)?)?.len(), 0);
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_select_coins() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let acc1 = accpool.value(1)?;
+        let addr1 = acc1.string()?;
+        for _ in 0..consts::COINBASE_MATURITY + 2 {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //未成熟的coinbase金额不能选择
+        let all = idx.coins(&acc)?;
+        let coins = idx.spendable_coins(&acc)?;
+        assert_eq!(
+            all.len(),
+            coins.len() + consts::COINBASE_MATURITY as usize - 1
+        );
+        let rate = conf.policy.min_fee_rate;
+        //分两个区块给acc1转入不同金额
+        for values in [[10, 30], [45, 20]].iter() {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            for v in values.iter() {
+                txh.add_out(&addr1, v * consts::COIN)?;
+            }
+            txh.select_coins(&acc.string()?, CoinSelect::LargestFirst, rate)?;
+            idx.append(&Tx::try_from(&txh)?)?;
+            let mut blk = idx.create_block("", |fee, helper| {
+                helper.add_out(&acc.string()?, fee)?;
+                Ok(())
+            })?;
+            idx.compute_pow(&mut blk)?;
+            idx.link(&blk)?;
+        }
+        let build = |strategy: CoinSelect, value: i64| -> Result<Tx, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.add_out(&acc.string()?, value)?;
+            txh.select_coins(&addr1, strategy, rate)?;
+            Tx::try_from(&txh)
+        };
+        let values = |tx: &Tx| -> Result<Vec<i64>, Error> {
+            let mut vs = vec![];
+            for inv in tx.ins.iter() {
+                vs.push(idx.get_txin_ref_txout(inv)?.value);
+            }
+            Ok(vs)
+        };
+        //优先使用大金额
+        let tx = build(CoinSelect::LargestFirst, 25 * consts::COIN)?;
+        assert_eq!(values(&tx)?, vec![45 * consts::COIN]);
+        assert_eq!(tx.outs.len(), 2);
+        //优先使用早期的金额
+        let tx = build(CoinSelect::OldestFirst, 25 * consts::COIN)?;
+        let mut vs = values(&tx)?;
+        vs.sort();
+        assert_eq!(vs, vec![10 * consts::COIN, 30 * consts::COIN]);
+        //不需要找零的组合,多余的金额作为交易费
+        let tx = build(CoinSelect::BranchAndBound, 30 * consts::COIN - 1000)?;
+        assert_eq!(values(&tx)?, vec![30 * consts::COIN]);
+        assert_eq!(tx.outs.len(), 1);
+        let tx = build(CoinSelect::BranchAndBound, 40 * consts::COIN - 1000)?;
+        let mut vs = values(&tx)?;
+        vs.sort();
+        assert_eq!(vs, vec![10 * consts::COIN, 30 * consts::COIN]);
+        assert_eq!(tx.outs.len(), 1);
+        //交易费满足接收策略
+        idx.append(&tx)?;
+        //交易池中已消费的金额不再选择
+        let tx = build(CoinSelect::LargestFirst, 60 * consts::COIN)?;
+        let mut vs = values(&tx)?;
+        vs.sort();
+        assert_eq!(vs, vec![20 * consts::COIN, 45 * consts::COIN]);
+        idx.append(&tx)?;
+        assert!(build(CoinSelect::LargestFirst, consts::COIN).is_err());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_coin_lock() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let addr1 = accpool.key(1)?;
+        for _ in 0..consts::COINBASE_MATURITY + 3 {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let rate = conf.policy.min_fee_rate;
+        let build = |lock: Option<bool>| -> Result<TxHelper, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.add_out(&addr1, consts::COIN)?;
+            if let Some(persist) = lock {
+                txh.set_coin_lock(persist)?;
+            }
+            txh.select_coins(&acc.string()?, CoinSelect::OldestFirst, rate)?;
+            Ok(txh)
+        };
+        //并发创建的交易选择不同的金额
+        let txh1 = build(Some(false))?;
+        let txh2 = build(Some(true))?;
+        assert_ne!(txh1.coins[0].tx, txh2.coins[0].tx);
+        assert_eq!(accpool.list_locked_coins().len(), 2);
+        //已添加的输入被锁定时选择失败,不锁定新选择的金额
+        let mut txh = idx.new_tx_helper();
+        txh.add_coin(&txh1.coins[0])?;
+        txh.add_out(&addr1, consts::COIN)?;
+        txh.set_coin_lock(false)?;
+        assert!(txh
+            .select_coins(&acc.string()?, CoinSelect::OldestFirst, rate)
+            .is_err());
+        assert_eq!(accpool.list_locked_coins().len(), 2);
+        idx.append(&Tx::try_from(&txh1)?)?;
+        idx.append(&Tx::try_from(&txh2)?)?;
+        let coin = idx.spendable_coins(&acc)?[0].clone();
+        accpool.lock_coin(coin.tx(), coin.idx(), true)?;
+        let txh3 = build(None)?;
+        assert_ne!(txh3.coins[0].tx, coin.tx);
+        accpool.unlock_coin(coin.tx(), coin.idx())?;
+        assert_eq!(build(None)?.coins[0].tx, coin.tx);
+        //交易链接到区块后清除锁定
+        let mut blk = idx.create_block("lock", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert!(accpool.list_locked_coins().is_empty());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_coin_lock_rollback() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let addr1 = accpool.key(1)?;
+        for _ in 0..consts::COINBASE_MATURITY + 1 {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let rate = conf.policy.min_fee_rate;
+        let build = |signed: bool| -> Result<TxHelper, Error> {
+            let mut txh = idx.new_tx_helper();
+            if signed {
+                txh.set_signer(FullSigner {})?;
+            }
+            txh.add_out(&addr1, consts::COIN)?;
+            txh.set_coin_lock(true)?;
+            txh.select_coins(&acc.string()?, CoinSelect::OldestFirst, rate)?;
+            Ok(txh)
+        };
+        //未签名的交易进入交易池失败,解除锁定
+        let mut txh = build(false)?;
+        assert_eq!(txh.locked_coins().len(), 1);
+        assert_eq!(accpool.list_locked_coins().len(), 1);
+        assert!(txh.append().is_err());
+        assert!(txh.locked_coins().is_empty());
+        assert!(accpool.list_locked_coins().is_empty());
+        //从交易池移除的交易解除锁定
+        let mut txh = build(true)?;
+        let id = txh.append()?;
+        assert_eq!(accpool.list_locked_coins().len(), 1);
+        idx.remove(&id)?;
+        assert!(accpool.list_locked_coins().is_empty());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_schnorr_sign() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = Account::new_schnorr(2, 2, false, true)?;
+        let addr = accpool.import(&acc.encode_to_bech32()?, "schnorr", 0)?;
+        let addr1 = accpool.key(1)?;
+        for _ in 0..consts::COINBASE_MATURITY + 2 {
+            idx.new_link_block("", &addr)?;
+        }
+        let rate = conf.policy.min_fee_rate;
+        //两个输入都使用schnorr签名
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_out(&addr1, idx.compute_reward(0)? + consts::COIN)?;
+        txh.select_coins(&addr, CoinSelect::LargestFirst, rate)?;
+        let tx = Tx::try_from(&txh)?;
+        assert_eq!(tx.ver, consts::TX_VER_SCHNORR);
+        assert_eq!(tx.ins.len(), 2);
+        //错误的签名使整个交易失败
+        let mut bad = tx.clone();
+        let mut sacc = acc.clone();
+        sacc.sign_full(b"other")?;
+        let (datas, _) = bad.ins[1].script.get_in_datas()?;
+        bad.ins[1].script = Script::new_script_in_with(&datas, &sacc)?;
+        let err = idx.append(&bad).unwrap_err();
+        //跟踪结果和进入交易池的结果一致
+        assert!(idx.trace_txin(&bad, 0)?.is_ok());
+        let trace = idx.trace_txin(&bad, 1)?;
+        assert_eq!(trace.error.unwrap().1, err.to_string());
+        idx.append(&tx)?;
+        let mut blk = idx.create_block("", |fee, helper| {
+            helper.add_out(&addr1, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        //消费两个金额,增加一个找零
+        assert_eq!(idx.coins(&Account::decode(&addr)?)?.len(), 101);
+        Ok(())
+    });
+}
+
+#[test]
+fn test_schnorr_checksig_notif() {
+    use crate::script::{OP_CHECKSIG, OP_ENDIF, OP_NOTIF};
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = Account::new_schnorr(2, 2, false, true)?;
+        let addr = accpool.import(&acc.encode_to_bech32()?, "schnorr", 0)?;
+        for _ in 0..consts::COINBASE_MATURITY + 1 {
+            idx.new_link_block("", &addr)?;
+        }
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_out(&accpool.key(1)?, consts::COIN)?;
+        txh.select_coins(&addr, CoinSelect::LargestFirst, conf.policy.min_fee_rate)?;
+        let tx = Tx::try_from(&txh)?;
+        let inv = &tx.ins[0];
+        let outv = idx.get_txin_ref_txout(inv)?;
+        let signer = FullSigner {};
+        let cache = signer.get_sign_cache(&tx)?;
+        let msg = signer.get_sign_bytes(&cache, inv, &outv)?;
+        let sigcache = SigCache::default();
+        // <acc> OP_CHECKSIG OP_NOTIF 1 OP_ENDIF
+        let exec = |acc: &Account, sigcache: Option<&SigCache>| -> Result<usize, Error> {
+            let env = &LinkExectorEnv {
+                tx: &tx,
+                inv,
+                outv: &outv,
+                cache: &cache,
+                singer: Box::new(&signer),
+                sigcache,
+            };
+            let mut script = Script::new(128);
+            script.data(&acc.into_bytes());
+            script.op(OP_CHECKSIG);
+            script.op(OP_NOTIF);
+            script.i8(1);
+            script.op(OP_ENDIF);
+            let mut exector = Exector::new();
+            exector.exec(&script, env)?;
+            Ok(exector.len())
+        };
+        //正确的签名结果为true,不执行分支
+        let mut good = acc.clone();
+        good.sign_full(msg.bytes())?;
+        assert_eq!(exec(&good, None)?, 0);
+        assert_eq!(exec(&good, Some(&sigcache))?, 0);
+        //签名数量不足结果为false,执行分支
+        let mut less = acc.clone();
+        less.sign_with_index(0, msg.bytes())?;
+        assert_eq!(exec(&less, None)?, 1);
+        assert_eq!(exec(&less, Some(&sigcache))?, 1);
+        //错误的签名不返回false,无论是否使用缓存脚本都失败
+        let mut bad = acc.clone();
+        bad.sign_full(b"other")?;
+        assert!(exec(&bad, None).is_err());
+        assert!(exec(&bad, Some(&sigcache)).is_err());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_sig_cache() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let addr = accpool.key(0)?;
+        let addr1 = accpool.key(1)?;
+        for _ in 0..consts::COINBASE_MATURITY + 2 {
+            idx.new_link_block("", &addr)?;
+        }
+        let sigcache = idx.sig_cache()?;
+        sigcache.clear();
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_out(&addr1, consts::COIN)?;
+        txh.select_coins(&addr, CoinSelect::LargestFirst, conf.policy.min_fee_rate)?;
+        let tx = Tx::try_from(&txh)?;
+        //进入交易池时验证并缓存
+        idx.append(&tx)?;
+        assert_eq!(sigcache.len(), tx.ins.len());
+        let hits = sigcache.hits();
+        let mut blk = idx.create_block("", |fee, helper| {
+            helper.add_out(&addr1, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        //区块链接时命中缓存
+        assert_eq!(sigcache.hits(), hits + tx.ins.len());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_link_parallel_sign() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = Account::new_schnorr(2, 2, false, true)?;
+        let addr = accpool.import(&acc.encode_to_bech32()?, "schnorr", 0)?;
+        let addr1 = accpool.key(1)?;
+        for _ in 0..consts::COINBASE_MATURITY + 4 {
+            idx.new_link_block("", &addr)?;
+        }
+        let rate = conf.policy.min_fee_rate;
+        let build = || -> Result<Tx, Error> {
+            let mut txh = idx.new_tx_helper();
+            txh.set_signer(FullSigner {})?;
+            txh.add_out(&addr1, idx.compute_reward(0)? + consts::COIN)?;
+            txh.set_coin_lock(false)?;
+            txh.select_coins(&addr, CoinSelect::LargestFirst, rate)?;
+            Tx::try_from(&txh)
+        };
+        let (tx1, tx2) = (build()?, build()?);
+        //替换第idx个输入的签名
+        let corrupt = |tx: &Tx, idx: usize| -> Result<Tx, Error> {
+            let mut bad = tx.clone();
+            let mut sacc = acc.clone();
+            sacc.sign_full(b"other")?;
+            let (datas, _) = bad.ins[idx].script.get_in_datas()?;
+            bad.ins[idx].script = Script::new_script_in_with(&datas, &sacc)?;
+            Ok(bad)
+        };
+        let new_block = |txs: &[&Tx]| -> Result<Block, Error> {
+            let mut helper = BlkHelper::new(conf.ver);
+            helper.set_attr(idx.next()?)?;
+            helper.set_cbstr("")?;
+            helper.add_out(&addr1, idx.compute_reward(0)?)?;
+            for tx in txs.iter() {
+                helper.add_tx(Arc::new((*tx).clone()))?;
+            }
+            let mut blk = Block::try_from(&helper)?;
+            idx.compute_pow(&mut blk)?;
+            Ok(blk)
+        };
+        //多个交易错误时返回区块中第一个错误的交易和输入
+        let (bad1, bad2) = (corrupt(&tx1, 1)?, corrupt(&tx2, 0)?);
+        let err = idx.link(&new_block(&[&bad1, &bad2])?).unwrap_err();
+        assert_eq!(err.as_str(), "tx 1 txin 1 schnorr sign verify error");
+        let err = idx.link(&new_block(&[&tx1, &bad2])?).unwrap_err();
+        assert_eq!(err.as_str(), "tx 2 txin 0 schnorr sign verify error");
+        let best = idx.best()?;
+        idx.link(&new_block(&[&tx1, &tx2])?)?;
+        assert_eq!(idx.best()?.height, best.height + 1);
+        Ok(())
+    });
+}
+
+#[test]
+fn test_tx_schnorr_batch() {
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = Account::new_schnorr(2, 2, false, true)?;
+        let addr = accpool.import(&acc.encode_to_bech32()?, "schnorr", 0)?;
+        for _ in 0..consts::COINBASE_MATURITY + 2 {
+            idx.new_link_block("", &addr)?;
+        }
+        //两个输入,每个输入两个签名
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_out(&accpool.key(1)?, idx.compute_reward(0)? + consts::COIN)?;
+        txh.select_coins(&addr, CoinSelect::LargestFirst, conf.policy.min_fee_rate)?;
+        let tx = Tx::try_from(&txh)?;
+        assert_eq!(tx.ins.len(), 2);
+        let outs = tx
+            .ins
+            .iter()
+            .map(|inv| idx.get_txin_ref_txout(inv).map(Some))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let signer = FullSigner {};
+        let cache = signer.get_sign_cache(&tx)?;
+        //批量通过后脚本验签全部命中缓存
+        let sigcache = SigCache::default();
+        assert!(batch_tx_schnorr(&tx, &outs, &cache, &signer, &sigcache));
+        assert_eq!(sigcache.len(), 4);
+        check_tx_script(&tx, &outs, &signer, &sigcache).unwrap();
+        assert_eq!(sigcache.hits(), 4);
+        //批量失败时逐个验签定位错误的输入
+        let mut bad = tx.clone();
+        let mut sacc = acc.clone();
+        sacc.sign_full(b"other")?;
+        bad.ins[1].script = Script::new_script_in(&sacc)?;
+        let sigcache = SigCache::default();
+        assert!(!batch_tx_schnorr(&bad, &outs, &cache, &signer, &sigcache));
+        assert!(sigcache.is_empty());
+        let (i, err) = check_tx_script(&bad, &outs, &signer, &sigcache).unwrap_err();
+        assert_eq!(i, 1);
+        assert_eq!(err.as_str(), "schnorr sign verify error");
+        Ok(())
+    });
 }
@@ -66,6 +66,10 @@ impl<'a> Reader<'a> {
     pub fn remaining(&self) -> usize {
         self.inner.remaining()
     }
+    // 剩余的字节
+    pub fn remaining_bytes(&self) -> &'a [u8] {
+        self.inner
+    }
     pub fn new(bytes: &'a [u8]) -> Reader<'a> {
         Reader { inner: bytes }
     }
@@ -0,0 +1,562 @@
+use crate::account::Account;
+use crate::bytes::{FromBytes, IntoBytes};
+use crate::errors::Error;
+use crate::iobuf::{Reader, Writer};
+use crate::util;
+use openssl::pkcs5::scrypt;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use rand::RngCore;
+use std::fmt;
+use std::fs;
+
+/// 加密文件头标识
+const MAGIC: &[u8; 4] = b"BTXK";
+/// 钱包密钥校验文件头标识
+const KEY_MAGIC: &[u8; 4] = b"BTXW";
+/// 当前文件版本
+const VERSION: u8 = 1;
+/// 盐长度
+const SALT_SIZE: usize = 16;
+/// aes-gcm nonce长度
+const NONCE_SIZE: usize = 12;
+/// aes-gcm 认证标签长度
+const TAG_SIZE: usize = 16;
+/// scrypt可使用的最大内存
+const SCRYPT_MAXMEM: u64 = 256 * 1024 * 1024;
+/// 允许的最小log_n,低于默认强度的参数只在测试中使用
+const MIN_LOG_N: u8 = if cfg!(test) { 10 } else { 15 };
+/// 允许的最小r,低于默认强度的参数只在测试中使用
+const MIN_R: u32 = if cfg!(test) { 1 } else { 8 };
+/// 允许的最大r
+const MAX_R: u32 = 32;
+/// 允许的最大p,限制解锁的计算时间
+const MAX_P: u32 = 16;
+
+/// scrypt密钥推导参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    /// N = 2^log_n
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// 检测参数强度,文件头中的参数不可信,低于最小强度的参数返回错误
+    pub fn check(&self) -> Result<(), Error> {
+        if self.log_n < MIN_LOG_N || self.log_n > 20 {
+            return Error::msg("keystore kdf params error");
+        }
+        if self.r < MIN_R || self.r > MAX_R || self.p == 0 || self.p > MAX_P {
+            return Error::msg("keystore kdf params error");
+        }
+        //scrypt需要128*r*N字节内存
+        if (128 * self.r as u64) << self.log_n > SCRYPT_MAXMEM {
+            return Error::msg("keystore kdf params error");
+        }
+        Ok(())
+    }
+    /// 重新加密使用的参数,低于默认强度时使用默认参数
+    fn upgrade(&self) -> KdfParams {
+        let def = KdfParams::default();
+        if self.log_n < def.log_n || self.r < def.r {
+            def
+        } else {
+            *self
+        }
+    }
+    /// 从密码推导256位加密密钥
+    fn derive(&self, password: &str, salt: &[u8]) -> Result<[u8; 32], Error> {
+        self.check()?;
+        let mut key = [0u8; 32];
+        scrypt(
+            password.as_bytes(),
+            salt,
+            1 << self.log_n,
+            self.r as u64,
+            self.p as u64,
+            SCRYPT_MAXMEM,
+            &mut key,
+        )
+        .map_or_else(Error::std, |_| Ok(key))
+    }
+}
+
+/// 从密码推导的加密密钥,只在内存中保存
+/// 钱包所有账户使用同一个密钥加密,每个账户使用随机的nonce,解锁钱包只需要推导一次
+#[derive(Clone)]
+pub struct SealKey {
+    params: KdfParams,
+    salt: [u8; SALT_SIZE],
+    key: [u8; 32],
+}
+
+impl fmt::Debug for SealKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SealKey")
+            .field("params", &self.params)
+            .field("salt", &self.salt)
+            .finish()
+    }
+}
+
+impl SealKey {
+    /// 使用新的盐推导密钥
+    pub fn new(password: &str, params: KdfParams) -> Result<Self, Error> {
+        let mut salt = [0; SALT_SIZE];
+        rand::thread_rng().fill_bytes(&mut salt);
+        SealKey::derive(password, params, &salt)
+    }
+    /// 使用指定的参数和盐推导密钥
+    pub fn derive(
+        password: &str,
+        params: KdfParams,
+        salt: &[u8; SALT_SIZE],
+    ) -> Result<Self, Error> {
+        Ok(SealKey {
+            params,
+            salt: *salt,
+            key: params.derive(password, salt)?,
+        })
+    }
+    /// kdf参数
+    pub fn params(&self) -> &KdfParams {
+        &self.params
+    }
+    /// 校验数据头,包含推导参数,作为附加认证数据
+    fn header(params: &KdfParams, salt: &[u8], nonce: &[u8]) -> Vec<u8> {
+        let mut wb = Writer::default();
+        wb.put_bytes(KEY_MAGIC);
+        wb.u8(VERSION);
+        wb.u8(params.log_n);
+        wb.u32(params.r);
+        wb.u32(params.p);
+        wb.put_bytes(salt);
+        wb.put_bytes(nonce);
+        wb.bytes().to_vec()
+    }
+    /// 保存密钥校验文件: 标识 版本 kdf参数 盐 nonce 认证标签
+    /// 文件不包含密钥,只用来在解锁时校验密码
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut nonce = [0; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let header = SealKey::header(&self.params, &self.salt, &nonce);
+        let mut tag = [0; TAG_SIZE];
+        encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            &header,
+            &[],
+            &mut tag,
+        )
+        .map_or_else(Error::std, Ok)?;
+        let mut wb = Writer::default();
+        wb.put_bytes(&header);
+        wb.put_bytes(&tag);
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, wb.bytes()).map_or_else(Error::std, Ok)?;
+        fs::rename(&tmp, path).map_or_else(Error::std, Ok)
+    }
+    /// 从校验文件推导密钥,密码错误或者数据被修改返回错误
+    pub fn load(path: &str, password: &str) -> Result<Self, Error> {
+        let (params, salt, nonce, tag) = util::read_file(path, |buf| {
+            let mut r = Reader::new(buf);
+            if r.get_bytes(4)? != KEY_MAGIC {
+                return Error::msg("not wallet key file");
+            }
+            if r.u8()? != VERSION {
+                return Error::msg("wallet key version not support");
+            }
+            let params = KdfParams {
+                log_n: r.u8()?,
+                r: r.u32()?,
+                p: r.u32()?,
+            };
+            params.check()?;
+            let (mut salt, mut nonce, mut tag) = ([0; SALT_SIZE], [0; NONCE_SIZE], [0; TAG_SIZE]);
+            salt.copy_from_slice(&r.get_bytes(SALT_SIZE)?);
+            nonce.copy_from_slice(&r.get_bytes(NONCE_SIZE)?);
+            tag.copy_from_slice(&r.get_bytes(TAG_SIZE)?);
+            Ok((params, salt, nonce, tag))
+        })?;
+        let key = SealKey::derive(password, params, &salt)?;
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(&nonce),
+            &SealKey::header(&params, &salt, &nonce),
+            &[],
+            &tag,
+        )
+        .map_or_else(|_| Error::msg("wallet password error"), |_| Ok(key))
+    }
+}
+
+/// 加密存储的账户
+/// 文件格式: 标识 版本 kdf参数 盐 nonce 公开账户数据 认证标签 密文
+/// 公开账户数据只包含公钥,作为附加认证数据,锁定状态下也可获取地址
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    params: KdfParams,
+    salt: [u8; SALT_SIZE],
+    nonce: [u8; NONCE_SIZE],
+    tag: [u8; TAG_SIZE],
+    cipher: Vec<u8>,
+    //锁定时不包含私钥
+    acc: Account,
+    locked: bool,
+}
+
+impl Keystore {
+    /// 使用密码加密账户
+    pub fn new(acc: &Account, password: &str) -> Result<Self, Error> {
+        Keystore::with_params(acc, password, KdfParams::default())
+    }
+    /// 使用指定的kdf参数加密账户
+    pub fn with_params(acc: &Account, password: &str, params: KdfParams) -> Result<Self, Error> {
+        Keystore::with_key(acc, &SealKey::new(password, params)?)
+    }
+    /// 使用已推导的密钥加密账户
+    pub fn with_key(acc: &Account, key: &SealKey) -> Result<Self, Error> {
+        if !acc.check_pris_pubs() {
+            return Error::msg("keystore account pris error");
+        }
+        let mut ks = Keystore {
+            params: key.params,
+            salt: key.salt,
+            nonce: [0; NONCE_SIZE],
+            tag: [0; TAG_SIZE],
+            cipher: vec![],
+            acc: acc.clone(),
+            locked: false,
+        };
+        ks.seal_with_key(key)?;
+        Ok(ks)
+    }
+    /// 使用新的盐和nonce加密当前账户
+    fn seal(&mut self, password: &str) -> Result<(), Error> {
+        let key = SealKey::new(password, self.params)?;
+        self.seal_with_key(&key)
+    }
+    /// 使用密钥和新的nonce加密当前账户,参数和盐来自密钥
+    pub fn seal_with_key(&mut self, key: &SealKey) -> Result<(), Error> {
+        if self.locked {
+            return Error::msg("keystore locked");
+        }
+        self.params = key.params;
+        self.salt = key.salt;
+        rand::thread_rng().fill_bytes(&mut self.nonce);
+        let mut wb = Writer::default();
+        self.acc.encode_to_writer(&mut wb)?;
+        self.cipher = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(&self.nonce),
+            &self.header(),
+            wb.bytes(),
+            &mut self.tag,
+        )
+        .map_or_else(Error::std, Ok)?;
+        Ok(())
+    }
+    /// 加密的附加认证数据
+    fn header(&self) -> Vec<u8> {
+        let mut wb = Writer::default();
+        wb.put_bytes(MAGIC);
+        wb.u8(VERSION);
+        wb.u8(self.params.log_n);
+        wb.u32(self.params.r);
+        wb.u32(self.params.p);
+        wb.put_bytes(&self.salt);
+        wb.put_bytes(&self.nonce);
+        let pubs = self.acc.public().into_bytes();
+        wb.u16(pubs.len() as u16);
+        wb.put_bytes(&pubs);
+        wb.bytes().to_vec()
+    }
+    /// 获取账户,锁定时不包含私钥
+    pub fn account(&self) -> &Account {
+        &self.acc
+    }
+    /// 是否锁定
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+    /// 从内存中清除私钥
+    pub fn lock(&mut self) {
+        self.acc = self.acc.public();
+        self.locked = true;
+    }
+    /// 解密并恢复私钥,密码错误或者数据被修改返回错误
+    pub fn unlock(&mut self, password: &str) -> Result<&Account, Error> {
+        let key = SealKey::derive(password, self.params, &self.salt)?;
+        self.unlock_with_key(&key)
+    }
+    /// 是否使用这个密钥加密
+    pub fn is_sealed_with(&self, key: &SealKey) -> bool {
+        self.params == key.params && self.salt == key.salt
+    }
+    /// 使用已推导的密钥解密,不是这个密钥加密的返回错误
+    pub fn unlock_with_key(&mut self, key: &SealKey) -> Result<&Account, Error> {
+        if !self.is_sealed_with(key) {
+            return Error::msg("keystore key not match");
+        }
+        let buf = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(&self.nonce),
+            &self.header(),
+            &self.cipher,
+            &self.tag,
+        )
+        .map_or_else(|_| Error::msg("keystore password error"), Ok)?;
+        let acc = Account::decode_from_reader(&mut Reader::new(&buf))?;
+        if acc != self.acc {
+            return Error::msg("keystore account not match");
+        }
+        self.acc = acc;
+        self.locked = false;
+        Ok(&self.acc)
+    }
+    /// 修改密码,需要旧密码解密,修改后保持原来的锁定状态
+    /// 加载的参数低于默认强度时使用默认参数重新加密
+    pub fn change_password(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        let locked = self.locked;
+        self.unlock(old)?;
+        self.params = self.params.upgrade();
+        self.seal(new)?;
+        if locked {
+            self.lock();
+        }
+        Ok(())
+    }
+    /// 数据是否为加密账户格式
+    pub fn is_keystore(buf: &[u8]) -> bool {
+        buf.starts_with(MAGIC)
+    }
+    /// 保存到文件,覆盖已存在的文件
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut wb = Writer::default();
+        self.encode_to_writer(&mut wb);
+        //先写临时文件再替换
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, wb.bytes()).map_or_else(Error::std, Ok)?;
+        fs::rename(&tmp, path).map_or_else(Error::std, Ok)
+    }
+    /// 从文件加载,加载后为锁定状态
+    pub fn load(path: &str) -> Result<Self, Error> {
+        util::read_file(path, |buf| {
+            Keystore::decode_from_reader(&mut Reader::new(buf))
+        })
+    }
+    /// 迁移明文账户文件,使用密码加密后覆盖原文件
+    pub fn migrate(path: &str, password: &str) -> Result<Self, Error> {
+        if Keystore::load(path).is_ok() {
+            return Error::msg("file already encrypted");
+        }
+        let ks = Keystore::new(&Account::load(path)?, password)?;
+        ks.save(path)?;
+        Ok(ks)
+    }
+}
+
+impl Keystore {
+    /// 编码加密数据
+    pub fn encode_to_writer(&self, w: &mut Writer) {
+        w.put_bytes(&self.header());
+        w.put_bytes(&self.tag);
+        w.u32(self.cipher.len() as u32);
+        w.put_bytes(&self.cipher);
+    }
+    /// 解码加密数据,解码后为锁定状态
+    pub fn decode_from_reader(r: &mut Reader) -> Result<Keystore, Error> {
+        if r.get_bytes(4)? != MAGIC {
+            return Error::msg("not keystore file");
+        }
+        if r.u8()? != VERSION {
+            return Error::msg("keystore version not support");
+        }
+        let params = KdfParams {
+            log_n: r.u8()?,
+            r: r.u32()?,
+            p: r.u32()?,
+        };
+        params.check()?;
+        let (mut salt, mut nonce, mut tag) = ([0; SALT_SIZE], [0; NONCE_SIZE], [0; TAG_SIZE]);
+        salt.copy_from_slice(&r.get_bytes(SALT_SIZE)?);
+        nonce.copy_from_slice(&r.get_bytes(NONCE_SIZE)?);
+        let size = r.u16()? as usize;
+        let acc = Account::from_bytes(&r.get_bytes(size)?)?;
+        tag.copy_from_slice(&r.get_bytes(TAG_SIZE)?);
+        let size = r.u32()? as usize;
+        Ok(Keystore {
+            params,
+            salt,
+            nonce,
+            tag,
+            cipher: r.get_bytes(size)?,
+            acc,
+            locked: true,
+        })
+    }
+}
+
+#[test]
+fn test_keystore_lock_unlock() {
+    use crate::account::HasAddress;
+    use tempdir::TempDir;
+    let tmp = TempDir::new("keystore").unwrap();
+    let path = tmp.path().join("acc.dat");
+    let path = path.to_str().unwrap();
+    let params = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+    let acc = Account::new(2, 2, false, true).unwrap();
+    let mut ks = Keystore::with_params(&acc, "123456", params).unwrap();
+    ks.lock();
+    assert!(ks.is_locked());
+    assert_eq!(ks.account().pris_size(), 0);
+    //锁定状态可获取地址
+    assert_eq!(ks.account().string().unwrap(), acc.string().unwrap());
+    assert!(ks.unlock("654321").is_err());
+    assert_eq!(ks.unlock("123456").unwrap().pris_size(), 2);
+    ks.save(path).unwrap();
+    //文件中不包含明文私钥
+    let buf = fs::read(path).unwrap();
+    let pri = acc.get_pri(0).unwrap().into_bytes();
+    assert!(!buf.windows(pri.len()).any(|v| v == &pri[..]));
+    assert!(Account::load(path).is_err());
+    let mut ks = Keystore::load(path).unwrap();
+    assert!(ks.is_locked());
+    //修改密码,使用默认强度重新加密
+    ks.change_password("123456", "abc").unwrap();
+    assert!(ks.is_locked());
+    assert_eq!(ks.params, KdfParams::default());
+    ks.save(path).unwrap();
+    let mut ks = Keystore::load(path).unwrap();
+    assert!(ks.unlock("123456").is_err());
+    ks.unlock("abc").unwrap();
+    assert!(ks.account().check_pris_pubs());
+    assert_eq!(*ks.account(), acc);
+    //修改数据后认证失败
+    let mut buf = fs::read(path).unwrap();
+    let last = buf.len() - 1;
+    buf[last] ^= 1;
+    fs::write(path, &buf).unwrap();
+    assert!(Keystore::load(path).unwrap().unlock("abc").is_err());
+}
+
+#[test]
+fn test_keystore_migrate() {
+    use tempdir::TempDir;
+    let tmp = TempDir::new("keystore").unwrap();
+    let path = tmp.path().join("acc.dat");
+    let path = path.to_str().unwrap();
+    let acc = Account::new(1, 1, false, true).unwrap();
+    acc.save(path).unwrap();
+    assert!(Keystore::load(path).is_err());
+    Keystore::migrate(path, "pass").unwrap();
+    assert!(Account::load(path).is_err());
+    assert!(Keystore::migrate(path, "pass").is_err());
+    let mut ks = Keystore::load(path).unwrap();
+    ks.unlock("pass").unwrap();
+    assert_eq!(*ks.account(), acc);
+}
+
+#[test]
+fn test_keystore_kdf_params() {
+    let acc = Account::new(1, 1, false, true).unwrap();
+    let weak = KdfParams {
+        log_n: MIN_LOG_N - 1,
+        r: 8,
+        p: 1,
+    };
+    assert!(Keystore::with_params(&acc, "pass", weak).is_err());
+    //文件头中低于最小强度的参数解码失败
+    let params = KdfParams {
+        log_n: MIN_LOG_N,
+        r: 8,
+        p: 1,
+    };
+    let ks = Keystore::with_params(&acc, "pass", params).unwrap();
+    let mut wb = Writer::default();
+    ks.encode_to_writer(&mut wb);
+    let mut buf = wb.bytes().to_vec();
+    assert!(Keystore::decode_from_reader(&mut Reader::new(&buf)).is_ok());
+    buf[MAGIC.len() + 1] = MIN_LOG_N - 1;
+    assert!(Keystore::decode_from_reader(&mut Reader::new(&buf)).is_err());
+    //强度高于默认的参数修改密码时保留
+    let strong = KdfParams {
+        log_n: 16,
+        r: 8,
+        p: 1,
+    };
+    assert_eq!(strong.upgrade(), strong);
+    assert_eq!(params.upgrade(), KdfParams::default());
+    //r和p超出范围,或者需要的内存超过限制
+    let check = |log_n: u8, r: u32, p: u32| KdfParams { log_n, r, p }.check().is_ok();
+    assert!(check(MIN_LOG_N, MIN_R, 1));
+    assert!(!check(MIN_LOG_N, MIN_R - 1, 1));
+    assert!(check(MIN_LOG_N, MAX_R, 1));
+    assert!(!check(MIN_LOG_N, MAX_R + 1, 1));
+    assert!(check(MIN_LOG_N, 8, MAX_P));
+    assert!(!check(MIN_LOG_N, 8, MAX_P + 1));
+    assert!(!check(MIN_LOG_N, 8, 0));
+    assert!(check(18, 8, 1));
+    assert!(!check(19, 8, 1));
+    assert!(KdfParams::default().check().is_ok());
+}
+
+#[test]
+fn test_keystore_seal_key() {
+    use tempdir::TempDir;
+    let tmp = TempDir::new("keystore").unwrap();
+    let path = tmp.path().join("wallet.key");
+    let path = path.to_str().unwrap();
+    let params = KdfParams {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+    let key = SealKey::new("pass", params).unwrap();
+    key.save(path).unwrap();
+    assert!(SealKey::load(path, "bad").is_err());
+    let key = SealKey::load(path, "pass").unwrap();
+    //同一个密钥加密的账户使用不同的nonce
+    let a1 = Account::new(1, 1, false, true).unwrap();
+    let a2 = Account::new(2, 2, false, true).unwrap();
+    let k1 = Keystore::with_key(&a1, &key).unwrap();
+    let mut k2 = Keystore::with_key(&a2, &key).unwrap();
+    assert!(k1.is_sealed_with(&key) && k2.is_sealed_with(&key));
+    assert_ne!(k1.nonce, k2.nonce);
+    k2.lock();
+    assert!(k2.seal_with_key(&key).is_err());
+    assert_eq!(*k2.unlock_with_key(&key).unwrap(), a2);
+    //密码解锁和密钥解锁结果相同
+    k2.lock();
+    assert_eq!(*k2.unlock("pass").unwrap(), a2);
+    //其他密钥不能解锁
+    let other = SealKey::new("pass", params).unwrap();
+    k2.lock();
+    assert!(!k2.is_sealed_with(&other));
+    assert!(k2.unlock_with_key(&other).is_err());
+    //修改校验文件后认证失败
+    let mut buf = fs::read(path).unwrap();
+    let last = buf.len() - 1;
+    buf[last] ^= 1;
+    fs::write(path, &buf).unwrap();
+    assert!(SealKey::load(path, "pass").is_err());
+}
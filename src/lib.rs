@@ -8,15 +8,23 @@ pub mod config;
 pub mod consts;
 pub mod crypto;
 pub mod errors;
+pub mod escrow;
 pub mod hasher;
+pub mod hd;
 pub mod index;
 pub mod iobuf;
+pub mod keystore;
 pub mod leveldb;
 pub mod merkle;
+pub mod mnemonic;
+pub mod policy;
+pub mod psbt;
 pub mod pubsub;
 pub mod script;
 pub mod store;
+pub mod stratum;
 pub mod util;
+pub mod wallet;
 
 #[macro_use]
 extern crate lazy_static;
@@ -79,6 +79,35 @@ impl MerkleTree {
         }
         Ok(())
     }
+    /// 获取第一个节点(coinbase交易)到根的默克尔路径
+    /// 第一个节点的值不参与计算,可以使用任意值占位
+    pub fn branch(nodes: &Vec<Hasher>) -> Result<Vec<Hasher>, Error> {
+        if nodes.is_empty() {
+            return Error::msg("trans == 0");
+        }
+        let mut branch = vec![];
+        let mut level = nodes.clone();
+        while level.len() > 1 {
+            //第一个节点的兄弟节点
+            branch.push(level[1].clone());
+            let mut next = vec![];
+            for pair in level.chunks(2) {
+                //奇数个节点时复制最后一个
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                next.push(Self::hash(&pair[0], right));
+            }
+            level = next;
+        }
+        Ok(branch)
+    }
+    /// 使用第一个节点和默克尔路径计算根hash
+    pub fn compute_with_branch(first: &Hasher, branch: &[Hasher]) -> Hasher {
+        let mut root = first.clone();
+        for hv in branch.iter() {
+            root = Self::hash(&root, hv);
+        }
+        root
+    }
     /// 计算默克尔树Hash
     pub fn compute(nodes: &Vec<Hasher>) -> Result<Hasher, Error> {
         let mut merkle = MerkleTree::new(nodes)?;
@@ -193,3 +222,19 @@ fn test_merkle2() {
         .unwrap();
     assert_eq!(hv, hm);
 }
+
+#[test]
+fn test_merkle_branch() {
+    for n in 1..12u8 {
+        let mut ids: Vec<Hasher> = vec![];
+        for i in 0..n {
+            ids.push(Hasher::hash(&[i]));
+        }
+        let root = MerkleTree::compute(&ids).unwrap();
+        let first = ids[0].clone();
+        //占位值不影响路径
+        ids[0] = Hasher::zero();
+        let branch = MerkleTree::branch(&ids).unwrap();
+        assert_eq!(root, MerkleTree::compute_with_branch(&first, &branch));
+    }
+}
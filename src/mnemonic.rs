@@ -0,0 +1,200 @@
+use crate::errors::Error;
+use crate::hd::{account_path, ExtPriKey};
+use core::{fmt, str};
+use lazy_static::lazy_static;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// BIP39英文单词表
+const ENGLISH: &str = include_str!("wordlist/english.txt");
+/// 种子推导迭代次数
+const PBKDF2_ROUNDS: usize = 2048;
+
+lazy_static! {
+    static ref WORDS: Vec<&'static str> = ENGLISH.lines().collect();
+}
+
+/// 助记词,用于钱包备份和恢复
+/// 英文单词表只包含ascii字符,助记词和密码不做NFKD规范化
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mnemonic {
+    entropy: Vec<u8>,
+}
+
+/// 查找单词索引
+fn word_index(word: &str) -> Result<usize, Error> {
+    WORDS.binary_search(&word).map_or_else(
+        |_| Error::fmt(format_args!("word {} not in wordlist", word)),
+        Ok,
+    )
+}
+
+/// 熵的校验位
+fn checksum(entropy: &[u8]) -> u8 {
+    let mut sh = Sha256::new();
+    sh.update(entropy);
+    sh.finalize()[0]
+}
+
+impl Mnemonic {
+    /// 生成随机助记词,words为单词数 12,15,18,21,24
+    pub fn new(words: usize) -> Result<Self, Error> {
+        if !(12..=24).contains(&words) || !words.is_multiple_of(3) {
+            return Error::msg("mnemonic words count error");
+        }
+        let mut entropy = vec![0u8; words / 3 * 4];
+        rand::thread_rng().fill_bytes(&mut entropy);
+        Mnemonic::from_entropy(&entropy)
+    }
+    /// 从熵创建,长度为16-32字节并且是4的倍数
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Error> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            return Error::msg("mnemonic entropy length error");
+        }
+        Ok(Mnemonic {
+            entropy: entropy.to_vec(),
+        })
+    }
+    /// 获取熵
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+    /// 获取助记词单词
+    pub fn words(&self) -> Vec<&'static str> {
+        //熵后附加 len/4 位校验位,每11位对应一个单词
+        let cs = checksum(&self.entropy);
+        let bits = self.entropy.len() * 8 + self.entropy.len() / 4;
+        let bit = |i: usize| -> usize {
+            let b = if i < self.entropy.len() * 8 {
+                self.entropy[i / 8]
+            } else {
+                cs
+            };
+            ((b >> (7 - i % 8)) & 1) as usize
+        };
+        (0..bits / 11)
+            .map(|w| WORDS[(0..11).fold(0, |v, j| v << 1 | bit(w * 11 + j))])
+            .collect()
+    }
+    /// 生成种子,passphrase为可选密码
+    pub fn to_seed(&self, passphrase: &str) -> Result<[u8; 64], Error> {
+        let mut seed = [0u8; 64];
+        let salt = format!("mnemonic{}", passphrase);
+        pbkdf2_hmac(
+            self.to_string().as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            MessageDigest::sha512(),
+            &mut seed,
+        )
+        .map_or_else(Error::std, |_| Ok(seed))
+    }
+    /// 生成主密钥
+    pub fn to_master(&self, passphrase: &str) -> Result<ExtPriKey, Error> {
+        ExtPriKey::from_seed(&self.to_seed(passphrase)?)
+    }
+    /// 生成第acc个BIP44账户级密钥,用于恢复账户
+    pub fn account_key(&self, passphrase: &str, acc: u32) -> Result<ExtPriKey, Error> {
+        self.to_master(passphrase)?.derive_path(&account_path(acc))
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.words().join(" "))
+    }
+}
+
+impl str::FromStr for Mnemonic {
+    type Err = Error;
+    /// 解析助记词并检测校验位
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let idxs = s
+            .split_whitespace()
+            .map(|w| word_index(&w.to_lowercase()))
+            .collect::<Result<Vec<usize>, Error>>()?;
+        if idxs.len() < 12 || idxs.len() > 24 || idxs.len() % 3 != 0 {
+            return Error::msg("mnemonic words count error");
+        }
+        let bits = idxs.len() * 11;
+        let mut entropy = vec![0u8; (bits - bits / 33) / 8];
+        let mut cs = 0u8;
+        for i in 0..bits {
+            let v = ((idxs[i / 11] >> (10 - i % 11)) & 1) as u8;
+            if i < entropy.len() * 8 {
+                entropy[i / 8] |= v << (7 - i % 8);
+            } else {
+                cs |= v << (7 - (i - entropy.len() * 8));
+            }
+        }
+        let shift = 8 - bits / 33;
+        if checksum(&entropy) >> shift != cs >> shift {
+            return Error::msg("mnemonic checksum error");
+        }
+        Mnemonic::from_entropy(&entropy)
+    }
+}
+
+#[test]
+fn test_mnemonic_vectors() {
+    use hex::{FromHex, ToHex};
+    assert_eq!(WORDS.len(), 2048);
+    let vectors = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+    ];
+    for (entropy, phrase, seed) in vectors.iter() {
+        let m = Mnemonic::from_entropy(&Vec::from_hex(entropy).unwrap()).unwrap();
+        assert_eq!(m.to_string(), *phrase);
+        assert_eq!(phrase.parse::<Mnemonic>().unwrap(), m);
+        assert_eq!(m.to_seed("TREZOR").unwrap().encode_hex::<String>(), *seed);
+    }
+    let m = Mnemonic::from_entropy(&[0xff; 32]).unwrap();
+    assert_eq!(m.words()[23], "vote");
+    assert_eq!(m.to_string().parse::<Mnemonic>().unwrap(), m);
+    //校验位错误
+    assert!("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+        .parse::<Mnemonic>()
+        .is_err());
+    //不在单词表中
+    assert!("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon btx"
+        .parse::<Mnemonic>()
+        .is_err());
+    assert!(Mnemonic::new(13).is_err());
+}
+
+#[test]
+fn test_mnemonic_recover_account() {
+    use crate::account::HasAddress;
+    let m = Mnemonic::new(24).unwrap();
+    assert_eq!(m.entropy().len(), 32);
+    let acc = m
+        .account_key("pass", 0)
+        .unwrap()
+        .new_account(2, 2, false, 0)
+        .unwrap();
+    //从助记词恢复相同的账户
+    let r: Mnemonic = m.to_string().parse().unwrap();
+    let key = r.account_key("pass", 0).unwrap();
+    assert_eq!(key.new_account(2, 2, false, 0).unwrap(), acc);
+    assert_eq!(
+        key.new_account(2, 2, false, 0)
+            .unwrap()
+            .get_address()
+            .unwrap(),
+        acc.get_address().unwrap()
+    );
+    //密码不同生成不同的账户
+    let other = r.account_key("", 0).unwrap();
+    assert_ne!(other.new_account(2, 2, false, 0).unwrap(), acc);
+}
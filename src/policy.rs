@@ -0,0 +1,202 @@
+use crate::account::HasAddress;
+use crate::block::{Tx, TxOut};
+use crate::errors::Error;
+use crate::script::{Script, SCRIPT_TYPE_DATA, SCRIPT_TYPE_OUT};
+
+/// 标准输出脚本模版
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Template {
+    /// 账户hash输出
+    Account,
+    /// 哈希时间锁输出
+    Htlc,
+    /// 脚本hash输出
+    ScriptHash,
+    /// 数据输出
+    Data,
+}
+
+impl Template {
+    /// 获取输出脚本对应的模版,非标准脚本返回None
+    pub fn from_script(script: &Script) -> Option<Self> {
+        match script.get_type() {
+            Ok(SCRIPT_TYPE_DATA) => script.get_data().ok().map(|_| Template::Data),
+            Ok(SCRIPT_TYPE_OUT) => {
+                if script.get_htlc().is_ok() {
+                    Some(Template::Htlc)
+                } else if script.get_sh().is_ok() {
+                    Some(Template::ScriptHash)
+                } else if script.get_out_hash().is_ok() {
+                    Some(Template::Account)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 交易池接收策略,在共识规则之上执行,不影响区块验证
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// 非数据输出的最小金额
+    pub dust: i64,
+    /// 最低交易费率,每1000字节
+    pub min_fee_rate: i64,
+    /// 允许的输出脚本模版
+    pub templates: Vec<Template>,
+    /// 最大交易大小
+    pub max_tx_size: usize,
+    /// 数据输出最大数据长度
+    pub max_data_size: usize,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            dust: 1000,
+            min_fee_rate: 1000,
+            templates: vec![
+                Template::Account,
+                Template::Htlc,
+                Template::ScriptHash,
+                Template::Data,
+            ],
+            max_tx_size: 100000,
+            max_data_size: 80,
+        }
+    }
+}
+
+impl Policy {
+    /// 交易大小对应的最低交易费
+    pub fn min_fee(&self, size: usize) -> i64 {
+        self.min_fee_rate * size as i64 / 1000
+    }
+    /// 检测输出是否符合策略
+    pub fn check_out(&self, outv: &TxOut) -> Result<(), Error> {
+        let tpl = match Template::from_script(&outv.script) {
+            Some(tpl) => tpl,
+            None => return Error::msg("policy: non-standard out script"),
+        };
+        if !self.templates.contains(&tpl) {
+            return Error::msg("policy: out script template not allowed");
+        }
+        if tpl == Template::Data {
+            if outv.script.get_data()?.len() > self.max_data_size {
+                return Error::msg("policy: data size too large");
+            }
+        } else if outv.value < self.dust {
+            return Error::msg("policy: dust out");
+        }
+        Ok(())
+    }
+    /// 检测交易是否可进入交易池,fee为交易费
+    pub fn check_tx(&self, tx: &Tx, fee: i64) -> Result<(), Error> {
+        let size = tx.get_size();
+        if size > self.max_tx_size {
+            return Error::msg("policy: tx size too large");
+        }
+        if fee < self.min_fee(size) {
+            return Error::msg("policy: fee rate too low");
+        }
+        //输入脚本只能包含数据
+        for inv in tx.ins.iter() {
+            inv.get_address()?;
+        }
+        let mut datas = 0;
+        for outv in tx.outs.iter() {
+            self.check_out(outv)?;
+            if outv.is_data() {
+                datas += 1;
+            }
+        }
+        //只允许一个数据输出
+        if datas > 1 {
+            return Error::msg("policy: multiple data outs");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_policy_check_out() {
+    use crate::block::Htlc;
+    use crate::hasher::Hasher;
+    use crate::script::OP_TRUE;
+    let policy = Policy::default();
+    let hv = Hasher::hash("policy".as_bytes());
+    let new_out = |script: Script, value: i64| {
+        let mut outv = TxOut::default();
+        outv.script = script;
+        outv.value = value;
+        outv
+    };
+    let out = new_out(Script::new_script_out(&hv).unwrap(), policy.dust);
+    assert_eq!(Template::from_script(&out.script), Some(Template::Account));
+    policy.check_out(&out).unwrap();
+    //粉尘输出
+    let out = new_out(Script::new_script_out(&hv).unwrap(), policy.dust - 1);
+    assert!(policy.check_out(&out).is_err());
+    let htlc = Htlc {
+        hashlock: hv.clone(),
+        recipient: hv.clone(),
+        refund: hv.clone(),
+        lock: 10,
+    };
+    let script = Script::new_script_htlc(&htlc).unwrap();
+    assert_eq!(Template::from_script(&script), Some(Template::Htlc));
+    let script = Script::new_script_sh(&hv).unwrap();
+    assert_eq!(Template::from_script(&script), Some(Template::ScriptHash));
+    //数据输出不检测粉尘,检测数据长度
+    let out = new_out(Script::new_script_data(&[1; 80]).unwrap(), 0);
+    assert_eq!(Template::from_script(&out.script), Some(Template::Data));
+    policy.check_out(&out).unwrap();
+    let out = new_out(Script::new_script_data(&[1; 81]).unwrap(), 0);
+    assert!(policy.check_out(&out).is_err());
+    //非标准脚本
+    let mut script = Script::new_script_out(&hv).unwrap();
+    script.op(OP_TRUE);
+    assert_eq!(Template::from_script(&script), None);
+    //不允许的模版
+    let mut policy = Policy::default();
+    policy.templates = vec![Template::Account];
+    let out = new_out(Script::new_script_sh(&hv).unwrap(), policy.dust);
+    assert!(policy.check_out(&out).is_err());
+}
+
+#[test]
+fn test_policy_relay_only() {
+    use crate::account::HasAddress;
+    use crate::config::Config;
+    use crate::consts;
+    use crate::index::FullSigner;
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&acc.string()?, conf.policy.dust - 1)?;
+        let tx = Tx::try_from(&txh)?;
+        //交易池拒绝粉尘输出和没有交易费的交易
+        assert!(idx.append(&tx).is_err());
+        //区块只检测共识规则
+        let mut blk = idx.create_block("policy", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            helper.add_tx(Arc::new(tx.clone()))?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(idx.get_tx(&tx.id()?.as_ref().into())?, tx);
+        Ok(())
+    });
+}
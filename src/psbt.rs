@@ -0,0 +1,214 @@
+use crate::account::Account;
+use crate::block::{Tx, TxOut};
+use crate::crypto::PriKey;
+use crate::errors::Error;
+use crate::index::{Chain, FullSigner, TxSigner};
+use crate::iobuf::{Reader, Serializer, Writer};
+use crate::script::Script;
+
+/// 部分签名交易
+/// 包含未签名交易,输入引用的输出和每个输入的签名账户,多方分别签名后合并
+#[derive(Debug, Clone, Default)]
+pub struct PartialTx {
+    pub tx: Tx,             //未签名交易
+    pub outs: Vec<TxOut>,   //每个输入引用的输出
+    pub accs: Vec<Account>, //每个输入的签名账户和已收集的签名
+}
+
+impl PartialTx {
+    /// 从未签名交易创建,输入脚本中需要包含签名账户
+    pub fn new(ctx: &Chain, tx: &Tx) -> Result<Self, Error> {
+        let mut ptx = PartialTx {
+            tx: tx.clone(),
+            ..PartialTx::default()
+        };
+        for inv in tx.ins.iter() {
+            if inv.is_coinbase() {
+                return Error::msg("coinbase tx can't partial sign");
+            }
+            let (_, acc) = inv.script.get_in_datas()?;
+            ptx.outs.push(ctx.get_txin_ref_txout(inv)?);
+            ptx.accs.push(acc);
+        }
+        Ok(ptx)
+    }
+    /// 获取第i个输入的签名数据
+    pub fn sign_bytes(&self, i: usize) -> Result<Writer, Error> {
+        if i >= self.accs.len() || i >= self.tx.ins.len() || i >= self.outs.len() {
+            return Error::msg("InvalidParam");
+        }
+        let signer = FullSigner {};
+        let cache = signer.get_sign_cache(&self.tx)?;
+        signer.get_sign_bytes(&cache, &self.tx.ins[i], &self.outs[i])
+    }
+    /// 使用账户第idx个私钥签名第i个输入
+    pub fn sign_with_index(
+        &mut self,
+        i: usize,
+        idx: usize,
+        pk: &PriKey,
+    ) -> Result<&mut Self, Error> {
+        let msg = self.sign_bytes(i)?;
+        let mut acc = self.accs[i].clone();
+        acc.set_pri(idx, pk)?;
+        acc.sign_with_index(idx, msg.bytes())?;
+        self.accs[i].merge_sigs(&acc, msg.bytes())?;
+        Ok(self)
+    }
+    /// 合并其他签名方的签名
+    pub fn merge(&mut self, other: &PartialTx) -> Result<&mut Self, Error> {
+        if self.tx.id()? != other.tx.id()? || self.outs != other.outs {
+            return Error::msg("partial tx not match");
+        }
+        //全部合并成功后才修改
+        let mut accs = self.accs.clone();
+        for (i, (acc, oacc)) in accs.iter_mut().zip(other.accs.iter()).enumerate() {
+            acc.merge_sigs(oacc, self.sign_bytes(i)?.bytes())?;
+        }
+        self.accs = accs;
+        Ok(self)
+    }
+    /// 第i个输入的签名是否满足账户要求
+    pub fn is_signed(&self, i: usize) -> bool {
+        match self.sign_bytes(i) {
+            Ok(msg) => self.accs[i].verify_full(msg.bytes()).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+    /// 是否所有输入都已完成签名
+    pub fn is_complete(&self) -> bool {
+        (0..self.accs.len()).all(|i| self.is_signed(i))
+    }
+    /// 所有输入完成签名后生成最终交易
+    pub fn finalize(&self) -> Result<Tx, Error> {
+        let mut tx = self.tx.clone();
+        for (i, inv) in tx.ins.iter_mut().enumerate() {
+            if !self.is_signed(i) {
+                return Error::msg("partial tx sigs not enough");
+            }
+            //保留账户前的数据
+            let (datas, _) = inv.script.get_in_datas()?;
+            inv.script = Script::new_script_in_with(&datas, &self.accs[i])?;
+        }
+        Ok(tx)
+    }
+}
+
+impl Serializer for PartialTx {
+    fn encode(&self, w: &mut Writer) {
+        w.encode(&self.tx);
+        w.u16(self.outs.len() as u16);
+        for outv in self.outs.iter() {
+            w.encode(outv);
+        }
+        w.u16(self.accs.len() as u16);
+        for acc in self.accs.iter() {
+            acc.encode_partial(w);
+        }
+    }
+    fn decode(r: &mut Reader) -> Result<PartialTx, Error> {
+        let mut ptx = PartialTx {
+            tx: r.decode()?,
+            ..PartialTx::default()
+        };
+        for _ in 0..r.u16()? {
+            ptx.outs.push(r.decode()?);
+        }
+        for _ in 0..r.u16()? {
+            ptx.accs.push(Account::decode_partial(r)?);
+        }
+        if ptx.outs.len() != ptx.tx.ins.len() || ptx.accs.len() != ptx.tx.ins.len() {
+            return Error::msg("partial tx format error");
+        }
+        Ok(ptx)
+    }
+}
+
+#[test]
+fn test_partial_tx_multisig() {
+    use crate::account::HasAddress;
+    use crate::block::TxIn;
+    use crate::config::Config;
+    use crate::consts;
+    use std::convert::TryFrom;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //2-3多重签名账户,私钥分别在不同的签名方
+        let treasury = Account::new(3, 2, false, true)?;
+        let coins = idx.coins(&acc)?;
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_coin(&coins[0])?;
+        txh.add_out(&treasury.string()?, 10 * consts::COIN)?;
+        txh.set_cost_fee(consts::COIN)?;
+        let fund = Tx::try_from(&txh)?;
+        idx.append(&fund)?;
+        let mut blk = idx.create_block("fund", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        //创建未签名交易,输入脚本只包含公钥
+        let mut tx = Tx::default();
+        let mut inv = TxIn::default();
+        inv.out = fund.id()?;
+        inv.idx = 0;
+        inv.script = Script::new_script_in(&treasury)?;
+        tx.ins.push(inv);
+        let mut outv = TxOut::default();
+        outv.value = 9 * consts::COIN;
+        outv.script = Script::new_script_out(&acc.hash()?)?;
+        tx.outs.push(outv);
+        let ptx = PartialTx::new(&idx, &tx)?;
+        assert!(!ptx.is_complete());
+        //序列化后分发到签名方
+        let buf = ptx.pack();
+        let mut p1: PartialTx = Reader::unpack(buf.bytes())?;
+        let mut p2: PartialTx = Reader::unpack(buf.bytes())?;
+        p1.sign_with_index(0, 0, treasury.get_pri(0).unwrap())?;
+        p2.sign_with_index(0, 2, treasury.get_pri(2).unwrap())?;
+        //错误的私钥
+        assert!(p2
+            .sign_with_index(0, 1, treasury.get_pri(2).unwrap())
+            .is_err());
+        assert!(!p1.is_complete());
+        assert!(p1.finalize().is_err());
+        let mut p1: PartialTx = Reader::unpack(p1.pack().bytes())?;
+        p1.merge(&p2)?;
+        assert!(p1.is_complete());
+        let signed = p1.finalize()?;
+        assert_eq!(signed.id()?, tx.id()?);
+        idx.append(&signed)?;
+        //错误的签名不能合并,已有的错误签名被正确的签名替换
+        let mut bad: PartialTx = Reader::unpack(buf.bytes())?;
+        let mut forged = bad.accs[0].clone();
+        forged.set_pri(1, treasury.get_pri(1).unwrap())?;
+        forged.sign_with_index(1, b"other")?;
+        let mut fp = bad.clone();
+        fp.accs[0] = forged;
+        assert!(bad.merge(&fp).is_err());
+        assert_eq!(bad.accs[0].sigs_size(), 0);
+        bad = fp;
+        let mut p3: PartialTx = Reader::unpack(buf.bytes())?;
+        p3.sign_with_index(0, 1, treasury.get_pri(1).unwrap())?;
+        bad.merge(&p3)?;
+        assert!(bad.accs[0].verify_with_public(1, bad.sign_bytes(0)?.bytes())?);
+        //不同的交易不能合并
+        let mut other = ptx.clone();
+        other.tx.outs[0].value -= 1;
+        assert!(other.merge(&p2).is_err());
+        let mut blk = idx.create_block("spend", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(idx.coins(&treasury)?.len(), 0);
+        Ok(())
+    });
+}
@@ -1,10 +1,13 @@
 use crate::account::Account;
 use crate::bytes::{FromBytes, IntoBytes};
+use crate::crypto::SigScheme;
 use crate::errors::Error;
 use crate::hasher::Hasher;
 use crate::iobuf;
 use crate::iobuf::{Reader, Serializer, Writer};
+use core::fmt;
 use std::convert::{From, TryFrom, TryInto};
+use std::str::FromStr;
 /// 放入0 - 16
 pub const OP_00: u8 = 0x00;
 pub const OP_01: u8 = 0x01;
@@ -59,6 +62,48 @@ pub const OP_EQUAL_VERIFY: u8 = 0xC5;
 pub const OP_CHECKSIG_VERIFY: u8 = 0xC6;
 /// 检测是否是IN_OUT脚本: 输入 + 输出
 pub const OP_VERIFY_INOUT: u8 = 0xC7;
+/// 检测交易锁定时间不小于栈顶数字,并丢弃栈顶参数
+pub const OP_CHECKLOCKTIME_VERIFY: u8 = 0xC8;
+/// 检测输入相对锁定不小于栈顶数字,并丢弃栈顶参数
+pub const OP_CHECKSEQUENCE_VERIFY: u8 = 0xC9;
+/// 对栈顶数据计算sha256并替换栈顶
+pub const OP_SHA256: u8 = 0xCA;
+/// 对栈顶数据计算double sha256并替换栈顶
+pub const OP_HASH256: u8 = 0xCB;
+/// 哈希时间锁检测 栈: [原像] 账户 hashlock 接收hash 退款hash 锁定时间
+/// 接收账户需要提供sha256(原像)==hashlock,退款账户需要达到锁定时间
+/// 检测通过后丢弃参数和原像,保留账户用于验签
+pub const OP_CHECKHTLC_VERIFY: u8 = 0xCC;
+/// 脚本hash检测 栈: [数据] 赎回脚本 账户 脚本hash
+/// hash(赎回脚本)==脚本hash后删除赎回脚本,使用剩余的栈执行赎回脚本,赎回脚本不能嵌套
+pub const OP_CHECKSCRIPT_VERIFY: u8 = 0xCD;
+/// 栈顶为true执行之后的分支,丢弃栈顶
+pub const OP_IF: u8 = 0xD0;
+/// 栈顶为false执行之后的分支,丢弃栈顶
+pub const OP_NOTIF: u8 = 0xD1;
+/// 切换当前分支的执行状态
+pub const OP_ELSE: u8 = 0xD2;
+/// 结束当前分支
+pub const OP_ENDIF: u8 = 0xD3;
+/// 数字运算,参数必须为数字否则StackEleTypeErr,溢出返回ScriptNumOverflowErr
+/// top(-2) + top(-1) -> push number,丢弃参数
+pub const OP_ADD: u8 = 0xE0;
+/// top(-2) - top(-1) -> push number,丢弃参数
+pub const OP_SUB: u8 = 0xE1;
+/// top(-2) < top(-1) -> push bool,丢弃参数
+pub const OP_LESSTHAN: u8 = 0xE2;
+/// top(-2) > top(-1) -> push bool,丢弃参数
+pub const OP_GREATERTHAN: u8 = 0xE3;
+/// top(-2) <= top(-3) < top(-1) -> push bool,栈: x min max,丢弃参数
+pub const OP_WITHIN: u8 = 0xE4;
+/// min(top(-2), top(-1)) -> push number,丢弃参数
+pub const OP_MIN: u8 = 0xE5;
+/// max(top(-2), top(-1)) -> push number,丢弃参数
+pub const OP_MAX: u8 = 0xE6;
+/// 参数必须为bool否则StackEleTypeErr, top(-2) && top(-1) -> push bool,丢弃参数
+pub const OP_BOOLAND: u8 = 0xE7;
+/// top(-2) || top(-1) -> push bool,丢弃参数
+pub const OP_BOOLOR: u8 = 0xE8;
 
 /// coinbase脚本类型
 pub const SCRIPT_TYPE_CB: u8 = 0x1;
@@ -72,16 +117,176 @@ pub const MAX_SCRIPT_IN_SIZE: usize = 2048;
 pub const SCRIPT_TYPE_OUT: u8 = 0x3;
 /// 最大输出脚本长度
 pub const MAX_SCRIPT_OUT_SIZE: usize = 2048;
+/// 数据输出脚本,金额为0不可消费,不进入金额索引
+pub const SCRIPT_TYPE_DATA: u8 = 0x4;
+/// 最大数据输出脚本长度
+pub const MAX_SCRIPT_DATA_SIZE: usize = 128;
 /// 脚本最大长度
 pub const MAX_SCRIPT_SIZE: usize = 4096;
 /// 脚本最大ops数量
 pub const MAX_SCRIPT_OPS: usize = 256;
+/// 脚本执行成功的结束条件(Exector::verify):
+/// 栈只剩一个true,或者栈为空并且最后执行的指令为*_VERIFY指令
+/// 赎回脚本在OP_CHECKSCRIPT_VERIFY中使用相同规则检测
+pub const SCRIPT_VERIFY_OPS: [u8; 7] = [
+    OP_VERIFY,
+    OP_EQUAL_VERIFY,
+    OP_CHECKSIG_VERIFY,
+    OP_CHECKLOCKTIME_VERIFY,
+    OP_CHECKSEQUENCE_VERIFY,
+    OP_CHECKHTLC_VERIFY,
+    OP_CHECKSCRIPT_VERIFY,
+];
+/// 指令计入MAX_SCRIPT_OPS的数量,数字运算按参数数量计算
+pub fn op_cost(op: u8) -> usize {
+    match op {
+        OP_WITHIN => 3,
+        OP_ADD..=OP_BOOLOR => 2,
+        _ => 1,
+    }
+}
+/// 条件分支最大嵌套深度
+pub const MAX_SCRIPT_IF_DEPTH: usize = 16;
+
+/// 指令名称,用于脚本文本格式
+const OP_NAMES: &[(u8, &str)] = &[
+    (OP_00, "OP_00"),
+    (OP_01, "OP_01"),
+    (OP_02, "OP_02"),
+    (OP_03, "OP_03"),
+    (OP_04, "OP_04"),
+    (OP_05, "OP_05"),
+    (OP_06, "OP_06"),
+    (OP_07, "OP_07"),
+    (OP_08, "OP_08"),
+    (OP_09, "OP_09"),
+    (OP_10, "OP_10"),
+    (OP_11, "OP_11"),
+    (OP_12, "OP_12"),
+    (OP_13, "OP_13"),
+    (OP_14, "OP_14"),
+    (OP_15, "OP_15"),
+    (OP_16, "OP_16"),
+    (OP_FALSE, "OP_FALSE"),
+    (OP_TRUE, "OP_TRUE"),
+    (OP_NUMBER_1, "OP_NUMBER_1"),
+    (OP_NUMBER_2, "OP_NUMBER_2"),
+    (OP_NUMBER_4, "OP_NUMBER_4"),
+    (OP_NUMBER_8, "OP_NUMBER_8"),
+    (OP_DATA_1, "OP_DATA_1"),
+    (OP_DATA_2, "OP_DATA_2"),
+    (OP_DATA_4, "OP_DATA_4"),
+    (OP_TYPE, "OP_TYPE"),
+    (OP_VERIFY, "OP_VERIFY"),
+    (OP_EQUAL, "OP_EQUAL"),
+    (OP_NOT, "OP_NOT"),
+    (OP_CHECKSIG, "OP_CHECKSIG"),
+    (OP_HASHER, "OP_HASHER"),
+    (OP_EQUAL_VERIFY, "OP_EQUAL_VERIFY"),
+    (OP_CHECKSIG_VERIFY, "OP_CHECKSIG_VERIFY"),
+    (OP_VERIFY_INOUT, "OP_VERIFY_INOUT"),
+    (OP_CHECKLOCKTIME_VERIFY, "OP_CHECKLOCKTIME_VERIFY"),
+    (OP_CHECKSEQUENCE_VERIFY, "OP_CHECKSEQUENCE_VERIFY"),
+    (OP_SHA256, "OP_SHA256"),
+    (OP_HASH256, "OP_HASH256"),
+    (OP_CHECKHTLC_VERIFY, "OP_CHECKHTLC_VERIFY"),
+    (OP_CHECKSCRIPT_VERIFY, "OP_CHECKSCRIPT_VERIFY"),
+    (OP_IF, "OP_IF"),
+    (OP_NOTIF, "OP_NOTIF"),
+    (OP_ELSE, "OP_ELSE"),
+    (OP_ENDIF, "OP_ENDIF"),
+    (OP_ADD, "OP_ADD"),
+    (OP_SUB, "OP_SUB"),
+    (OP_LESSTHAN, "OP_LESSTHAN"),
+    (OP_GREATERTHAN, "OP_GREATERTHAN"),
+    (OP_WITHIN, "OP_WITHIN"),
+    (OP_MIN, "OP_MIN"),
+    (OP_MAX, "OP_MAX"),
+    (OP_BOOLAND, "OP_BOOLAND"),
+    (OP_BOOLOR, "OP_BOOLOR"),
+];
+
+/// 获取指令名称
+pub fn op_name(op: u8) -> Option<&'static str> {
+    OP_NAMES.iter().find(|v| v.0 == op).map(|v| v.1)
+}
 
 //脚本执行器
 #[derive(Debug)]
 pub struct Exector {
     eles: Vec<Ele>,
     typs: Vec<u8>,
+    trace: Option<Trace>, //设置时记录每一步的执行信息
+    redeem: bool,         //是否在执行赎回脚本
+    last: u8,             //最后执行的指令
+}
+
+/// 脚本单步执行信息
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    pub pos: usize,           //指令在脚本中的位置
+    pub op: u8,               //指令
+    pub exec: bool,           //是否执行,不执行的分支为false
+    pub eles: Vec<Ele>,       //执行前的栈
+    pub typs: Vec<u8>,        //执行前的脚本类型
+    pub eles_after: Vec<Ele>, //执行后的栈
+    pub typs_after: Vec<u8>,  //执行后的脚本类型
+}
+
+/// 脚本执行跟踪结果
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,          //执行过的指令,失败的指令为最后一个
+    pub error: Option<(usize, String)>, //失败的位置和错误信息
+}
+
+impl Trace {
+    /// 脚本是否执行成功
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+    /// 开始一个指令
+    fn begin(&mut self, pos: usize, op: u8, exec: bool, eles: &[Ele], typs: &[u8]) {
+        self.steps.push(TraceStep {
+            pos,
+            op,
+            exec,
+            eles: eles.to_vec(),
+            typs: typs.to_vec(),
+            eles_after: vec![],
+            typs_after: vec![],
+        });
+    }
+    /// 结束最后一个指令
+    fn end(&mut self, eles: &[Ele], typs: &[u8]) {
+        if let Some(step) = self.steps.last_mut() {
+            step.eles_after = eles.to_vec();
+            step.typs_after = typs.to_vec();
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in self.steps.iter() {
+            let name = op_name(step.op).map_or(format!("0x{:02x}", step.op), |v| v.into());
+            writeln!(
+                f,
+                "#{} {}{} {:?} {:?} -> {:?} {:?}",
+                step.pos,
+                name,
+                if step.exec { "" } else { " (skip)" },
+                step.typs,
+                step.eles,
+                step.typs_after,
+                step.eles_after
+            )?;
+        }
+        if let Some((pos, err)) = &self.error {
+            writeln!(f, "failed at #{}: {}", pos, err)?;
+        }
+        Ok(())
+    }
 }
 
 /// 执行环境特性定义
@@ -89,6 +294,22 @@ pub trait ExectorEnv: Sized {
     /// OP_CHECKSIG OP_CHECKSIG_VERIFY 验签使用
     /// ele: 堆栈顶部元素(account数据类型)
     fn verify_sign(&self, ele: &Ele) -> Result<bool, Error>;
+    /// Schnorr账户验签,签名数量不足返回false,存在错误的签名必须返回错误
+    /// acc: 从堆栈顶部元素解码的账户
+    fn verify_schnorr(&self, acc: &Account) -> Result<bool, Error> {
+        let _ = acc;
+        Error::msg("schnorr sign not support")
+    }
+    /// OP_CHECKLOCKTIME_VERIFY 检测交易锁定时间
+    /// lock: 脚本要求的锁定时间
+    fn check_lock_time(&self, _lock: i64) -> Result<bool, Error> {
+        Ok(false)
+    }
+    /// OP_CHECKSEQUENCE_VERIFY 检测输入相对锁定
+    /// seq: 脚本要求的相对锁定
+    fn check_sequence(&self, _seq: i64) -> Result<bool, Error> {
+        Ok(false)
+    }
 }
 
 impl Exector {
@@ -125,6 +346,46 @@ impl Exector {
         Exector {
             eles: vec![],
             typs: vec![],
+            trace: None,
+            redeem: false,
+            last: 0,
+        }
+    }
+    /// 跟踪模式执行脚本,返回每一步的执行信息和失败位置
+    pub fn trace(&mut self, script: &Script, env: &impl ExectorEnv) -> Trace {
+        self.trace = Some(Trace::default());
+        let ret = self.exec(script, env);
+        let mut trace = self.trace.take().unwrap_or_default();
+        if let Err(err) = ret {
+            //失败指令的执行后状态
+            trace.end(&self.eles, &self.typs);
+            let pos = trace.steps.last().map_or(0, |v| v.pos);
+            trace.error = Some((pos, err.to_string()));
+        }
+        trace
+    }
+    /// 跟踪模式执行并检测结束状态,结束状态错误的位置为脚本长度
+    pub fn trace_verify(&mut self, script: &Script, env: &impl ExectorEnv) -> Trace {
+        let mut trace = self.trace(script, env);
+        if trace.is_ok() {
+            if let Err(err) = self.check_result() {
+                trace.error = Some((script.len(), err.to_string()));
+            }
+        }
+        trace
+    }
+    /// 执行脚本并检测结束状态,消费输出时使用
+    pub fn verify(&mut self, script: &Script, env: &impl ExectorEnv) -> Result<usize, Error> {
+        let step = self.exec(script, env)?;
+        self.check_result()?;
+        Ok(step)
+    }
+    /// 检测脚本结束状态,规则见SCRIPT_VERIFY_OPS
+    pub fn check_result(&self) -> Result<(), Error> {
+        match self.eles.as_slice() {
+            [] if SCRIPT_VERIFY_OPS.contains(&self.last) => Ok(()),
+            [Ele::Bool(true)] => Ok(()),
+            _ => Error::msg("ScriptResultErr"),
         }
     }
     //检测栈元素数量至少为l个
@@ -154,6 +415,23 @@ impl Exector {
             _ => Error::msg("op error"),
         }
     }
+    /// 跳过指令相关的数据
+    pub fn skip_data(r: &mut Reader, op: u8) -> Result<(), Error> {
+        match op {
+            OP_TYPE => r.advance(1),
+            OP_NUMBER_1..=OP_NUMBER_8 => {
+                let p = op - OP_NUMBER_1;
+                r.advance((1 << p) as usize)
+            }
+            OP_DATA_1..=OP_DATA_4 => {
+                let n = (op - OP_DATA_1 + 1) as usize;
+                let l = r.length(n)?;
+                r.advance(l)
+            }
+            //这里应该都是指令,不包含数据
+            _ => Ok(()),
+        }
+    }
     /// 执行脚本
     pub fn exec(&mut self, script: &Script, env: &impl ExectorEnv) -> Result<usize, Error> {
         //脚本过大
@@ -165,10 +443,59 @@ impl Exector {
             return Error::msg("ScriptEmptyErr");
         }
         let mut step = 0;
+        //条件分支执行状态,全部为true时执行
+        let mut conds: Vec<bool> = vec![];
         loop {
-            step += 1;
+            let pos = script.len() - reader.remaining();
             let op = reader.u8()?;
+            step += op_cost(op);
+            //不执行的分支只处理条件指令
+            let exec = conds.iter().all(|v| *v);
+            if let Some(trace) = &mut self.trace {
+                trace.begin(pos, op, exec, &self.eles, &self.typs);
+            }
+            //脚本类型不能出现在分支中
+            if op == OP_TYPE && !conds.is_empty() {
+                return Error::msg("ScriptIfUnbalancedErr");
+            }
+            if !exec && !(OP_IF..=OP_ENDIF).contains(&op) {
+                Self::skip_data(&mut reader, op)?;
+                if let Some(trace) = &mut self.trace {
+                    trace.end(&self.eles, &self.typs);
+                }
+                if step > MAX_SCRIPT_OPS {
+                    return Error::msg("ScriptFmtErr");
+                }
+                if reader.remaining() == 0 {
+                    break;
+                }
+                continue;
+            }
             match op {
+                OP_IF | OP_NOTIF => {
+                    if conds.len() >= MAX_SCRIPT_IF_DEPTH {
+                        return Error::msg("ScriptIfDepthErr");
+                    }
+                    let mut val = false;
+                    if exec {
+                        self.check(1)?;
+                        val = self.top(-1).try_into()?;
+                        self.pop(1)?;
+                        if op == OP_NOTIF {
+                            val = !val;
+                        }
+                    }
+                    conds.push(val);
+                }
+                OP_ELSE => match conds.last_mut() {
+                    Some(v) => *v = !*v,
+                    None => return Error::msg("ScriptIfUnbalancedErr"),
+                },
+                OP_ENDIF => {
+                    if conds.pop().is_none() {
+                        return Error::msg("ScriptIfUnbalancedErr");
+                    }
+                }
                 OP_TYPE => {
                     let typ = reader.u8()?;
                     self.typs.push(typ);
@@ -245,7 +572,13 @@ impl Exector {
                     //检测签名,放置结果到栈顶并销毁参数数据
                     self.check(1)?;
                     let ele = self.top(-1);
-                    let val = env.verify_sign(ele)?;
+                    //按账户签名算法分别验签
+                    let val = match Account::try_from(ele) {
+                        Ok(ref acc) if acc.scheme() == SigScheme::Schnorr => {
+                            env.verify_schnorr(acc)?
+                        }
+                        _ => env.verify_sign(ele)?,
+                    };
                     self.pop(1)?;
                     //如果只验证true不放入结果到堆栈
                     if op == OP_CHECKSIG_VERIFY {
@@ -256,6 +589,120 @@ impl Exector {
                         self.eles.push(Ele::from(val));
                     }
                 }
+                OP_CHECKLOCKTIME_VERIFY | OP_CHECKSEQUENCE_VERIFY => {
+                    //检测锁定并丢弃参数
+                    self.check(1)?;
+                    let val: i64 = self.top(-1).try_into()?;
+                    if val < 0 || val > u32::MAX as i64 {
+                        return Error::msg("LockTimeErr");
+                    }
+                    self.pop(1)?;
+                    if op == OP_CHECKLOCKTIME_VERIFY {
+                        if !env.check_lock_time(val)? {
+                            return Error::msg("OP_CHECKLOCKTIME_VERIFY");
+                        }
+                    } else if !env.check_sequence(val)? {
+                        return Error::msg("OP_CHECKSEQUENCE_VERIFY");
+                    }
+                }
+                OP_SHA256 | OP_HASH256 => {
+                    //hash栈顶数据并替换
+                    self.check(1)?;
+                    let data: &[u8] = self.top(-1).try_into()?;
+                    let hv = if op == OP_SHA256 {
+                        Hasher::sum(data)
+                    } else {
+                        Hasher::hash(data)
+                    };
+                    self.pop(1)?;
+                    self.eles.push(Ele::from(&hv.into_bytes()));
+                }
+                OP_CHECKHTLC_VERIFY => {
+                    self.check(5)?;
+                    let lock: i64 = self.top(-1).try_into()?;
+                    let refund: Hasher = self.top(-2).try_into()?;
+                    let recipient: Hasher = self.top(-3).try_into()?;
+                    let hashlock: Hasher = self.top(-4).try_into()?;
+                    self.pop(4)?;
+                    let acc: Account = self.top(-1).try_into()?;
+                    let addr = acc.hash()?;
+                    //接收账户提供原像
+                    let mut claim = false;
+                    if addr == recipient && self.len() >= 2 {
+                        if let Ele::Data(preimage) = self.top(-2) {
+                            claim = Hasher::sum(preimage) == hashlock;
+                        }
+                    }
+                    if claim {
+                        //删除原像,保留账户
+                        let l = self.len();
+                        self.eles.remove(l - 2);
+                    } else if addr != refund
+                        || lock < 0
+                        || lock > u32::MAX as i64
+                        || !env.check_lock_time(lock)?
+                    {
+                        return Error::msg("OP_CHECKHTLC_VERIFY");
+                    }
+                }
+                OP_ADD..=OP_MAX => {
+                    //数字运算和比较
+                    let n = if op == OP_WITHIN { 3 } else { 2 };
+                    self.check(n)?;
+                    let a: i64 = self.top(-(n as isize)).try_into()?;
+                    let b: i64 = self.top(-(n as isize) + 1).try_into()?;
+                    let ele = match op {
+                        OP_ADD => a.checked_add(b).map(Ele::from),
+                        OP_SUB => a.checked_sub(b).map(Ele::from),
+                        OP_LESSTHAN => Some(Ele::from(a < b)),
+                        OP_GREATERTHAN => Some(Ele::from(a > b)),
+                        OP_WITHIN => {
+                            let c: i64 = self.top(-1).try_into()?;
+                            Some(Ele::from(b <= a && a < c))
+                        }
+                        OP_MIN => Some(Ele::from(a.min(b))),
+                        _ => Some(Ele::from(a.max(b))),
+                    };
+                    let ele = ele.ok_or(Error::error("ScriptNumOverflowErr"))?;
+                    self.pop(n)?;
+                    self.eles.push(ele);
+                }
+                OP_BOOLAND | OP_BOOLOR => {
+                    self.check(2)?;
+                    let a: bool = self.top(-2).try_into()?;
+                    let b: bool = self.top(-1).try_into()?;
+                    self.pop(2)?;
+                    let val = if op == OP_BOOLAND { a && b } else { a || b };
+                    self.eles.push(Ele::from(val));
+                }
+                OP_CHECKSCRIPT_VERIFY => {
+                    if self.redeem {
+                        return Error::msg("OP_CHECKSCRIPT_VERIFY");
+                    }
+                    self.check(3)?;
+                    let hv: Hasher = self.top(-1).try_into()?;
+                    let data: &[u8] = self.top(-3).try_into()?;
+                    if Hasher::hash(data) != hv {
+                        return Error::msg("OP_CHECKSCRIPT_VERIFY");
+                    }
+                    //赎回脚本必须是输出脚本,使用自己的限制检测
+                    let redeem = Script::from_bytes(&data.to_vec())?;
+                    if redeem.get_type()? != SCRIPT_TYPE_OUT {
+                        return Error::msg("OP_CHECKSCRIPT_VERIFY");
+                    }
+                    redeem.check()?;
+                    //删除脚本hash和赎回脚本,保留数据和账户
+                    self.pop(1)?;
+                    let l = self.len();
+                    self.eles.remove(l - 2);
+                    let mut exector = Exector::new();
+                    exector.redeem = true;
+                    exector.eles = self.eles.split_off(0);
+                    exector.typs.push(SCRIPT_TYPE_IN);
+                    exector.exec(&redeem, env)?;
+                    //赎回脚本结果已验证,不保留在栈中
+                    exector.check_result()?;
+                }
                 OP_VERIFY_INOUT => {
                     //检测是否为输入+输出脚本
                     if self.typs.len() != 2 {
@@ -269,6 +716,10 @@ impl Exector {
                     return Error::msg("ScriptFmtErr");
                 }
             }
+            self.last = op;
+            if let Some(trace) = &mut self.trace {
+                trace.end(&self.eles, &self.typs);
+            }
             if step > MAX_SCRIPT_OPS {
                 return Error::msg("ScriptFmtErr");
             }
@@ -276,6 +727,10 @@ impl Exector {
                 break;
             }
         }
+        //分支必须结束
+        if !conds.is_empty() {
+            return Error::msg("ScriptIfUnbalancedErr");
+        }
         Ok(step)
     }
 }
@@ -288,6 +743,480 @@ impl ExectorEnv for TestEnv {
         let a: Account = ele.try_into()?;
         a.verify_full("aaa".as_bytes())
     }
+    fn verify_schnorr(&self, acc: &Account) -> Result<bool, Error> {
+        acc.verify_full("aaa".as_bytes())
+    }
+}
+
+/// 测试锁定时间环境
+struct LockEnv {
+    lock: i64,
+    seq: i64,
+}
+
+impl ExectorEnv for LockEnv {
+    fn verify_sign(&self, _: &Ele) -> Result<bool, Error> {
+        Ok(false)
+    }
+    fn check_lock_time(&self, lock: i64) -> Result<bool, Error> {
+        Ok(lock <= self.lock)
+    }
+    fn check_sequence(&self, seq: i64) -> Result<bool, Error> {
+        Ok(seq <= self.seq)
+    }
+}
+
+#[test]
+fn test_op_check_lock() {
+    let env = LockEnv { lock: 100, seq: 10 };
+    let mut script = Script::new(32);
+    script.i32(100);
+    script.op(OP_CHECKLOCKTIME_VERIFY);
+    script.i8(10);
+    script.op(OP_CHECKSEQUENCE_VERIFY);
+    let mut exector = Exector::new();
+    exector.exec(&script, &env).unwrap();
+    assert_eq!(exector.len(), 0);
+    let mut script = Script::new(32);
+    script.i32(101);
+    script.op(OP_CHECKLOCKTIME_VERIFY);
+    assert!(Exector::new().exec(&script, &env).is_err());
+    let mut script = Script::new(32);
+    script.i8(11);
+    script.op(OP_CHECKSEQUENCE_VERIFY);
+    assert!(Exector::new().exec(&script, &env).is_err());
+    //负数参数直接失败,不能被当作无符号数
+    let env = LockEnv {
+        lock: 1 << 32,
+        seq: 1 << 32,
+    };
+    let mut script = Script::new(32);
+    script.i32(-1);
+    script.op(OP_CHECKSEQUENCE_VERIFY);
+    assert!(Exector::new().exec(&script, &env).is_err());
+    //2^31以上的锁定时间使用8字节数字
+    let mut script = Script::new(32);
+    script.i64(1 << 31);
+    script.op(OP_CHECKLOCKTIME_VERIFY);
+    Exector::new().exec(&script, &env).unwrap();
+    let mut script = Script::new(32);
+    script.i64(u32::MAX as i64 + 1);
+    script.op(OP_CHECKLOCKTIME_VERIFY);
+    assert!(Exector::new().exec(&script, &env).is_err());
+    //默认环境不支持锁定检测
+    let mut script = Script::new(32);
+    script.i8(1);
+    script.op(OP_CHECKLOCKTIME_VERIFY);
+    assert!(Exector::new().exec(&script, &TestEnv {}).is_err());
+}
+
+#[test]
+fn test_op_checksig_schnorr() {
+    let mut acc = Account::new_schnorr(2, 2, false, true).unwrap();
+    acc.sign_full("aaa".as_bytes()).unwrap();
+    let mut script = Script::new(32);
+    script.data(&acc.into_bytes());
+    script.op(OP_CHECKSIG);
+    let mut exector = Exector::new();
+    exector.exec(&script, &TestEnv {}).unwrap();
+    let b: bool = exector.top(-1).try_into().unwrap();
+    assert!(b);
+    //schnorr签名错误时脚本失败,不返回false
+    acc.sign_with_index(1, "bbb".as_bytes()).unwrap();
+    let mut script = Script::new(32);
+    script.data(&acc.into_bytes());
+    script.op(OP_CHECKSIG);
+    script.op(OP_NOT);
+    assert!(Exector::new().exec(&script, &TestEnv {}).is_err());
+    //不支持schnorr的环境
+    assert!(Exector::new()
+        .exec(&script, &LockEnv { lock: 0, seq: 0 })
+        .is_err());
+}
+
+#[test]
+fn test_op_sha256_hash256() {
+    let data = "hello".as_bytes();
+    let mut script = Script::new(32);
+    script.data(data);
+    script.op(OP_SHA256);
+    script.data(data);
+    script.op(OP_HASH256);
+    let mut exector = Exector::new();
+    exector.exec(&script, &TestEnv {}).unwrap();
+    assert_eq!(exector.len(), 2);
+    let v1: Hasher = exector.top(-2).try_into().unwrap();
+    let v2: Hasher = exector.top(-1).try_into().unwrap();
+    assert_eq!(v1, Hasher::sum(data));
+    assert_eq!(v2, Hasher::hash(data));
+    //非数据类型不能hash
+    let mut script = Script::new(32);
+    script.i8(1);
+    script.op(OP_SHA256);
+    assert!(Exector::new().exec(&script, &TestEnv {}).is_err());
+}
+
+#[test]
+fn test_op_if_else() {
+    let env = LockEnv { lock: 100, seq: 0 };
+    //owner OR (after timeout),不执行的分支包含数据
+    let new_script = |owner: bool, lock: i32| {
+        let mut script = Script::new(64);
+        script.bool(owner);
+        script.op(OP_IF);
+        script.data(&[OP_ENDIF, OP_ELSE, OP_TYPE]);
+        script.i8(1);
+        script.op(OP_ELSE);
+        script.i32(lock);
+        script.op(OP_CHECKLOCKTIME_VERIFY);
+        script.i8(2);
+        script.op(OP_ENDIF);
+        script
+    };
+    let mut exector = Exector::new();
+    exector.exec(&new_script(true, 1000), &env).unwrap();
+    assert_eq!(exector.len(), 2);
+    let v: i64 = exector.top(-1).try_into().unwrap();
+    assert_eq!(v, 1);
+    let mut exector = Exector::new();
+    exector.exec(&new_script(false, 100), &env).unwrap();
+    assert_eq!(exector.len(), 1);
+    let v: i64 = exector.top(-1).try_into().unwrap();
+    assert_eq!(v, 2);
+    assert!(Exector::new().exec(&new_script(false, 101), &env).is_err());
+    //所有指令都计数
+    assert_eq!(new_script(true, 0).ops().unwrap(), 9);
+    //嵌套和OP_NOTIF
+    let mut script = Script::new(32);
+    script.bool(false);
+    script.bool(true);
+    script.op(OP_IF);
+    script.op(OP_NOTIF);
+    script.i8(3);
+    script.op(OP_ENDIF);
+    script.op(OP_ENDIF);
+    let mut exector = Exector::new();
+    exector.exec(&script, &env).unwrap();
+    let v: i64 = exector.top(-1).try_into().unwrap();
+    assert_eq!(v, 3);
+    //条件必须为bool
+    let mut script = Script::new(32);
+    script.i8(1);
+    script.op(OP_IF);
+    script.op(OP_ENDIF);
+    assert!(Exector::new().exec(&script, &env).is_err());
+}
+
+#[test]
+fn test_op_checkscript_verify() {
+    let env = LockEnv { lock: 0, seq: 0 };
+    let acc = "acc".as_bytes();
+    let new_script = |redeem: &Script, hv: &Hasher| {
+        let mut script = Script::from(SCRIPT_TYPE_IN);
+        script.i8(1);
+        script.data(redeem.bytes());
+        script.data(acc);
+        script.set_type(SCRIPT_TYPE_OUT);
+        script.data(hv.as_bytes());
+        script.op(OP_CHECKSCRIPT_VERIFY);
+        script
+    };
+    //赎回脚本使用剩余的栈: 1 acc
+    let mut redeem = Script::from(SCRIPT_TYPE_OUT);
+    redeem.op(OP_VERIFY_INOUT);
+    redeem.data(acc);
+    redeem.op(OP_EQUAL_VERIFY);
+    redeem.i8(1);
+    redeem.op(OP_EQUAL);
+    let hv = Hasher::hash(redeem.bytes());
+    let mut exector = Exector::new();
+    exector.verify(&new_script(&redeem, &hv), &env).unwrap();
+    assert_eq!(exector.len(), 0);
+    //赎回脚本结果为false
+    let mut redeem = Script::from(SCRIPT_TYPE_OUT);
+    redeem.op(OP_VERIFY_INOUT);
+    redeem.data(acc);
+    redeem.op(OP_EQUAL_VERIFY);
+    redeem.i8(2);
+    redeem.op(OP_EQUAL);
+    let fhv = Hasher::hash(redeem.bytes());
+    assert!(Exector::new()
+        .exec(&new_script(&redeem, &fhv), &env)
+        .is_err());
+    //hash不匹配
+    let bad = Hasher::hash("bad".as_bytes());
+    assert!(Exector::new()
+        .exec(&new_script(&redeem, &bad), &env)
+        .is_err());
+    //赎回脚本不能嵌套
+    let mut nested = Script::from(SCRIPT_TYPE_OUT);
+    nested.data(redeem.bytes());
+    nested.data(acc);
+    nested.data(hv.as_bytes());
+    nested.op(OP_CHECKSCRIPT_VERIFY);
+    let nhv = Hasher::hash(nested.bytes());
+    assert!(Exector::new()
+        .exec(&new_script(&nested, &nhv), &env)
+        .is_err());
+    //赎回脚本必须是输出脚本
+    let mut redeem = Script::from(SCRIPT_TYPE_IN);
+    redeem.op(OP_TRUE);
+    let hv = Hasher::hash(redeem.bytes());
+    assert!(Exector::new()
+        .exec(&new_script(&redeem, &hv), &env)
+        .is_err());
+}
+
+#[test]
+fn test_exector_verify_result() {
+    let env = LockEnv { lock: 0, seq: 0 };
+    let verify = |f: &dyn Fn(&mut Script)| -> Result<usize, Error> {
+        let mut script = Script::new(64);
+        f(&mut script);
+        Exector::new().verify(&script, &env)
+    };
+    assert!(verify(&|s| {
+        s.i8(1);
+        s.i8(1);
+        s.op(OP_EQUAL);
+    })
+    .is_ok());
+    //条件为false
+    assert!(verify(&|s| {
+        s.i8(1);
+        s.i8(2);
+        s.op(OP_EQUAL);
+    })
+    .is_err());
+    //栈顶为true但有剩余元素
+    assert!(verify(&|s| {
+        s.i8(1);
+        s.op(OP_TRUE);
+    })
+    .is_err());
+    //栈为空且最后为VERIFY指令
+    assert!(verify(&|s| {
+        s.op(OP_TRUE);
+        s.op(OP_VERIFY);
+    })
+    .is_ok());
+    //栈为空但最后不是VERIFY指令
+    assert!(verify(&|s| {
+        s.op(OP_TRUE);
+        s.op(OP_IF);
+        s.op(OP_ENDIF);
+    })
+    .is_err());
+    //跟踪模式报告结束状态错误
+    let mut script = Script::new(64);
+    script.i8(1);
+    script.i8(2);
+    script.op(OP_EQUAL);
+    let trace = Exector::new().trace_verify(&script, &env);
+    assert_eq!(
+        trace.error,
+        Some((script.len(), "btx error: ScriptResultErr".into()))
+    );
+}
+
+#[test]
+fn test_op_arithmetic() {
+    let env = LockEnv { lock: 0, seq: 0 };
+    let exec = |f: &dyn Fn(&mut Script)| -> Result<Ele, Error> {
+        let mut script = Script::new(64);
+        f(&mut script);
+        let mut exector = Exector::new();
+        exector.exec(&script, &env)?;
+        assert_eq!(exector.len(), 1);
+        Ok(exector.top(-1).clone())
+    };
+    let binop = |a: i64, b: i64, op: u8| {
+        exec(&|s| {
+            s.i64(a);
+            s.i64(b);
+            s.op(op);
+        })
+    };
+    assert_eq!(binop(7, 3, OP_ADD).unwrap(), Ele::from(10i64));
+    assert_eq!(binop(7, 3, OP_SUB).unwrap(), Ele::from(4i64));
+    assert_eq!(binop(3, 7, OP_SUB).unwrap(), Ele::from(-4i64));
+    assert_eq!(binop(3, 7, OP_LESSTHAN).unwrap(), Ele::from(true));
+    assert_eq!(binop(7, 7, OP_LESSTHAN).unwrap(), Ele::from(false));
+    assert_eq!(binop(8, 7, OP_GREATERTHAN).unwrap(), Ele::from(true));
+    assert_eq!(binop(-1, 5, OP_MIN).unwrap(), Ele::from(-1i64));
+    assert_eq!(binop(-1, 5, OP_MAX).unwrap(), Ele::from(5i64));
+    //溢出
+    assert!(binop(i64::MAX, 1, OP_ADD).is_err());
+    assert!(binop(i64::MIN, 1, OP_SUB).is_err());
+    //x min max 区间[min, max)
+    let within = |x: i64| {
+        exec(&|s| {
+            s.i64(x);
+            s.i64(10);
+            s.i64(20);
+            s.op(OP_WITHIN);
+        })
+        .unwrap()
+    };
+    assert_eq!(within(10), Ele::from(true));
+    assert_eq!(within(19), Ele::from(true));
+    assert_eq!(within(20), Ele::from(false));
+    assert_eq!(within(9), Ele::from(false));
+    //bool运算
+    let boolop = |a: bool, b: bool, op: u8| {
+        exec(&|s| {
+            s.bool(a);
+            s.bool(b);
+            s.op(op);
+        })
+        .unwrap()
+    };
+    assert_eq!(boolop(true, false, OP_BOOLAND), Ele::from(false));
+    assert_eq!(boolop(true, true, OP_BOOLAND), Ele::from(true));
+    assert_eq!(boolop(false, true, OP_BOOLOR), Ele::from(true));
+    assert_eq!(boolop(false, false, OP_BOOLOR), Ele::from(false));
+    //类型不匹配
+    let err = exec(&|s| {
+        s.bool(true);
+        s.i8(1);
+        s.op(OP_ADD);
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("StackEleTypeErr"));
+    assert!(exec(&|s| {
+        s.i8(1);
+        s.i8(0);
+        s.op(OP_BOOLOR);
+    })
+    .is_err());
+    //参数不足
+    assert!(exec(&|s| {
+        s.i8(1);
+        s.op(OP_ADD);
+    })
+    .is_err());
+    //运算结果为false时不能通过验证
+    let verify = |x: i64| {
+        let mut script = Script::new(64);
+        script.i64(x);
+        script.i64(5);
+        script.op(OP_LESSTHAN);
+        Exector::new().verify(&script, &env)
+    };
+    assert!(verify(3).is_ok());
+    assert!(verify(7).is_err());
+    let verify = |x: i64| {
+        let mut script = Script::new(64);
+        script.i64(x);
+        script.i64(10);
+        script.i64(20);
+        script.op(OP_WITHIN);
+        script.op(OP_VERIFY);
+        Exector::new().verify(&script, &env)
+    };
+    assert!(verify(15).is_ok());
+    assert!(verify(20).is_err());
+    //数字运算按参数数量计入ops
+    let new_script = |n: usize| {
+        let mut script = Script::new(1024);
+        script.i8(0);
+        for _ in 0..n {
+            script.i8(1);
+            script.op(OP_ADD);
+        }
+        script
+    };
+    let script = new_script((MAX_SCRIPT_OPS - 1) / 3);
+    assert_eq!(script.ops().unwrap(), MAX_SCRIPT_OPS);
+    Exector::new().exec(&script, &env).unwrap();
+    let script = new_script((MAX_SCRIPT_OPS - 1) / 3 + 1);
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+    assert_eq!(
+        Script::from_str("OP_01 OP_02 OP_03 OP_WITHIN")
+            .unwrap()
+            .ops()
+            .unwrap(),
+        6
+    );
+    assert_eq!(
+        Script::from_str("OP_ADD OP_BOOLOR").unwrap().to_string(),
+        "OP_ADD OP_BOOLOR"
+    );
+}
+
+#[test]
+fn test_op_if_unbalanced() {
+    let env = LockEnv { lock: 0, seq: 0 };
+    let mut script = Script::new(32);
+    script.bool(true);
+    script.op(OP_IF);
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+    let mut script = Script::new(32);
+    script.op(OP_ELSE);
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+    let mut script = Script::new(32);
+    script.op(OP_ENDIF);
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+    //分支中不能出现脚本类型
+    let mut script = Script::new(32);
+    script.bool(false);
+    script.op(OP_IF);
+    script.set_type(SCRIPT_TYPE_OUT);
+    script.op(OP_ENDIF);
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+    //嵌套深度限制
+    let mut script = Script::new(64);
+    for _ in 0..=MAX_SCRIPT_IF_DEPTH {
+        script.bool(true);
+        script.op(OP_IF);
+    }
+    for _ in 0..=MAX_SCRIPT_IF_DEPTH {
+        script.op(OP_ENDIF);
+    }
+    assert!(script.ops().is_err());
+    assert!(Exector::new().exec(&script, &env).is_err());
+}
+
+#[test]
+fn test_exector_trace() {
+    let env = LockEnv { lock: 0, seq: 0 };
+    let mut script = Script::from(SCRIPT_TYPE_IN);
+    script.i8(1);
+    script.bool(false);
+    script.op(OP_IF);
+    script.op(OP_NOT);
+    script.op(OP_ENDIF);
+    script.i8(2);
+    script.op(OP_EQUAL_VERIFY);
+    let mut exector = Exector::new();
+    let trace = exector.trace(&script, &env);
+    assert!(!trace.is_ok());
+    assert_eq!(trace.steps.len(), 8);
+    //OP_NOT在不执行的分支中
+    assert_eq!(trace.steps[4].op, OP_NOT);
+    assert!(!trace.steps[4].exec);
+    let last = trace.steps.last().unwrap();
+    assert_eq!(last.op, OP_EQUAL_VERIFY);
+    assert_eq!(last.typs, vec![SCRIPT_TYPE_IN]);
+    assert_eq!(last.eles, vec![Ele::from(1i64), Ele::from(2i64)]);
+    assert_eq!(last.eles_after.len(), 0);
+    let (pos, err) = trace.error.as_ref().unwrap();
+    assert_eq!(*pos, script.len() - 1);
+    assert!(err.contains("OP_EQUAL_VERIFY"));
+    assert!(trace.to_string().contains("OP_NOT (skip)"));
+    //成功执行
+    let mut script = Script::new(8);
+    script.i8(1);
+    script.i8(1);
+    script.op(OP_EQUAL);
+    let trace = Exector::new().trace(&script, &env);
+    assert!(trace.is_ok());
+    assert_eq!(trace.steps[2].eles_after, vec![Ele::from(true)]);
 }
 
 #[test]
@@ -496,26 +1425,30 @@ impl Script {
             return Error::msg("ScriptEmptyErr");
         }
         let mut ops = 0;
+        //条件分支深度,不执行的分支同样计数
+        let mut depth = 0;
         loop {
-            ops += 1;
             let op = reader.u8()?;
+            ops += op_cost(op);
             match op {
-                OP_TYPE => {
-                    reader.advance(1)?;
-                }
-                OP_NUMBER_1..=OP_NUMBER_8 => {
-                    let p = op - OP_NUMBER_1;
-                    let l = (1 << p) as usize;
-                    reader.advance(l)?;
+                OP_IF | OP_NOTIF => {
+                    depth += 1;
+                    if depth > MAX_SCRIPT_IF_DEPTH {
+                        return Error::msg("ScriptFmtErr");
+                    }
                 }
-                OP_DATA_1..=OP_DATA_4 => {
-                    let n = (op - OP_DATA_1 + 1) as usize;
-                    let l = reader.length(n)?;
-                    reader.advance(l)?;
+                OP_ELSE | OP_ENDIF => {
+                    if depth == 0 {
+                        return Error::msg("ScriptFmtErr");
+                    }
+                    if op == OP_ENDIF {
+                        depth -= 1;
+                    }
                 }
-                _ => {
-                    //这里应该都是指令,否则需要跳过指令相关的数据
+                OP_TYPE if depth > 0 => {
+                    return Error::msg("ScriptFmtErr");
                 }
+                _ => Exector::skip_data(&mut reader, op)?,
             }
             if ops > MAX_SCRIPT_OPS {
                 return Error::msg("ScriptFmtErr");
@@ -524,6 +1457,10 @@ impl Script {
                 break;
             }
         }
+        //分支必须在同一个脚本内结束
+        if depth != 0 {
+            return Error::msg("ScriptFmtErr");
+        }
         Ok(ops)
     }
     /// 获取脚本最大长度
@@ -532,6 +1469,7 @@ impl Script {
             SCRIPT_TYPE_CB => Ok(MAX_SCRIPT_CB_SIZE),
             SCRIPT_TYPE_IN => Ok(MAX_SCRIPT_IN_SIZE),
             SCRIPT_TYPE_OUT => Ok(MAX_SCRIPT_OUT_SIZE),
+            SCRIPT_TYPE_DATA => Ok(MAX_SCRIPT_DATA_SIZE),
             _ => return Error::msg("ScriptFmtErr"),
         }
     }
@@ -696,6 +1634,183 @@ impl IntoBytes for Script {
     }
 }
 
+/// 脚本文本格式,空格分隔
+/// OP_* 指令
+/// OP_NUMBER_n <十进制数字>
+/// <hex> 最短长度的OP_DATA_n数据
+/// 0x<hex> 原始字节,例如OP_TYPE的类型,未定义的指令,非最短长度的数据
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut toks: Vec<String> = vec![];
+        let mut r = self.reader();
+        while r.remaining() > 0 {
+            let bytes = r.remaining_bytes();
+            let op = bytes[0];
+            let name = match op_name(op) {
+                Some(name) => name,
+                None => {
+                    r.advance(1).map_err(|_| fmt::Error)?;
+                    toks.push(format!("0x{:02x}", op));
+                    continue;
+                }
+            };
+            let mut tr = r.clone();
+            tr.advance(1).map_err(|_| fmt::Error)?;
+            let tok = match op {
+                OP_TYPE => tr.u8().map(|v| format!("{} 0x{:02x}", name, v)),
+                OP_NUMBER_1 => tr.i8().map(|v| format!("{} {}", name, v)),
+                OP_NUMBER_2 => tr.i16().map(|v| format!("{} {}", name, v)),
+                OP_NUMBER_4 => tr.i32().map(|v| format!("{} {}", name, v)),
+                OP_NUMBER_8 => tr.i64().map(|v| format!("{} {}", name, v)),
+                OP_DATA_1..=OP_DATA_4 => {
+                    let start = tr.remaining();
+                    Exector::read_binary(&mut tr, op).map(|d| {
+                        let mut min = Script::new(d.len() + 8);
+                        min.data(&d);
+                        let l = start - tr.remaining();
+                        if !d.is_empty() && min.bytes() == &bytes[..l + 1] {
+                            hex::encode(&d)
+                        } else {
+                            format!("{} 0x{}", name, hex::encode(&bytes[1..l + 1]))
+                        }
+                    })
+                }
+                _ => Ok(name.to_string()),
+            };
+            match tok {
+                Ok(tok) => {
+                    toks.push(tok);
+                    r = tr;
+                }
+                Err(_) => {
+                    //数据不完整,剩余部分按原始字节输出
+                    toks.push(format!("0x{}", hex::encode(bytes)));
+                    break;
+                }
+            }
+        }
+        write!(f, "{}", toks.join(" "))
+    }
+}
+
+impl FromStr for Script {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script = Script::new(s.len());
+        let mut toks = s.split_whitespace();
+        while let Some(tok) = toks.next() {
+            if let Some(hex) = tok.strip_prefix("0x") {
+                //原始字节
+                let d = hex::decode(hex).or_else(Error::std)?;
+                if d.is_empty() {
+                    return Error::msg("ScriptTextErr");
+                }
+                script.writer.put_bytes(&d);
+            } else if tok.starts_with("OP_") {
+                let op = match OP_NAMES.iter().find(|v| v.1 == tok) {
+                    Some(v) => v.0,
+                    None => return Error::fmt(format_args!("unknown op {}", tok)),
+                };
+                if !(OP_NUMBER_1..=OP_NUMBER_8).contains(&op) {
+                    script.op(op);
+                    continue;
+                }
+                let v = match toks.next() {
+                    Some(v) => v,
+                    None => return Error::msg("ScriptTextErr"),
+                };
+                match op {
+                    OP_NUMBER_1 => script.i8(v.parse().or_else(Error::std)?),
+                    OP_NUMBER_2 => script.i16(v.parse().or_else(Error::std)?),
+                    OP_NUMBER_4 => script.i32(v.parse().or_else(Error::std)?),
+                    _ => script.i64(v.parse().or_else(Error::std)?),
+                };
+            } else {
+                //最短长度数据
+                let d = hex::decode(tok).or_else(Error::std)?;
+                if d.is_empty() {
+                    return Error::msg("ScriptTextErr");
+                }
+                script.data(&d);
+            }
+        }
+        Ok(script)
+    }
+}
+
+#[test]
+fn test_script_text_format() {
+    let hv = Hasher::hash(&[1, 2, 3]);
+    let script = Script::new_script_out(&hv).unwrap();
+    let text = script.to_string();
+    assert_eq!(
+        text,
+        format!(
+            "OP_TYPE 0x03 OP_VERIFY_INOUT OP_HASHER {} OP_EQUAL_VERIFY OP_CHECKSIG_VERIFY",
+            hex::encode(hv.as_bytes())
+        )
+    );
+    assert_eq!(text.parse::<Script>().unwrap(), script);
+    //所有指令,数字和非最短数据
+    let mut script = Script::new(256);
+    for v in OP_NAMES.iter() {
+        match v.0 {
+            OP_TYPE => {
+                script.set_type(SCRIPT_TYPE_IN);
+            }
+            OP_NUMBER_1 => {
+                script.i8(-1);
+            }
+            OP_NUMBER_2 => {
+                script.i16(300);
+            }
+            OP_NUMBER_4 => {
+                script.i32(-70000);
+            }
+            OP_NUMBER_8 => {
+                script.i64(i64::MAX);
+            }
+            OP_DATA_1 => {
+                script.data(&[0xB0, 0xFF]);
+            }
+            OP_DATA_2 => {
+                script.op(OP_DATA_2);
+                script.writer.u16(1);
+                script.writer.u8(0xAB);
+            }
+            OP_DATA_4 => {
+                script.op(OP_DATA_4);
+                script.writer.u32(2);
+                script.writer.put_bytes(&[1, 2]);
+                script.op(OP_DATA_1);
+                script.writer.u8(0);
+            }
+            op => {
+                script.op(op);
+            }
+        }
+    }
+    //未定义指令
+    script.op(0x7F);
+    let text = script.to_string();
+    assert!(text.contains("OP_NUMBER_8 9223372036854775807"));
+    assert!(text.contains(" b0ff "));
+    assert!(text.contains("OP_DATA_2 0x0100ab"));
+    assert!(text.contains("OP_DATA_4 0x020000000102"));
+    assert!(text.contains("OP_DATA_1 0x00"));
+    assert!(text.ends_with("OP_BOOLOR 0x7f"));
+    assert_eq!(text.parse::<Script>().unwrap(), script);
+    //不完整的数据
+    let mut script = Script::new(8);
+    script.op(OP_DATA_1);
+    script.writer.u8(5);
+    assert_eq!(script.to_string(), "0xb005");
+    assert_eq!(script.to_string().parse::<Script>().unwrap(), script);
+    assert!("OP_UNKNOWN".parse::<Script>().is_err());
+    assert!("OP_NUMBER_1 300".parse::<Script>().is_err());
+    assert!("0xz1".parse::<Script>().is_err());
+}
+
 impl FromBytes for Script {
     fn from_bytes(bb: &Vec<u8>) -> Result<Self, Error> {
         match iobuf::Writer::from_bytes(bb) {
@@ -706,7 +1821,7 @@ impl FromBytes for Script {
 }
 
 ///栈元素
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Ele {
     Bool(bool),
     Number(i64),
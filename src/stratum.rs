@@ -0,0 +1,735 @@
+use crate::block::{Block, Tx};
+use crate::errors::Error;
+use crate::hasher::{Hasher, SIZE as HasherSize};
+use crate::index::{Chain, ChainListener};
+use crate::iobuf::Writer;
+use crate::merkle::MerkleTree;
+use crate::script::Script;
+use crate::util;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// 服务器为每个连接分配的随机数长度
+pub const EXTRANONCE1_SIZE: usize = 4;
+/// 矿工自己修改的随机数长度
+pub const EXTRANONCE2_SIZE: usize = 4;
+/// 期望的份额提交间隔(秒)
+pub const VARDIFF_TARGET_TIME: i64 = 10;
+/// 每提交多少个份额调整一次难度
+pub const VARDIFF_RETARGET_SHARES: u32 = 16;
+/// 保留的历史任务数量,用于接收延迟提交的份额
+pub const MAX_RECENT_JOBS: usize = 8;
+/// 提交的ntime最多超过当前时间(秒)
+pub const MAX_NTIME_OFFSET: i64 = 2 * 60 * 60;
+
+/// stratum错误码
+const ERR_OTHER: i64 = 20;
+const ERR_JOB_NOT_FOUND: i64 = 21;
+const ERR_DUPLICATE_SHARE: i64 = 22;
+const ERR_LOW_DIFFICULTY: i64 = 23;
+const ERR_UNAUTHORIZED: i64 = 24;
+const ERR_NOT_SUBSCRIBED: i64 = 25;
+
+/// 挖矿任务
+/// coinbase自定义数据 = 前缀 + extranonce1 + extranonce2
+#[derive(Debug)]
+pub struct StratumJob {
+    id: String,          //任务id
+    blk: Block,          //区块模板,coinbase随机数为0
+    height: u32,         //区块高度
+    prefix: Vec<u8>,     //coinbase自定义数据前缀
+    coinb1: Vec<u8>,     //coinbase签名数据随机数之前部分
+    coinb2: Vec<u8>,     //coinbase签名数据随机数之后部分
+    branch: Vec<Hasher>, //coinbase默克尔路径
+}
+
+impl StratumJob {
+    /// 从区块模板创建任务
+    fn new(id: String, blk: Block, height: u32, prefix: &[u8]) -> Result<Self, Error> {
+        let cb = blk.get_tx(0)?;
+        let mut wb = Writer::default();
+        cb.encode_sign(&mut wb)?;
+        //版本(4) + 输入数量(2) + 引用交易(32) + 引用索引(2) + coinbase脚本
+        //随机数放在coinbase脚本的最后
+        let end = 4 + 2 + HasherSize + 2 + cb.ins[0].script.len();
+        let start = end - EXTRANONCE1_SIZE - EXTRANONCE2_SIZE;
+        let bytes = wb.bytes();
+        let mut ids = vec![Hasher::zero()];
+        for tx in blk.txs.iter().skip(1) {
+            ids.push(tx.id()?);
+        }
+        Ok(StratumJob {
+            id,
+            blk: blk.clone(),
+            height,
+            prefix: prefix.to_vec(),
+            coinb1: bytes[..start].to_vec(),
+            coinb2: bytes[end..].to_vec(),
+            branch: MerkleTree::branch(&ids)?,
+        })
+    }
+    /// 任务id
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    /// 任务对应的区块高度
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// mining.notify参数
+    /// [job_id, prev, coinb1, coinb2, [branch], ver, bits, time, clean]
+    /// hash按序列化字节顺序编码为16进制,整数为大端16进制
+    fn notify_params(&self, clean: bool) -> Value {
+        let branch: Vec<String> = self
+            .branch
+            .iter()
+            .map(|v| hex::encode(v.as_bytes()))
+            .collect();
+        let header = &self.blk.header;
+        json!([
+            self.id,
+            hex::encode(header.prev.as_bytes()),
+            hex::encode(&self.coinb1),
+            hex::encode(&self.coinb2),
+            branch,
+            format!("{:08x}", header.ver),
+            format!("{:08x}", header.bits),
+            format!("{:08x}", header.time),
+            clean
+        ])
+    }
+    /// 使用矿工提交的数据生成完整区块
+    fn build_block(&self, en1: &[u8], en2: &[u8], time: u32, nonce: u32) -> Result<Block, Error> {
+        let mut blk = self.blk.clone();
+        let mut data = self.prefix.clone();
+        data.extend_from_slice(en1);
+        data.extend_from_slice(en2);
+        blk.txs[0].ins[0].script = Script::new_script_cb(self.height, &data)?;
+        blk.finish()?;
+        blk.header.time = time;
+        blk.header.nonce = nonce;
+        Ok(blk)
+    }
+}
+
+/// 矿工份额统计
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStats {
+    pub accepted: u64,   //接受的份额
+    pub rejected: u64,   //拒绝的份额
+    pub stale: u64,      //过期任务的份额
+    pub blocks: u64,     //找到的区块
+    pub last_share: i64, //最后一次有效份额时间
+}
+
+/// 动态份额难度
+#[derive(Debug, Clone)]
+pub struct VarDiff {
+    bits: u32,   //当前份额难度
+    limit: u32,  //最低份额难度
+    start: i64,  //本周期开始时间
+    shares: u32, //本周期份额数量
+}
+
+impl VarDiff {
+    /// 从最低份额难度创建
+    pub fn new(limit: u32, now: i64) -> Self {
+        VarDiff {
+            bits: limit,
+            limit,
+            start: now,
+            shares: 0,
+        }
+    }
+    /// 当前份额难度
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+    /// 当前份额目标值
+    pub fn target(&self) -> Result<Hasher, Error> {
+        Hasher::try_from(self.bits)
+    }
+    /// 记录一个有效份额,难度变化时返回新的难度
+    /// 调整幅度限制在1/4 - 4倍之间,并且不低于最低份额难度
+    pub fn record(&mut self, now: i64) -> Result<Option<u32>, Error> {
+        self.shares += 1;
+        if self.shares < VARDIFF_RETARGET_SHARES {
+            return Ok(None);
+        }
+        let expect = VARDIFF_TARGET_TIME * self.shares as i64;
+        let mut span = now - self.start;
+        if span < expect / 4 {
+            span = expect / 4;
+        }
+        if span > expect * 4 {
+            span = expect * 4;
+        }
+        self.start = now;
+        self.shares = 0;
+        //使用大数计算,目标值乘以时间比例可能超过256位
+        let target: BigUint = (&self.target()?).into();
+        let target = target * (span as u64) / (expect as u64);
+        let limit: BigUint = (&Hasher::try_from(self.limit)?).into();
+        let bits = if target > limit {
+            self.limit
+        } else {
+            Hasher::from(&target).compact()
+        };
+        if bits == self.bits {
+            return Ok(None);
+        }
+        self.bits = bits;
+        Ok(Some(bits))
+    }
+}
+
+#[test]
+fn test_vardiff_retarget() {
+    let limit = Hasher::hex("00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+    let mut diff = VarDiff::new(limit.compact(), 0);
+    //份额提交过快,难度提高到4倍
+    for _ in 0..VARDIFF_RETARGET_SHARES - 1 {
+        assert_eq!(diff.record(1).unwrap(), None);
+    }
+    let bits = diff.record(1).unwrap().unwrap();
+    let target = Hasher::try_from(bits).unwrap();
+    assert_eq!(target, Hasher::try_from(limit.compact()).unwrap() / 4);
+    //份额提交过慢,难度不低于最低份额难度
+    for _ in 0..VARDIFF_RETARGET_SHARES - 1 {
+        diff.record(1).unwrap();
+    }
+    let bits = diff.record(100000).unwrap().unwrap();
+    assert_eq!(bits, limit.compact());
+    //最低份额难度接近最大值时不溢出
+    let limit = Hasher::hex("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+    let mut diff = VarDiff::new(limit.compact(), 0);
+    for _ in 0..VARDIFF_RETARGET_SHARES - 1 {
+        diff.record(0).unwrap();
+    }
+    assert_eq!(diff.record(100000).unwrap(), None);
+    assert_eq!(diff.bits(), limit.compact());
+}
+
+/// 矿池服务器
+/// 一个节点上的多个矿工通过stratum协议获取任务并提交份额
+pub struct StratumServer {
+    chain: Arc<Chain>,                            //链对象
+    addr: String,                                 //区块奖励地址
+    prefix: Vec<u8>,                              //coinbase数据前缀
+    limit: u32,                                   //最低份额难度
+    jobs: RwLock<Vec<Arc<StratumJob>>>,           //最近的任务,最后一个为当前任务
+    shares: Mutex<HashSet<Vec<u8>>>,              //已提交的份额
+    workers: Mutex<HashMap<String, WorkerStats>>, //矿工份额统计
+    notify: broadcast::Sender<Arc<StratumJob>>,   //新任务通知
+    next_en1: AtomicU32,                          //下个连接的extranonce1
+    next_job: AtomicU32,                          //下个任务id
+}
+
+impl StratumServer {
+    /// 创建矿池服务器
+    /// addr: 区块奖励地址
+    /// prefix: coinbase数据前缀
+    pub fn new(chain: Arc<Chain>, addr: &str, prefix: &str) -> Result<Arc<Self>, Error> {
+        let conf = chain.config()?;
+        let (notify, _) = broadcast::channel(16);
+        let server = Arc::new(StratumServer {
+            chain,
+            addr: addr.into(),
+            prefix: prefix.as_bytes().to_vec(),
+            limit: conf.pow_limit.compact(),
+            jobs: RwLock::new(vec![]),
+            shares: Mutex::new(HashSet::new()),
+            workers: Mutex::new(HashMap::new()),
+            notify,
+            next_en1: AtomicU32::new(util::rand_u32()),
+            next_job: AtomicU32::new(0),
+        });
+        server.refresh()?;
+        //其他来源的区块链接或者回退后立即更新任务
+        server.chain.add_listener(Arc::new(StratumListener {
+            server: Arc::downgrade(&server),
+        }))?;
+        Ok(server)
+    }
+    /// 从交易池创建新任务并通知所有矿工
+    pub fn refresh(&self) -> Result<Arc<StratumJob>, Error> {
+        let mut data = self.prefix.clone();
+        data.extend_from_slice(&[0u8; EXTRANONCE1_SIZE + EXTRANONCE2_SIZE]);
+        let addr = self.addr.clone();
+        let blk = self.chain.create_block("", |fee, helper| {
+            helper.set_cbdata(&data)?;
+            helper.add_out(&addr, fee)?;
+            Ok(())
+        })?;
+        let (_, height, _) = self.chain.next()?;
+        let id = format!("{:x}", self.next_job.fetch_add(1, Ordering::SeqCst));
+        let job = Arc::new(StratumJob::new(id, blk, height, &self.prefix)?);
+        {
+            let mut jobs = self.jobs.write().map_or_else(Error::std, Ok)?;
+            //高度变化后旧任务不再有效
+            if jobs.last().is_some_and(|v| v.height != height) {
+                jobs.clear();
+                self.shares.lock().map_or_else(Error::std, Ok)?.clear();
+            }
+            jobs.push(job.clone());
+            if jobs.len() > MAX_RECENT_JOBS {
+                jobs.remove(0);
+            }
+        }
+        //没有连接时发送失败可以忽略
+        let _ = self.notify.send(job.clone());
+        Ok(job)
+    }
+    /// 获取当前任务
+    pub fn current(&self) -> Result<Arc<StratumJob>, Error> {
+        let jobs = self.jobs.read().map_or_else(Error::std, Ok)?;
+        jobs.last()
+            .map_or(Error::msg("stratum job miss"), |v| Ok(v.clone()))
+    }
+    /// 按id查找最近的任务
+    fn find_job(&self, id: &str) -> Option<Arc<StratumJob>> {
+        let jobs = self.jobs.read().ok()?;
+        jobs.iter().find(|v| v.id == id).cloned()
+    }
+    /// 获取所有矿工的份额统计
+    pub fn workers(&self) -> HashMap<String, WorkerStats> {
+        self.workers.lock().map_or(HashMap::new(), |v| v.clone())
+    }
+    /// 更新矿工统计
+    fn update_worker<F>(&self, worker: &str, f: F)
+    where
+        F: FnOnce(&mut WorkerStats),
+    {
+        if let Ok(mut workers) = self.workers.lock() {
+            f(workers.entry(worker.into()).or_default());
+        }
+    }
+    /// 分配一个新的extranonce1
+    fn alloc_extranonce1(&self) -> Vec<u8> {
+        let v = self.next_en1.fetch_add(1, Ordering::SeqCst);
+        v.to_be_bytes()[..EXTRANONCE1_SIZE].to_vec()
+    }
+    /// 检测提交的份额,达到区块难度时放入session.found等待链接
+    /// 返回是否找到区块
+    fn submit(
+        &self,
+        session: &mut Session,
+        worker: &str,
+        job: &str,
+        en2: &[u8],
+        time: u32,
+        nonce: u32,
+    ) -> Result<bool, (i64, String)> {
+        let job = match self.find_job(job) {
+            Some(job) => job,
+            None => {
+                self.update_worker(worker, |v| v.stale += 1);
+                return Err((ERR_JOB_NOT_FOUND, "job not found".into()));
+            }
+        };
+        if en2.len() != EXTRANONCE2_SIZE {
+            self.update_worker(worker, |v| v.rejected += 1);
+            return Err((ERR_OTHER, "extranonce2 size error".into()));
+        }
+        if time < job.blk.header.time || time as i64 > util::timestamp() + MAX_NTIME_OFFSET {
+            self.update_worker(worker, |v| v.rejected += 1);
+            return Err((ERR_OTHER, "ntime out of range".into()));
+        }
+        let blk = job
+            .build_block(&session.en1, en2, time, nonce)
+            .map_err(|err| (ERR_OTHER, err.to_string()))?;
+        let id = blk.id().map_err(|err| (ERR_OTHER, err.to_string()))?;
+        //份额难度,难度不足的份额不占用去重记录
+        let target = session
+            .diff
+            .target()
+            .map_err(|err| (ERR_OTHER, err.to_string()))?;
+        if id > target {
+            self.update_worker(worker, |v| v.rejected += 1);
+            return Err((ERR_LOW_DIFFICULTY, "low difficulty share".into()));
+        }
+        //重复的份额
+        let mut key = job.id.as_bytes().to_vec();
+        key.extend_from_slice(id.as_bytes());
+        let dup = self.shares.lock().map_or(true, |mut v| !v.insert(key));
+        if dup {
+            self.update_worker(worker, |v| v.rejected += 1);
+            return Err((ERR_DUPLICATE_SHARE, "duplicate share".into()));
+        }
+        let now = util::timestamp();
+        self.update_worker(worker, |v| {
+            v.accepted += 1;
+            v.last_share = now;
+        });
+        //达到区块难度
+        let limit = Hasher::try_from(self.limit).map_err(|err| (ERR_OTHER, err.to_string()))?;
+        if !id.verify_pow(&limit, blk.header.bits) {
+            return Ok(false);
+        }
+        session.found = Some((worker.into(), blk));
+        Ok(true)
+    }
+    /// 链接矿工找到的区块,返回是否链接成功
+    /// 区块验证需要链写锁并等待验签线程池,不能在异步执行线程中调用
+    fn link_found(&self, worker: &str, blk: &Block) -> bool {
+        //链接成功后由监听器更新任务
+        match self.chain.link(blk) {
+            Ok(best) => {
+                log::info!("stratum block found height={} id={}", best.height, best.id);
+                self.update_worker(worker, |v| v.blocks += 1);
+                true
+            }
+            Err(err) => {
+                log::error!("stratum link block error: {}", err);
+                false
+            }
+        }
+    }
+    /// 绑定地址并开始服务
+    pub async fn run(self: Arc<Self>, bind: &str) -> Result<(), Error> {
+        let listener = TcpListener::bind(bind).await.map_or_else(Error::std, Ok)?;
+        self.serve(listener).await
+    }
+    /// 在监听器上接收矿工连接
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let (stream, peer) = listener.accept().await.map_or_else(Error::std, Ok)?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = server.handle_conn(stream).await {
+                    log::debug!("stratum conn {} closed: {}", peer, err);
+                }
+            });
+        }
+    }
+    /// 处理一个矿工连接
+    async fn handle_conn(self: Arc<Self>, stream: TcpStream) -> Result<(), Error> {
+        let (rd, mut wr) = stream.into_split();
+        let mut lines = BufReader::new(rd).lines();
+        let mut jobs = self.notify.subscribe();
+        let mut session = Session::new(self.alloc_extranonce1(), self.limit);
+        loop {
+            let msgs = tokio::select! {
+                line = lines.next_line() => {
+                    match line.map_or_else(Error::std, Ok)? {
+                        Some(line) => session.handle(&self, &line),
+                        None => return Ok(()),
+                    }
+                }
+                job = jobs.recv() => {
+                    match job {
+                        Ok(job) => session.notify(&job, true),
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(err) => return Error::std(err),
+                    }
+                }
+            };
+            //找到的区块在阻塞线程中链接,不占用异步执行线程
+            if let Some((worker, blk)) = session.found.take() {
+                let server = self.clone();
+                tokio::task::spawn_blocking(move || server.link_found(&worker, &blk))
+                    .await
+                    .map_or_else(Error::std, Ok)?;
+            }
+            for msg in msgs.iter() {
+                let mut line = msg.to_string();
+                line.push('\n');
+                wr.write_all(line.as_bytes())
+                    .await
+                    .map_or_else(Error::std, Ok)?;
+            }
+        }
+    }
+}
+
+/// 链顶变化时更新矿池任务,弱引用避免链和矿池循环引用
+struct StratumListener {
+    server: Weak<StratumServer>,
+}
+
+impl StratumListener {
+    fn refresh(&self) -> Result<(), Error> {
+        if let Some(server) = self.server.upgrade() {
+            if let Err(err) = server.refresh() {
+                log::error!("stratum refresh job error: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChainListener for StratumListener {
+    fn on_link_block(&self, _: &Chain, _: &Block) -> Result<(), Error> {
+        self.refresh()
+    }
+    fn on_pop_block(&self, _: &Chain, _: &Block) -> Result<(), Error> {
+        self.refresh()
+    }
+    fn on_append_tx(&self, _: &Chain, _: &Tx) -> Result<(), Error> {
+        Ok(())
+    }
+    fn on_remove_tx(&self, _: &Chain, _: &Tx) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// 矿工连接会话
+struct Session {
+    en1: Vec<u8>,                   //分配的extranonce1
+    subscribed: bool,               //是否已订阅
+    workers: Vec<String>,           //已授权的矿工
+    diff: VarDiff,                  //份额难度
+    found: Option<(String, Block)>, //达到区块难度等待链接的矿工和区块
+}
+
+impl Session {
+    fn new(en1: Vec<u8>, limit: u32) -> Self {
+        Session {
+            en1,
+            subscribed: false,
+            workers: vec![],
+            diff: VarDiff::new(limit, util::timestamp()),
+            found: None,
+        }
+    }
+    /// 请求结果
+    fn result(id: &Value, result: Value) -> Value {
+        json!({"id": id, "result": result, "error": null})
+    }
+    /// 请求错误
+    fn error(id: &Value, code: i64, msg: &str) -> Value {
+        json!({"id": id, "result": null, "error": [code, msg, null]})
+    }
+    /// 服务器通知
+    fn method(method: &str, params: Value) -> Value {
+        json!({"id": null, "method": method, "params": params})
+    }
+    /// 任务通知
+    fn notify(&self, job: &StratumJob, clean: bool) -> Vec<Value> {
+        if !self.subscribed || self.workers.is_empty() {
+            return vec![];
+        }
+        vec![Self::method("mining.notify", job.notify_params(clean))]
+    }
+    /// 份额目标通知
+    fn set_target(&self) -> Value {
+        let target = self.diff.target().unwrap_or_default();
+        Self::method("mining.set_target", json!([target.encode_hex()]))
+    }
+    /// 处理一行请求,返回需要发送的消息
+    fn handle(&mut self, server: &StratumServer, line: &str) -> Vec<Value> {
+        let req: Value = match serde_json::from_str(line) {
+            Ok(req) => req,
+            Err(_) => return vec![Self::error(&Value::Null, ERR_OTHER, "parse error")],
+        };
+        let id = req.get("id").cloned().unwrap_or(Value::Null);
+        let params = req.get("params").cloned().unwrap_or(json!([]));
+        let str_param = |i: usize| params.get(i).and_then(|v| v.as_str()).unwrap_or("");
+        match req.get("method").and_then(|v| v.as_str()).unwrap_or("") {
+            "mining.subscribe" => {
+                self.subscribed = true;
+                let subid = hex::encode(&self.en1);
+                let result = json!([
+                    [["mining.set_target", subid], ["mining.notify", subid]],
+                    hex::encode(&self.en1),
+                    EXTRANONCE2_SIZE
+                ]);
+                vec![Self::result(&id, result)]
+            }
+            "mining.authorize" => {
+                if !self.subscribed {
+                    return vec![Self::error(&id, ERR_NOT_SUBSCRIBED, "not subscribed")];
+                }
+                let worker = str_param(0);
+                if worker.is_empty() {
+                    return vec![Self::error(&id, ERR_UNAUTHORIZED, "worker name empty")];
+                }
+                if !self.workers.iter().any(|v| v == worker) {
+                    self.workers.push(worker.into());
+                }
+                server.update_worker(worker, |_| {});
+                let mut msgs = vec![Self::result(&id, json!(true)), self.set_target()];
+                if let Ok(job) = server.current() {
+                    msgs.append(&mut self.notify(&job, true));
+                }
+                msgs
+            }
+            "mining.submit" => {
+                let worker = str_param(0);
+                if !self.workers.iter().any(|v| v == worker) {
+                    return vec![Self::error(&id, ERR_UNAUTHORIZED, "unauthorized worker")];
+                }
+                let en2 = hex::decode(str_param(2)).unwrap_or_default();
+                let time = u32::from_str_radix(str_param(3), 16);
+                let nonce = u32::from_str_radix(str_param(4), 16);
+                let (time, nonce) = match (time, nonce) {
+                    (Ok(time), Ok(nonce)) => (time, nonce),
+                    _ => return vec![Self::error(&id, ERR_OTHER, "ntime or nonce error")],
+                };
+                match server.submit(self, worker, str_param(1), &en2, time, nonce) {
+                    Ok(_) => {
+                        let mut msgs = vec![Self::result(&id, json!(true))];
+                        //份额难度变化时通知矿工
+                        if let Ok(Some(_)) = self.diff.record(util::timestamp()) {
+                            msgs.push(self.set_target());
+                        }
+                        msgs
+                    }
+                    Err((code, msg)) => vec![Self::error(&id, code, &msg)],
+                }
+            }
+            "mining.extranonce.subscribe" => vec![Self::result(&id, json!(false))],
+            _ => vec![Self::error(&id, ERR_OTHER, "method not found")],
+        }
+    }
+}
+
+/// 测试用: 按矿工的方式计算区块id
+#[cfg(test)]
+fn miner_block_id(job: &StratumJob, en1: &[u8], en2: &[u8], time: u32, nonce: u32) -> Hasher {
+    use crate::block::Header;
+    let mut cb = job.coinb1.clone();
+    cb.extend_from_slice(en1);
+    cb.extend_from_slice(en2);
+    cb.extend_from_slice(&job.coinb2);
+    let merkle = MerkleTree::compute_with_branch(&Hasher::hash(&cb), &job.branch);
+    let mut header: Header = job.blk.header.clone();
+    header.merkle = merkle;
+    header.time = time;
+    header.nonce = nonce;
+    header.id().unwrap()
+}
+
+#[test]
+fn test_stratum_submit_block() {
+    use crate::account::HasAddress;
+    use crate::config::Config;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let addr = accpool.value(0)?.string()?;
+        let server = StratumServer::new(idx.clone(), &addr, "pool")?;
+        let mut session = Session::new(server.alloc_extranonce1(), server.limit);
+        let msgs = session.handle(
+            &server,
+            r#"{"id":1,"method":"mining.subscribe","params":[]}"#,
+        );
+        assert_eq!(msgs[0]["result"][1], json!(hex::encode(&session.en1)));
+        let msgs = session.handle(
+            &server,
+            r#"{"id":2,"method":"mining.authorize","params":["w1","x"]}"#,
+        );
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0]["result"], json!(true));
+        assert_eq!(msgs[2]["method"], json!("mining.notify"));
+        let job = server.current()?;
+        assert_eq!(job.height(), 1);
+        let en2 = [1u8, 2, 3, 4];
+        let time = job.blk.header.time;
+        //矿工计算的区块id和服务器一致
+        let blk = job.build_block(&session.en1, &en2, time, 7).unwrap();
+        assert_eq!(blk.id()?, miner_block_id(&job, &session.en1, &en2, time, 7));
+        //找到一个达不到难度的nonce
+        let target = session.diff.target()?;
+        let mut nonce = 0;
+        while miner_block_id(&job, &session.en1, &en2, time, nonce) <= target {
+            nonce += 1;
+        }
+        let req = json!({"id": 3, "method": "mining.submit",
+            "params": ["w1", job.id(), hex::encode(en2), format!("{:08x}", time), format!("{:08x}", nonce)]});
+        let msgs = session.handle(&server, &req.to_string());
+        assert_eq!(msgs[0]["error"][0], json!(ERR_LOW_DIFFICULTY));
+        //难度不足的份额不记录去重
+        assert!(server.shares.lock().unwrap().is_empty());
+        //ntime超过当前时间太多
+        let ntime = (util::timestamp() + MAX_NTIME_OFFSET + 60) as u32;
+        let req = json!({"id": 3, "method": "mining.submit",
+            "params": ["w1", job.id(), hex::encode(en2), format!("{:08x}", ntime), format!("{:08x}", nonce)]});
+        let msgs = session.handle(&server, &req.to_string());
+        assert!(msgs[0]["error"].is_array());
+        //找到有效的nonce,测试链份额难度等于区块难度
+        let mut nonce = 0;
+        while miner_block_id(&job, &session.en1, &en2, time, nonce) > target {
+            nonce += 1;
+        }
+        let req = json!({"id": 4, "method": "mining.submit",
+            "params": ["w1", job.id(), hex::encode(en2), format!("{:08x}", time), format!("{:08x}", nonce)]});
+        let msgs = session.handle(&server, &req.to_string());
+        assert_eq!(msgs[0]["result"], json!(true));
+        //区块由连接任务在阻塞线程中链接
+        assert_eq!(idx.best()?.height, 0);
+        let (worker, blk) = session.found.take().unwrap();
+        assert_eq!(worker, "w1");
+        assert!(server.link_found(&worker, &blk));
+        assert_eq!(idx.best()?.height, 1);
+        //重复提交,旧任务已经失效
+        let msgs = session.handle(&server, &req.to_string());
+        assert_eq!(msgs[0]["error"][0], json!(ERR_JOB_NOT_FOUND));
+        assert_eq!(server.current()?.height(), 2);
+        //其他来源链接的区块也会更新任务
+        idx.new_link_block("", &addr)?;
+        assert_eq!(idx.best()?.height, 2);
+        assert_eq!(server.current()?.height(), 3);
+        let stats = server.workers();
+        let w1 = stats.get("w1").unwrap();
+        assert_eq!(w1.accepted, 1);
+        assert_eq!(w1.rejected, 2);
+        assert_eq!(w1.stale, 1);
+        assert_eq!(w1.blocks, 1);
+        Ok(())
+    });
+}
+
+#[test]
+fn test_stratum_tcp_session() {
+    use crate::account::HasAddress;
+    use crate::config::Config;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let addr = accpool.value(0)?.string()?;
+        let server = StratumServer::new(idx.clone(), &addr, "pool")?;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let local = listener.local_addr().unwrap();
+            tokio::spawn(server.clone().serve(listener));
+            let stream = TcpStream::connect(local).await.unwrap();
+            let (rd, mut wr) = stream.into_split();
+            let mut lines = BufReader::new(rd).lines();
+            let send = |v: Value| {
+                let mut line = v.to_string();
+                line.push('\n');
+                line
+            };
+            let line = send(json!({"id":1,"method":"mining.subscribe","params":[]}));
+            wr.write_all(line.as_bytes()).await.unwrap();
+            let resp: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(resp["id"], json!(1));
+            let line = send(json!({"id":2,"method":"mining.authorize","params":["w2","x"]}));
+            wr.write_all(line.as_bytes()).await.unwrap();
+            let resp: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(resp["result"], json!(true));
+            let resp: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(resp["method"], json!("mining.set_target"));
+            let resp: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(resp["method"], json!("mining.notify"));
+            //新任务推送给已授权的矿工
+            server.refresh().unwrap();
+            let resp: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(resp["method"], json!("mining.notify"));
+            assert!(server.workers().contains_key("w2"));
+        });
+        Ok(())
+    });
+}
@@ -0,0 +1,522 @@
+use crate::account::{Account, HasAddress};
+use crate::block::{Block, Tx};
+use crate::consts;
+use crate::errors::Error;
+use crate::hasher::Hasher;
+use crate::index::{Chain, ChainListener, IKey};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+/// 账户余额
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Balance {
+    /// 已确认并且可消费
+    pub confirmed: i64,
+    /// 在交易池中未确认
+    pub unconfirmed: i64,
+    /// 未成熟的coinbase输出
+    pub immature: i64,
+}
+
+impl Balance {
+    /// 总金额
+    pub fn total(&self) -> i64 {
+        self.confirmed + self.unconfirmed + self.immature
+    }
+}
+
+/// 输出位置 交易id + 输出索引
+type OutPoint = (Hasher, u16);
+
+/// 钱包拥有的输出
+#[derive(Debug, Clone)]
+struct WalletOut {
+    addr: Hasher,
+    value: i64,
+    //所在区块高度,在交易池中为None
+    height: Option<u32>,
+    coinbase: bool,
+    //消费此输出的交易
+    spent: Option<Hasher>,
+}
+
+/// 和钱包相关的交易
+#[derive(Debug, Clone, Default)]
+struct WalletTx {
+    //所在区块高度,在交易池中为None
+    height: Option<u32>,
+    //消费的钱包输出
+    ins: Vec<OutPoint>,
+    //产生的钱包输出
+    outs: Vec<OutPoint>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    best: u32,
+    //最高区块id,未知时为None
+    best_id: Option<Hasher>,
+    addrs: BTreeSet<Hasher>,
+    outs: BTreeMap<OutPoint, WalletOut>,
+    txs: BTreeMap<Hasher, WalletTx>,
+    balances: BTreeMap<Hasher, Balance>,
+    //coinbase输出按高度索引,高度变化时只需要重新计算临界高度的输出
+    coinbase: BTreeMap<u32, Vec<OutPoint>>,
+}
+
+impl State {
+    /// 计算输出对余额的影响,sign为1时增加,-1时减少
+    fn apply(&mut self, out: &WalletOut, sign: i64) {
+        if out.spent.is_some() {
+            return;
+        }
+        let best = self.best;
+        let bal = self.balances.entry(out.addr.clone()).or_default();
+        match out.height {
+            None => bal.unconfirmed += sign * out.value,
+            Some(h) if out.coinbase && (best + 1).saturating_sub(h) < consts::COINBASE_MATURITY => {
+                bal.immature += sign * out.value
+            }
+            Some(_) => bal.confirmed += sign * out.value,
+        }
+    }
+    /// 修改输出并更新余额
+    fn update<F>(&mut self, key: &OutPoint, f: F)
+    where
+        F: FnOnce(&mut WalletOut),
+    {
+        if let Some(mut out) = self.outs.get(key).cloned() {
+            self.apply(&out, -1);
+            f(&mut out);
+            self.apply(&out, 1);
+            self.outs.insert(key.clone(), out);
+        }
+    }
+    /// 移除输出并更新余额
+    fn remove_out(&mut self, key: &OutPoint) {
+        if let Some(out) = self.outs.remove(key) {
+            self.apply(&out, -1);
+            if let (true, Some(h)) = (out.coinbase, out.height) {
+                if let Some(v) = self.coinbase.get_mut(&h) {
+                    v.retain(|k| k != key);
+                }
+            }
+        }
+    }
+    /// 设置链高度,重新计算成熟状态改变的coinbase输出
+    fn set_best(&mut self, best: u32) {
+        let (lo, hi) = if best > self.best {
+            (self.best, best)
+        } else {
+            (best, self.best)
+        };
+        //成熟高度在(lo,hi]之间的输出状态会改变
+        let lo = (lo + 2).saturating_sub(consts::COINBASE_MATURITY);
+        let hi = (hi + 1).saturating_sub(consts::COINBASE_MATURITY);
+        if lo > hi {
+            self.best = best;
+            return;
+        }
+        let keys: Vec<OutPoint> = self
+            .coinbase
+            .range(lo..=hi)
+            .flat_map(|(_, v)| v.iter().cloned())
+            .collect();
+        let outs: Vec<WalletOut> = keys
+            .iter()
+            .filter_map(|k| self.outs.get(k).cloned())
+            .collect();
+        for out in outs.iter() {
+            self.apply(out, -1);
+        }
+        self.best = best;
+        for out in outs.iter() {
+            self.apply(out, 1);
+        }
+    }
+    /// 添加交易,height为None时在交易池中
+    fn add_tx(&mut self, tx: &Tx, height: Option<u32>) -> Result<(), Error> {
+        let id = tx.id()?;
+        let mut wtx = self.txs.remove(&id).unwrap_or_default();
+        wtx.height = height;
+        for inv in tx.ins.iter().filter(|v| !v.is_coinbase()) {
+            let key = (inv.out.clone(), inv.idx);
+            if !self.outs.contains_key(&key) {
+                continue;
+            }
+            self.update(&key, |out| out.spent = Some(id.clone()));
+            if !wtx.ins.contains(&key) {
+                wtx.ins.push(key);
+            }
+        }
+        for (i, outv) in tx.outs.iter().enumerate() {
+            if outv.is_data() {
+                continue;
+            }
+            let addr = outv.get_address()?;
+            if !self.addrs.contains(&addr) {
+                continue;
+            }
+            let key = (id.clone(), i as u16);
+            if self.outs.contains_key(&key) {
+                //从交易池进入区块
+                self.update(&key, |out| out.height = height);
+            } else {
+                let out = WalletOut {
+                    addr,
+                    value: outv.value,
+                    height,
+                    coinbase: tx.is_coinbase(),
+                    spent: None,
+                };
+                self.apply(&out, 1);
+                self.outs.insert(key.clone(), out);
+                wtx.outs.push(key.clone());
+            }
+            if let (true, Some(h)) = (tx.is_coinbase(), height) {
+                self.coinbase.entry(h).or_default().push(key);
+            }
+        }
+        if !wtx.ins.is_empty() || !wtx.outs.is_empty() {
+            self.txs.insert(id, wtx);
+        }
+        Ok(())
+    }
+    /// 撤销交易,移除产生的输出并恢复消费的输出
+    fn undo_tx(&mut self, id: &Hasher) {
+        if let Some(wtx) = self.txs.remove(id) {
+            for key in wtx.outs.iter() {
+                self.remove_out(key);
+            }
+            for key in wtx.ins.iter() {
+                self.update(key, |out| {
+                    if out.spent.as_ref() == Some(id) {
+                        out.spent = None
+                    }
+                });
+            }
+        }
+    }
+    /// 在高度height链接区块
+    fn link_block(&mut self, blk: &Block, height: u32) -> Result<(), Error> {
+        self.set_best(height);
+        self.best_id = Some(blk.id()?);
+        for tx in blk.txs.iter() {
+            self.add_tx(tx, Some(height))?;
+        }
+        Ok(())
+    }
+    /// 回退最高区块
+    fn pop_block(&mut self, blk: &Block) -> Result<(), Error> {
+        for tx in blk.txs.iter().rev() {
+            self.undo_tx(&tx.id()?);
+        }
+        self.set_best(self.best.saturating_sub(1));
+        self.best_id = Some(blk.header.prev.clone());
+        Ok(())
+    }
+    /// 撤销height及以上高度的区块交易,按链上区块重新链接到链最高区块
+    fn rescan(&mut self, ctx: &Chain, height: u32) -> Result<(), Error> {
+        let best = ctx.best()?.height;
+        let height = height.min(best + 1);
+        //按高度从高到低撤销
+        let mut ids: Vec<(u32, Hasher)> = self
+            .txs
+            .iter()
+            .filter_map(|(id, wtx)| wtx.height.filter(|h| *h >= height).map(|h| (h, id.clone())))
+            .collect();
+        ids.sort();
+        for (_, id) in ids.iter().rev() {
+            self.undo_tx(id);
+        }
+        self.set_best(height.saturating_sub(1));
+        self.best_id = None;
+        for h in height..=best {
+            let blk = ctx.get(&IKey::from(h))?;
+            self.link_block(&blk, h)?;
+        }
+        Ok(())
+    }
+}
+
+/// 钱包余额跟踪
+/// 作为链监听器在区块链接回退和交易池变化时更新每个地址的余额,
+/// 查询余额不需要读取链上的金额索引
+#[derive(Debug, Default)]
+pub struct WalletTracker {
+    state: RwLock<State>,
+}
+
+impl WalletTracker {
+    /// 跟踪链账户池中的所有账户,需要调用rescan加载历史交易
+    pub fn new(ctx: &Chain) -> Result<Self, Error> {
+        let tracker = WalletTracker::default();
+        tracker.load_accounts(ctx)?;
+        let best = ctx.best()?;
+        tracker.do_write(|state| {
+            state.best = best.height;
+            state.best_id = Some(best.id).filter(|v| !v.is_zero());
+            Ok(())
+        })?;
+        Ok(tracker)
+    }
+    /// 从账户池加载地址,返回新增地址数量
+    fn load_accounts(&self, ctx: &Chain) -> Result<usize, Error> {
+        let addrs = ctx
+            .get_account_pool()?
+            .list_keys()
+            .iter()
+            .map(|k| Account::decode(k))
+            .collect::<Result<Vec<Hasher>, Error>>()?;
+        self.do_write(|state| {
+            Ok(addrs
+                .into_iter()
+                .filter(|v| state.addrs.insert(v.clone()))
+                .count())
+        })
+    }
+    /// 添加跟踪地址,需要调用rescan加载历史交易
+    pub fn watch<T: HasAddress>(&self, acc: &T) -> Result<(), Error> {
+        let addr = acc.get_address()?;
+        self.do_write(|state| {
+            state.addrs.insert(addr);
+            Ok(())
+        })
+    }
+    /// 获取账户余额
+    pub fn balance<T: HasAddress>(&self, acc: &T) -> Result<Balance, Error> {
+        let addr = acc.get_address()?;
+        self.do_read(|state| {
+            if !state.addrs.contains(&addr) {
+                return Error::msg("address not in wallet");
+            }
+            Ok(state.balances.get(&addr).cloned().unwrap_or_default())
+        })
+    }
+    /// 获取交易的确认数,在交易池中为0
+    pub fn confirmations(&self, id: &Hasher) -> Result<u32, Error> {
+        self.do_read(|state| match state.txs.get(id) {
+            Some(wtx) => Ok(wtx.height.map_or(0, |h| (state.best + 1).saturating_sub(h))),
+            None => Error::msg("tx not in wallet"),
+        })
+    }
+    /// 获取钱包相关的交易id和确认数
+    pub fn txs(&self) -> Result<Vec<(Hasher, u32)>, Error> {
+        self.do_read(|state| {
+            Ok(state
+                .txs
+                .iter()
+                .map(|(id, wtx)| {
+                    let n = wtx.height.map_or(0, |h| (state.best + 1).saturating_sub(h));
+                    (id.clone(), n)
+                })
+                .collect())
+        })
+    }
+    /// 从height高度开始重新扫描区块,会重新加载账户池中的地址
+    /// 交易池中的交易保持不变
+    pub fn rescan(&self, ctx: &Chain, height: u32) -> Result<(), Error> {
+        self.load_accounts(ctx)?;
+        let best = ctx.best()?.height;
+        if height > best {
+            return Error::msg("rescan height error");
+        }
+        self.do_write(|state| state.rescan(ctx, height))
+    }
+    fn do_read<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&State) -> Result<R, Error>,
+    {
+        self.state.read().map_or_else(Error::std, |ref v| f(v))
+    }
+    fn do_write<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut State) -> Result<R, Error>,
+    {
+        self.state.write().map_or_else(Error::std, |ref mut v| f(v))
+    }
+}
+
+/// 区块高度,链接时已检测和coinbase中的高度一致
+/// 通知到达时区块可能已经被回退,不能从链上查询
+fn block_height(blk: &Block) -> Result<u32, Error> {
+    blk.check_coinbase()?;
+    blk.txs[0].ins[0].script.get_cb_height()
+}
+
+impl ChainListener for WalletTracker {
+    /// 通知时链锁已经释放,多个链接的通知可能乱序到达
+    /// 只按顺序链接下一个区块,其他情况从链上重新扫描
+    fn on_link_block(&self, ctx: &Chain, blk: &Block) -> Result<(), Error> {
+        let (id, height) = (blk.id()?, block_height(blk)?);
+        self.do_write(|state| {
+            let next = height == state.best + 1
+                && state.best_id.as_ref().is_none_or(|v| *v == blk.header.prev);
+            if next {
+                return state.link_block(blk, height);
+            }
+            //重新扫描时已经链接
+            if height == state.best && state.best_id.as_ref() == Some(&id) {
+                return Ok(());
+            }
+            state.rescan(ctx, height.min(state.best))
+        })
+    }
+    /// 只回退当前最高区块,其他情况从链上重新扫描
+    fn on_pop_block(&self, ctx: &Chain, blk: &Block) -> Result<(), Error> {
+        let (id, height) = (blk.id()?, block_height(blk)?);
+        self.do_write(|state| {
+            //区块还没有链接或者已经被重新扫描移除
+            if height > state.best {
+                return Ok(());
+            }
+            if height == state.best && state.best_id.as_ref().is_none_or(|v| *v == id) {
+                return state.pop_block(blk);
+            }
+            state.rescan(ctx, height)
+        })
+    }
+    fn on_append_tx(&self, _ctx: &Chain, tx: &Tx) -> Result<(), Error> {
+        self.do_write(|state| state.add_tx(tx, None))
+    }
+    fn on_remove_tx(&self, _ctx: &Chain, tx: &Tx) -> Result<(), Error> {
+        let id = tx.id()?;
+        self.do_write(|state| {
+            //只撤销还在交易池中的交易
+            if matches!(state.txs.get(&id), Some(v) if v.height.is_none()) {
+                state.undo_tx(&id);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[test]
+fn test_wallet_tracker_balance() {
+    use crate::config::Config;
+    use crate::index::{CoinSelect, FullSigner};
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    Config::test(|conf, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        let acc1 = accpool.value(1)?;
+        let tracker = Arc::new(WalletTracker::new(&idx)?);
+        idx.add_listener(tracker.clone())?;
+        //和链上的金额索引一致
+        let check = |acc: &Arc<Account>| -> Result<(), Error> {
+            let bal = tracker.balance(acc)?;
+            let coins = idx.coins(acc)?;
+            let next = idx.best()?.next();
+            let sum = |f: &dyn Fn(&crate::index::CoinAttr) -> bool| -> i64 {
+                coins.iter().filter(|v| f(v)).map(|v| v.coin()).sum()
+            };
+            assert_eq!(bal.confirmed, sum(&|v| v.is_valid(next)));
+            assert_eq!(bal.immature, sum(&|v| !v.is_txpool() && !v.is_valid(next)));
+            Ok(())
+        };
+        let reward = idx.compute_reward(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        check(&acc)?;
+        let bal = tracker.balance(&acc)?;
+        assert_eq!(bal.confirmed, reward);
+        assert_eq!(
+            bal.immature,
+            reward * (consts::COINBASE_MATURITY - 1) as i64
+        );
+        //交易池中的交易
+        let mut txh = idx.new_tx_helper();
+        txh.set_signer(FullSigner {})?;
+        txh.add_out(&acc1.string()?, 10 * consts::COIN)?;
+        txh.select_coins(
+            &acc.string()?,
+            CoinSelect::LargestFirst,
+            conf.policy.min_fee_rate,
+        )?;
+        let tx = Tx::try_from(&txh)?;
+        let change = tx.outs[1].value;
+        let id = idx.append(&tx)?;
+        assert_eq!(tracker.confirmations(&id)?, 0);
+        let bal = tracker.balance(&acc)?;
+        assert_eq!(bal.confirmed, 0);
+        assert_eq!(bal.unconfirmed, change);
+        assert_eq!(tracker.balance(&acc1)?.unconfirmed, 10 * consts::COIN);
+        idx.remove(&id)?;
+        assert!(tracker.confirmations(&id).is_err());
+        assert_eq!(tracker.balance(&acc)?.confirmed, reward);
+        assert_eq!(tracker.balance(&acc1)?, Balance::default());
+        //打包进区块
+        idx.append(&tx)?;
+        let mut blk = idx.create_block("", |fee, helper| {
+            helper.add_out(&acc.string()?, fee)?;
+            Ok(())
+        })?;
+        idx.compute_pow(&mut blk)?;
+        idx.link(&blk)?;
+        assert_eq!(tracker.confirmations(&id)?, 1);
+        idx.new_link_block("", &acc.string()?)?;
+        assert_eq!(tracker.confirmations(&id)?, 2);
+        assert_eq!(tracker.balance(&acc1)?.confirmed, 10 * consts::COIN);
+        check(&acc)?;
+        check(&acc1)?;
+        //回退区块
+        idx.pop()?;
+        idx.pop()?;
+        assert!(tracker.confirmations(&id).is_err());
+        assert_eq!(tracker.balance(&acc1)?, Balance::default());
+        check(&acc)?;
+        //重新扫描得到相同的余额
+        let bal = tracker.balance(&acc)?;
+        let other = WalletTracker::new(&idx)?;
+        other.rescan(&idx, 0)?;
+        assert_eq!(other.balance(&acc)?, bal);
+        tracker.rescan(&idx, 50)?;
+        assert_eq!(tracker.balance(&acc)?, bal);
+        assert!(tracker.rescan(&idx, 1000).is_err());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_wallet_tracker_out_of_order() {
+    use crate::config::Config;
+    Config::test(|_, idx| {
+        let accpool = idx.get_account_pool()?;
+        let acc = accpool.value(0)?;
+        for _ in 0..consts::COINBASE_MATURITY {
+            idx.new_link_block("", &acc.string()?)?;
+        }
+        //不注册为监听器,手动发送乱序的通知
+        let tracker = WalletTracker::new(&idx)?;
+        tracker.rescan(&idx, 0)?;
+        let mut blks = vec![];
+        for _ in 0..3 {
+            idx.new_link_block("", &acc.string()?)?;
+            blks.push(idx.get(&IKey::from(idx.best()?.height))?);
+        }
+        let check = || -> Result<(), Error> {
+            let other = WalletTracker::new(&idx)?;
+            other.rescan(&idx, 0)?;
+            assert_eq!(tracker.balance(&acc)?, other.balance(&acc)?);
+            assert_eq!(tracker.txs()?, other.txs()?);
+            Ok(())
+        };
+        //后面的区块先到达,最高高度不能回退
+        tracker.on_link_block(&idx, &blks[1])?;
+        tracker.on_link_block(&idx, &blks[0])?;
+        tracker.on_link_block(&idx, &blks[2])?;
+        check()?;
+        let id = blks[0].txs[0].id()?;
+        assert_eq!(tracker.confirmations(&id)?, 3);
+        //回退通知乱序
+        idx.pop()?;
+        idx.pop()?;
+        tracker.on_pop_block(&idx, &blks[1])?;
+        tracker.on_pop_block(&idx, &blks[2])?;
+        check()?;
+        assert_eq!(tracker.confirmations(&id)?, 1);
+        Ok(())
+    });
+}